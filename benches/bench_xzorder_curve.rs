@@ -15,7 +15,7 @@ fn test_space_filling_curve_2d_xzorder_city_size(b: &mut Bencher) {
     let y_max = 56.95869;
 
     b.iter(|| {
-        let _range = curve.ranges(x_min, y_min, x_max, y_max, None);
+        let _range = curve.ranges(x_min, y_min, x_max, y_max, &[]);
     })
 }
 
@@ -28,7 +28,7 @@ fn test_space_filling_curve_2d_xzorder_state_size(b: &mut Bencher) {
     let y_max = 46.28405;
 
     b.iter(|| {
-        let _range = curve.ranges(x_min, y_min, x_max, y_max, None);
+        let _range = curve.ranges(x_min, y_min, x_max, y_max, &[]);
     })
 }
 
@@ -41,6 +41,6 @@ fn test_space_filling_curve_2d_xzorder_country_size(b: &mut Bencher) {
     let y_max = 73.6753792663;
 
     b.iter(|| {
-        let _range = curve.ranges(x_min, y_min, x_max, y_max, None);
+        let _range = curve.ranges(x_min, y_min, x_max, y_max, &[]);
     })
 }
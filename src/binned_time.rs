@@ -0,0 +1,280 @@
+//
+// Copyright 2020, Gobsmacked Labs, LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Splits milliseconds since the Unix Epoch into a time bin and an offset within
+//! that bin.
+//!
+//! Based on [geomesa-z3 BinnedTime](https://github.com/locationtech/geomesa/blob/771777d3a9716b04f7dcd27a6b7d1bb822a1b5a7/geomesa-z3/src/main/scala/org/locationtech/geomesa/curve/BinnedTime.scala).
+//! Bins are counted from the Unix Epoch and offsets use the same units as geomesa:
+//! milliseconds for days, seconds for weeks and months, and minutes for years.
+
+use crate::error::Error;
+
+const MILLIS_PER_SECOND: i64 = 1000;
+const MILLIS_PER_MINUTE: i64 = 60 * MILLIS_PER_SECOND;
+const MILLIS_PER_DAY: i64 = 86_400_000;
+const MILLIS_PER_WEEK: i64 = 7 * MILLIS_PER_DAY;
+
+/// The length of a time bin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimePeriod {
+    /// Bins of one day, offsets in milliseconds.
+    Day,
+    /// Bins of one week, offsets in seconds.
+    Week,
+    /// Bins of one calendar month, offsets in seconds.
+    Month,
+    /// Bins of one calendar year, offsets in minutes.
+    Year,
+}
+
+/// A time bin and the offset into it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BinnedTime {
+    /// Number of periods since the Unix Epoch.
+    pub bin: u16,
+    /// Offset into the bin, in the units of the `TimePeriod`.
+    pub offset: i64,
+}
+
+impl TimePeriod {
+    /// The largest offset into a bin, in the units of the period, as in geomesa's
+    /// `BinnedTime.maxOffset`. Years are 52 weeks long, so the offsets of the last day
+    /// or two of a year are past the max offset and index like the max offset.
+    #[must_use]
+    pub const fn max_offset(self) -> i64 {
+        match self {
+            TimePeriod::Day => MILLIS_PER_DAY,
+            TimePeriod::Week => MILLIS_PER_WEEK / MILLIS_PER_SECOND,
+            TimePeriod::Month => 31 * MILLIS_PER_DAY / MILLIS_PER_SECOND,
+            TimePeriod::Year => 52 * MILLIS_PER_WEEK / MILLIS_PER_MINUTE,
+        }
+    }
+
+    /// Number of milliseconds in one unit of offset.
+    pub(crate) const fn offset_unit(self) -> i64 {
+        match self {
+            TimePeriod::Day => 1,
+            TimePeriod::Week | TimePeriod::Month => MILLIS_PER_SECOND,
+            TimePeriod::Year => MILLIS_PER_MINUTE,
+        }
+    }
+
+    /// Split milliseconds since the Unix Epoch into a bin and offset.
+    ///
+    /// # NOTE:
+    ///   panics if `millis` is before the Unix Epoch or after the last bin.
+    #[must_use]
+    pub fn bin_time(self, millis: i64) -> BinnedTime {
+        assert!(millis >= 0, "Times before the Unix Epoch can not be binned");
+
+        let bin = match self {
+            TimePeriod::Day => millis / MILLIS_PER_DAY,
+            TimePeriod::Week => millis / MILLIS_PER_WEEK,
+            TimePeriod::Month => {
+                let (year, month, _) = civil_from_days(millis / MILLIS_PER_DAY);
+                (year - 1970) * 12 + i64::from(month) - 1
+            }
+            TimePeriod::Year => civil_from_days(millis / MILLIS_PER_DAY).0 - 1970,
+        };
+        assert!(bin <= i64::from(u16::MAX), "Time is past the last bin");
+
+        BinnedTime {
+            bin: bin as u16,
+            offset: (millis - self.bin_start(bin as u16)) / self.offset_unit(),
+        }
+    }
+
+    /// Split milliseconds since the Unix Epoch into a bin and offset, returning an
+    /// error if `millis` is before the Unix Epoch or after the last bin.
+    pub fn try_bin_time(self, millis: i64) -> Result<BinnedTime, Error> {
+        if millis < 0 || millis > self.max_millis() {
+            Err(Error::OutOfBounds)
+        } else {
+            Ok(self.bin_time(millis))
+        }
+    }
+
+    /// The last millisecond since the Unix Epoch that falls in a bin.
+    #[must_use]
+    pub fn max_millis(self) -> i64 {
+        self.start(i64::from(u16::MAX) + 1) - 1
+    }
+
+    /// The first millisecond since the Unix Epoch that falls in `bin`.
+    #[must_use]
+    pub fn bin_start(self, bin: u16) -> i64 {
+        self.start(i64::from(bin))
+    }
+
+    fn start(self, bin: i64) -> i64 {
+        match self {
+            TimePeriod::Day => bin * MILLIS_PER_DAY,
+            TimePeriod::Week => bin * MILLIS_PER_WEEK,
+            TimePeriod::Month => {
+                days_from_civil(1970 + bin / 12, (bin % 12) as u32 + 1, 1) * MILLIS_PER_DAY
+            }
+            TimePeriod::Year => days_from_civil(1970 + bin, 1, 1) * MILLIS_PER_DAY,
+        }
+    }
+
+    /// Convert a bin and offset back to milliseconds since the Unix Epoch.
+    #[must_use]
+    pub fn millis(self, binned: BinnedTime) -> i64 {
+        self.bin_start(binned.bin) + binned.offset * self.offset_unit()
+    }
+}
+
+/// Days since the Unix Epoch for a proleptic Gregorian date.
+///
+/// Howard Hinnant's `days_from_civil`.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Proleptic Gregorian date for days since the Unix Epoch.
+///
+/// Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2020-04-22T19:33:17.829Z
+    const APRIL_22_2020: i64 = 1_587_583_997_829;
+
+    #[test]
+    fn test_civil_days() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(civil_from_days(11_017), (2000, 3, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+
+    #[quickcheck]
+    fn test_civil_round_trip(days: i32) -> bool {
+        let days = i64::from(days);
+        let (y, m, d) = civil_from_days(days);
+        days_from_civil(y, m, d) == days
+    }
+
+    #[test]
+    fn test_bin_time() {
+        assert_eq!(
+            TimePeriod::Day.bin_time(APRIL_22_2020),
+            BinnedTime {
+                bin: 18_374,
+                offset: 70_397_829
+            }
+        );
+        assert_eq!(
+            TimePeriod::Week.bin_time(APRIL_22_2020),
+            BinnedTime {
+                bin: 2_624,
+                offset: 588_797
+            }
+        );
+        assert_eq!(
+            TimePeriod::Month.bin_time(APRIL_22_2020),
+            BinnedTime {
+                bin: 603,
+                offset: 1_884_797
+            }
+        );
+        assert_eq!(
+            TimePeriod::Year.bin_time(APRIL_22_2020),
+            BinnedTime {
+                bin: 50,
+                offset: 162_453
+            }
+        );
+    }
+
+    #[test]
+    fn test_max_offset() {
+        assert_eq!(TimePeriod::Day.max_offset(), 86_400_000);
+        assert_eq!(TimePeriod::Week.max_offset(), 604_800);
+        assert_eq!(TimePeriod::Month.max_offset(), 2_678_400);
+        assert_eq!(TimePeriod::Year.max_offset(), 524_160);
+
+        // 2020-12-31T00:00:00Z is past 52 weeks into the year.
+        let binned = TimePeriod::Year.bin_time(1_609_372_800_000);
+        assert_eq!(binned.bin, 50);
+        assert_eq!(binned.offset, 525_600);
+    }
+
+    #[test]
+    fn test_offsets_fit_in_bins() {
+        for period in &[
+            TimePeriod::Day,
+            TimePeriod::Week,
+            TimePeriod::Month,
+            TimePeriod::Year,
+        ] {
+            let mut t = 0;
+            while t < 2_000_000_000_000 {
+                let binned = period.bin_time(t);
+                let max_offset = match period {
+                    TimePeriod::Year => 366 * MILLIS_PER_DAY / MILLIS_PER_MINUTE,
+                    _ => period.max_offset(),
+                };
+                assert!(binned.offset >= 0 && binned.offset < max_offset);
+                assert!(period.millis(binned) <= t);
+                assert!(t - period.millis(binned) < period.offset_unit());
+                t += 7_777_777_777;
+            }
+        }
+    }
+
+    #[test]
+    fn test_try_bin_time() {
+        for period in &[
+            TimePeriod::Day,
+            TimePeriod::Week,
+            TimePeriod::Month,
+            TimePeriod::Year,
+        ] {
+            assert_eq!(period.try_bin_time(-1), Err(Error::OutOfBounds));
+            assert_eq!(period.try_bin_time(0).map(|b| b.bin), Ok(0));
+
+            let last = period.try_bin_time(period.max_millis());
+            assert_eq!(last.map(|b| b.bin), Ok(u16::MAX));
+            assert_eq!(
+                period.try_bin_time(period.max_millis() + 1),
+                Err(Error::OutOfBounds)
+            );
+        }
+    }
+}
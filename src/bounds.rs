@@ -0,0 +1,348 @@
+//
+// Copyright 2020, Gobsmacked Labs, LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `BoundsPolicy` decides how curves treat coordinates outside of their bounds.
+//!
+//! The policy is applied by both `index` and `ranges`, so a value that is clamped or
+//! wrapped on ingest is found by a query over the same edge.
+//! ```
+//! use space_time::{bounds::BoundsPolicy, zorder::z_curve_2d::ZCurve2D};
+//!
+//! let curve = ZCurve2D::default().with_bounds_policy(BoundsPolicy::WrapLongitude);
+//!
+//! assert_eq!(curve.index(180.5, 10.0), curve.index(-179.5, 10.0));
+//! assert!(curve.try_index(180.0000001, 10.0).is_ok());
+//! ```
+
+use crate::error::{check_interval, check_value, Error};
+
+/// How a curve treats coordinates outside of its bounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BoundsPolicy {
+    /// The `try_` methods return `Error::OutOfBounds`. The infallible methods index
+    /// the coordinates as given.
+    #[default]
+    Reject,
+    /// Clamp every coordinate to the curve bounds.
+    Clamp,
+    /// Wrap x (longitude) into the curve's x bounds and clamp every other coordinate.
+    WrapLongitude,
+}
+
+impl BoundsPolicy {
+    /// Apply the policy to a y or time coordinate.
+    pub(crate) fn constrain(self, value: f64, min: f64, max: f64) -> f64 {
+        match self {
+            BoundsPolicy::Reject => value,
+            BoundsPolicy::Clamp | BoundsPolicy::WrapLongitude => value.max(min).min(max),
+        }
+    }
+
+    /// Apply the policy to an x coordinate. Wrapped coordinates are in `[min, max)`,
+    /// so `max` indexes like `min`.
+    pub(crate) fn constrain_x(self, value: f64, min: f64, max: f64) -> f64 {
+        match self {
+            BoundsPolicy::WrapLongitude if value < min || value >= max => {
+                let offset = (value - min) % (max - min);
+                if offset < 0.0 {
+                    max + offset
+                } else {
+                    min + offset
+                }
+            }
+            _ => self.constrain(value, min, max),
+        }
+    }
+
+    /// Apply the policy to a y or time interval.
+    pub(crate) fn constrain_interval(
+        self,
+        lower: f64,
+        upper: f64,
+        min: f64,
+        max: f64,
+    ) -> (f64, f64) {
+        (
+            self.constrain(lower, min, max),
+            self.constrain(upper, min, max),
+        )
+    }
+
+    /// Apply the policy to an x interval.
+    ///
+    /// The result may wrap across the x bounds (`lower > upper`), in which case it
+    /// should be split with `split_x_interval`. An interval at least as wide as the x
+    /// extent is widened to the full x extent. A wrapped interval keeps its width, so
+    /// it may end at `max`.
+    pub(crate) fn constrain_x_interval(
+        self,
+        lower: f64,
+        upper: f64,
+        min: f64,
+        max: f64,
+    ) -> (f64, f64) {
+        match self {
+            BoundsPolicy::WrapLongitude if lower <= upper && upper - lower >= max - min => {
+                (min, max)
+            }
+            BoundsPolicy::WrapLongitude
+                if lower < min || lower >= max || upper < min || upper > max =>
+            {
+                let extent = max - min;
+                let width = if lower <= upper {
+                    upper - lower
+                } else {
+                    (upper - lower) % extent + extent
+                };
+                let lower = self.constrain_x(lower, min, max);
+                let upper = lower + width;
+                (lower, if upper > max { upper - extent } else { upper })
+            }
+            _ => self.constrain_interval(lower, upper, min, max),
+        }
+    }
+
+    /// Apply the policy to the x interval of a query over points. A wrapped point at
+    /// `max` is indexed at `min`, so an interval that ends at `max` also covers `min`.
+    pub(crate) fn constrain_x_point_interval(
+        self,
+        lower: f64,
+        upper: f64,
+        min: f64,
+        max: f64,
+    ) -> (f64, f64) {
+        let (lower, upper) = self.constrain_x_interval(lower, upper, min, max);
+        if self == BoundsPolicy::WrapLongitude && upper >= max && lower > min {
+            (lower, min)
+        } else {
+            (lower, upper)
+        }
+    }
+
+    /// Apply the policy to a y or time coordinate, returning an error if it is not
+    /// finite or the policy rejects it.
+    pub(crate) fn check(self, value: f64, min: f64, max: f64) -> Result<f64, Error> {
+        if !value.is_finite() {
+            return Err(Error::NotFinite);
+        }
+        let value = self.constrain(value, min, max);
+        check_value(value, min, max)?;
+        Ok(value)
+    }
+
+    /// Apply the policy to an x coordinate, returning an error if it is not finite or
+    /// the policy rejects it.
+    pub(crate) fn check_x(self, value: f64, min: f64, max: f64) -> Result<f64, Error> {
+        if !value.is_finite() {
+            return Err(Error::NotFinite);
+        }
+        let value = self.constrain_x(value, min, max);
+        check_value(value, min, max)?;
+        Ok(value)
+    }
+
+    /// Apply the policy to a y or time interval, returning an error if it is not
+    /// finite, is inverted or the policy rejects it.
+    pub(crate) fn check_interval(
+        self,
+        lower: f64,
+        upper: f64,
+        min: f64,
+        max: f64,
+    ) -> Result<(f64, f64), Error> {
+        check_interval(lower, upper, f64::MIN, f64::MAX)?;
+        let (lower, upper) = self.constrain_interval(lower, upper, min, max);
+        check_interval(lower, upper, min, max)?;
+        Ok((lower, upper))
+    }
+
+    /// Apply the policy to an x interval, returning an error if it is not finite or
+    /// the policy rejects it. An interval with `lower > upper` wraps across the x
+    /// bounds and is not an error.
+    pub(crate) fn check_x_interval(
+        self,
+        lower: f64,
+        upper: f64,
+        min: f64,
+        max: f64,
+    ) -> Result<(f64, f64), Error> {
+        if !lower.is_finite() || !upper.is_finite() {
+            return Err(Error::NotFinite);
+        }
+        let (lower, upper) = self.constrain_x_interval(lower, upper, min, max);
+        check_value(lower, min, max)?;
+        check_value(upper, min, max)?;
+        Ok((lower, upper))
+    }
+}
+
+/// Split an x interval that wraps across the x bounds (`lower > upper`) into the
+/// window from `lower` to `max` and the window from `min` to `upper`.
+pub(crate) fn split_x_interval(
+    lower: f64,
+    upper: f64,
+    min: f64,
+    max: f64,
+) -> impl Iterator<Item = (f64, f64)> {
+    let (first, second) = if lower > upper {
+        ((lower, max), Some((min, upper)))
+    } else {
+        ((lower, upper), None)
+    };
+    core::iter::once(first).chain(second)
+}
+
+/// Widen an x interval that wraps across the x bounds (`lower > upper`) to the full x
+/// extent.
+pub(crate) fn cover_x_interval(lower: f64, upper: f64, min: f64, max: f64) -> (f64, f64) {
+    if lower > upper {
+        (min, max)
+    } else {
+        (lower, upper)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{vec, vec::Vec};
+
+    #[test]
+    fn test_constrain() {
+        assert_eq!(BoundsPolicy::Reject.constrain(91.0, -90.0, 90.0), 91.0);
+        assert_eq!(BoundsPolicy::Clamp.constrain(91.0, -90.0, 90.0), 90.0);
+        assert_eq!(
+            BoundsPolicy::Clamp.constrain_x(-181.0, -180.0, 180.0),
+            -180.0
+        );
+        assert_eq!(
+            BoundsPolicy::WrapLongitude.constrain(-91.0, -90.0, 90.0),
+            -90.0
+        );
+        assert_eq!(
+            BoundsPolicy::WrapLongitude.constrain_x(181.0, -180.0, 180.0),
+            -179.0
+        );
+        assert_eq!(
+            BoundsPolicy::WrapLongitude.constrain_x(-540.0, -180.0, 180.0),
+            -180.0
+        );
+        assert_eq!(
+            BoundsPolicy::WrapLongitude.constrain_x(180.0, -180.0, 180.0),
+            -180.0
+        );
+        assert_eq!(
+            BoundsPolicy::WrapLongitude.constrain_x(540.0, -180.0, 180.0),
+            -180.0
+        );
+        assert_eq!(BoundsPolicy::Clamp.constrain_x(180.0, -180.0, 180.0), 180.0);
+    }
+
+    #[test]
+    fn test_constrain_x_interval() {
+        let wrap = BoundsPolicy::WrapLongitude;
+        assert_eq!(
+            wrap.constrain_x_interval(190.0, 200.0, -180.0, 180.0),
+            (-170.0, -160.0)
+        );
+        assert_eq!(
+            wrap.constrain_x_interval(170.0, 190.0, -180.0, 180.0),
+            (170.0, -170.0)
+        );
+        assert_eq!(
+            wrap.constrain_x_interval(-200.0, 200.0, -180.0, 180.0),
+            (-180.0, 180.0)
+        );
+        assert_eq!(
+            wrap.constrain_x_interval(170.0, 180.0, -180.0, 180.0),
+            (170.0, 180.0)
+        );
+        assert_eq!(
+            wrap.constrain_x_interval(180.0, 180.0, -180.0, 180.0),
+            (-180.0, -180.0)
+        );
+        assert_eq!(
+            wrap.constrain_x_interval(-190.0, -180.0, -180.0, 180.0),
+            (170.0, 180.0)
+        );
+        assert_eq!(
+            wrap.constrain_x_point_interval(170.0, 180.0, -180.0, 180.0),
+            (170.0, -180.0)
+        );
+        assert_eq!(
+            wrap.constrain_x_point_interval(-180.0, 180.0, -180.0, 180.0),
+            (-180.0, 180.0)
+        );
+        assert_eq!(
+            BoundsPolicy::Clamp.constrain_x_point_interval(170.0, 180.0, -180.0, 180.0),
+            (170.0, 180.0)
+        );
+        assert_eq!(
+            BoundsPolicy::Clamp.constrain_x_interval(170.0, 190.0, -180.0, 180.0),
+            (170.0, 180.0)
+        );
+        assert_eq!(
+            BoundsPolicy::Reject.constrain_x_interval(170.0, -170.0, -180.0, 180.0),
+            (170.0, -170.0)
+        );
+    }
+
+    #[test]
+    fn test_split_x_interval() {
+        assert_eq!(
+            split_x_interval(170.0, -170.0, -180.0, 180.0).collect::<Vec<_>>(),
+            vec![(170.0, 180.0), (-180.0, -170.0)]
+        );
+        assert_eq!(
+            split_x_interval(-10.0, 10.0, -180.0, 180.0).collect::<Vec<_>>(),
+            vec![(-10.0, 10.0)]
+        );
+        assert_eq!(
+            cover_x_interval(170.0, -170.0, -180.0, 180.0),
+            (-180.0, 180.0)
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        assert_eq!(
+            BoundsPolicy::Reject.check(91.0, -90.0, 90.0),
+            Err(Error::OutOfBounds)
+        );
+        assert_eq!(BoundsPolicy::Clamp.check(91.0, -90.0, 90.0), Ok(90.0));
+        assert_eq!(
+            BoundsPolicy::Clamp.check(f64::NAN, -90.0, 90.0),
+            Err(Error::NotFinite)
+        );
+        assert_eq!(
+            BoundsPolicy::WrapLongitude
+                .check_x(180.0000001, -180.0, 180.0)
+                .map(|x| x < -179.9),
+            Ok(true)
+        );
+        assert_eq!(
+            BoundsPolicy::Clamp.check_interval(10.0, -10.0, -90.0, 90.0),
+            Err(Error::InvertedBox)
+        );
+        assert_eq!(
+            BoundsPolicy::Reject.check_x_interval(170.0, 190.0, -180.0, 180.0),
+            Err(Error::OutOfBounds)
+        );
+        assert_eq!(
+            BoundsPolicy::Reject.check_x_interval(170.0, -170.0, -180.0, 180.0),
+            Ok((170.0, -170.0))
+        );
+    }
+}
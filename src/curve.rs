@@ -0,0 +1,213 @@
+//
+// Copyright 2020, Gobsmacked Labs, LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Traits shared by the space filling curves, so that ingest and query code can be
+//! written once for any curve.
+//!
+//! Every trait is object safe. A query box with `x_min > x_max` wraps across the x
+//! bounds, so a window over the antimeridian is a single `ranges` call.
+//! ```
+//! use space_time::{
+//!     hilbert::hilbert_2d::HilbertCurve2D, PointCurveKind, SpaceFillingCurve2D,
+//!     SpaceFillingCurves,
+//! };
+//!
+//! fn matches(curve: &dyn SpaceFillingCurve2D) -> bool {
+//!     let index = curve.index(2.3522, 48.8566);
+//!     curve
+//!         .ranges(2.35, 48.85, 2.354, 48.857, &[])
+//!         .iter()
+//!         .any(|r| r.lower() <= index && index <= r.upper())
+//! }
+//!
+//! assert!(matches(&SpaceFillingCurves::get_point_curve(
+//!     1024, -180.0, -90.0, 180.0, 90.0
+//! )));
+//! assert!(matches(&HilbertCurve2D::default()));
+//! assert!(matches(&*SpaceFillingCurves::get_boxed_point_curve(
+//!     PointCurveKind::Hilbert,
+//!     1024,
+//!     -180.0,
+//!     -90.0,
+//!     180.0,
+//!     90.0
+//! )));
+//! ```
+
+use crate::{index_range::KeyRange, RangeComputeHints};
+use alloc::vec::Vec;
+
+/// A curve that indexes two dimensional points.
+pub trait SpaceFillingCurve2D {
+    /// Index a point.
+    fn index(&self, x: f64, y: f64) -> u64;
+
+    /// The `(x_min, y_min, x_max, y_max)` bounds of the curve.
+    fn bounds(&self) -> (f64, f64, f64, f64);
+
+    /// The index ranges that cover the bounding box. Hints a curve does not
+    /// support are ignored.
+    fn ranges(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange>;
+}
+
+/// A curve that indexes two dimensional bounding boxes.
+pub trait SpaceFillingCurve2DExtent {
+    /// Index a bounding box.
+    fn index(&self, x_min: f64, y_min: f64, x_max: f64, y_max: f64) -> u64;
+
+    /// The index ranges of the bounding boxes that are contained in or overlap the
+    /// query box. Hints a curve does not support are ignored.
+    fn ranges(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange>;
+}
+
+/// A curve that indexes points in space and time.
+pub trait SpaceTimeFillingCurve {
+    /// Index a point at time `t`.
+    fn index(&self, x: f64, y: f64, t: f64) -> u64;
+
+    /// The `(x_min, y_min, x_max, y_max)` bounds of the curve.
+    fn bounds(&self) -> (f64, f64, f64, f64);
+
+    /// The index ranges that cover the bounding box and time range. Hints a curve
+    /// does not support are ignored.
+    #[allow(clippy::too_many_arguments)]
+    fn ranges(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        t_min: f64,
+        t_max: f64,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange>;
+}
+
+/// A curve that indexes bounding boxes with a time range.
+///
+/// The methods take the box before the time range, like the other traits. The
+/// inherent `index` and `ranges` of `XZ3SFC` take `(x_min, y_min, t_min, x_max, y_max,
+/// t_max)` and are picked by method calls on an `XZ3SFC`, so call these through a
+/// `dyn SpaceTimeFillingCurveExtent` or with `SpaceTimeFillingCurveExtent::index`.
+pub trait SpaceTimeFillingCurveExtent {
+    /// Index a bounding box and time range.
+    fn index(&self, x_min: f64, y_min: f64, x_max: f64, y_max: f64, t_min: f64, t_max: f64) -> u64;
+
+    /// The index ranges of the boxes and time ranges that are contained in or
+    /// overlap the query. Hints a curve does not support are ignored.
+    #[allow(clippy::too_many_arguments)]
+    fn ranges(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        t_min: f64,
+        t_max: f64,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{NonPointCurveKind, PointCurveKind, SpaceFillingCurves, SpaceTimeFillingCurves};
+
+    fn any_contains(ranges: &[KeyRange], index: u64) -> bool {
+        ranges
+            .iter()
+            .any(|r| r.lower() <= index && index <= r.upper())
+    }
+
+    #[test]
+    fn test_curves_through_traits() {
+        for &kind in &[PointCurveKind::ZOrder, PointCurveKind::Hilbert] {
+            let curve =
+                SpaceFillingCurves::get_boxed_point_curve(kind, 1024, -180.0, -90.0, 180.0, 90.0);
+            let index = curve.index(10.5, 10.5);
+            assert!(any_contains(
+                &curve.ranges(10.0, 10.0, 11.0, 11.0, &[]),
+                index
+            ));
+
+            let curve = SpaceTimeFillingCurves::get_boxed_point_curve(
+                kind, 1024, -180.0, -90.0, 180.0, 90.0, 0.0, 10_000.0,
+            );
+            let index = curve.index(10.5, 10.5, 1_000.0);
+            assert!(any_contains(
+                &curve.ranges(10.0, 10.0, 11.0, 11.0, 900.0, 1_100.0, &[]),
+                index
+            ));
+        }
+
+        let extent = SpaceFillingCurves::get_boxed_non_point_curve(
+            NonPointCurveKind::XZ,
+            12,
+            -180.0,
+            -90.0,
+            180.0,
+            90.0,
+        );
+        let index = extent.index(10.0, 10.0, 12.0, 12.0);
+        assert!(any_contains(
+            &extent.ranges(9.0, 9.0, 13.0, 13.0, &[]),
+            index
+        ));
+
+        let xz3 = SpaceTimeFillingCurves::get_non_point_curve(
+            12, -180.0, -90.0, 0.0, 180.0, 90.0, 10_000.0,
+        );
+        let index =
+            SpaceTimeFillingCurveExtent::index(&xz3, 10.0, 10.0, 12.0, 12.0, 1_000.0, 2_000.0);
+        assert_eq!(index, xz3.index(10.0, 10.0, 1_000.0, 12.0, 12.0, 2_000.0));
+        assert_eq!(
+            SpaceTimeFillingCurveExtent::ranges(&xz3, 9.0, 9.0, 13.0, 13.0, 900.0, 2_100.0, &[],),
+            xz3.ranges(9.0, 9.0, 900.0, 13.0, 13.0, 2_100.0, &[])
+        );
+
+        let extent_time = SpaceTimeFillingCurves::get_boxed_non_point_curve(
+            NonPointCurveKind::XZ,
+            12,
+            -180.0,
+            -90.0,
+            0.0,
+            180.0,
+            90.0,
+            10_000.0,
+        );
+        assert_eq!(
+            extent_time.index(10.0, 10.0, 12.0, 12.0, 1_000.0, 2_000.0),
+            index
+        );
+        assert!(any_contains(
+            &extent_time.ranges(9.0, 9.0, 13.0, 13.0, 900.0, 2_100.0, &[]),
+            index
+        ));
+    }
+}
@@ -0,0 +1,137 @@
+//
+// Copyright 2020, Gobsmacked Labs, LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Error type returned by the fallible `try_` constructors and methods.
+
+use core::fmt;
+
+/// Errors from constructing curves, indexing values and computing ranges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// A coordinate was NaN or infinite.
+    NotFinite,
+    /// A coordinate was outside of the bounds of the curve.
+    OutOfBounds,
+    /// A box or range had its minimum greater than its maximum.
+    InvertedBox,
+    /// The bounds of a curve were not finite or had no extent.
+    InvalidBounds,
+    /// The resolution was zero or more than the curve can index in 64 bits.
+    InvalidResolution,
+    /// A geohash had a character outside of the geohash alphabet or was too long.
+    InvalidGeohash,
+    /// A quadkey had a digit other than 0 to 3 or was too long.
+    InvalidQuadkey,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotFinite => write!(f, "coordinate is NaN or infinite"),
+            Error::OutOfBounds => write!(f, "coordinate is outside of the curve bounds"),
+            Error::InvertedBox => write!(f, "minimum is greater than maximum"),
+            Error::InvalidBounds => write!(f, "curve bounds are not finite or are empty"),
+            Error::InvalidResolution => write!(f, "resolution is zero or too large"),
+            Error::InvalidGeohash => write!(f, "geohash is not valid base32 or is too long"),
+            Error::InvalidQuadkey => {
+                write!(f, "quadkey has digits other than 0 to 3 or is too long")
+            }
+        }
+    }
+}
+
+/// Check that `min` and `max` describe a usable dimension of a curve.
+pub(crate) fn check_bounds(min: f64, max: f64) -> Result<(), Error> {
+    if min.is_finite() && max.is_finite() && min < max {
+        Ok(())
+    } else {
+        Err(Error::InvalidBounds)
+    }
+}
+
+/// Check that `value` is finite and within `[min, max]`.
+pub(crate) fn check_value(value: f64, min: f64, max: f64) -> Result<(), Error> {
+    if !value.is_finite() {
+        Err(Error::NotFinite)
+    } else if value < min || value > max {
+        Err(Error::OutOfBounds)
+    } else {
+        Ok(())
+    }
+}
+
+/// Check that `[lower, upper]` is a finite, non-inverted interval within
+/// `[min, max]`.
+pub(crate) fn check_interval(lower: f64, upper: f64, min: f64, max: f64) -> Result<(), Error> {
+    if !lower.is_finite() || !upper.is_finite() {
+        Err(Error::NotFinite)
+    } else if lower > upper {
+        Err(Error::InvertedBox)
+    } else {
+        check_value(lower, min, max)?;
+        check_value(upper, min, max)
+    }
+}
+
+/// Check that a curve's bounds are longitude and latitude, as queries on the sphere
+/// need.
+pub(crate) fn check_geographic(
+    x_min: f64,
+    y_min: f64,
+    x_max: f64,
+    y_max: f64,
+) -> Result<(), Error> {
+    if x_min >= -180.0 && x_max <= 180.0 && y_min >= -90.0 && y_max <= 90.0 {
+        Ok(())
+    } else {
+        Err(Error::InvalidBounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checks() {
+        assert_eq!(check_bounds(-180.0, 180.0), Ok(()));
+        assert_eq!(check_bounds(180.0, -180.0), Err(Error::InvalidBounds));
+        assert_eq!(check_bounds(0.0, f64::INFINITY), Err(Error::InvalidBounds));
+
+        assert_eq!(check_value(180.0, -180.0, 180.0), Ok(()));
+        assert_eq!(check_value(180.1, -180.0, 180.0), Err(Error::OutOfBounds));
+        assert_eq!(check_value(f64::NAN, -180.0, 180.0), Err(Error::NotFinite));
+
+        assert_eq!(check_interval(-1.0, 1.0, -180.0, 180.0), Ok(()));
+        assert_eq!(
+            check_interval(1.0, -1.0, -180.0, 180.0),
+            Err(Error::InvertedBox)
+        );
+        assert_eq!(
+            check_interval(-1.0, f64::NEG_INFINITY, -180.0, 180.0),
+            Err(Error::NotFinite)
+        );
+        assert_eq!(
+            check_interval(-181.0, 1.0, -180.0, 180.0),
+            Err(Error::OutOfBounds)
+        );
+
+        assert_eq!(check_geographic(0.0, 0.0, 10.0, 10.0), Ok(()));
+        assert_eq!(
+            check_geographic(-20_037_508.3, -20_037_508.3, 20_037_508.3, 20_037_508.3),
+            Err(Error::InvalidBounds)
+        );
+    }
+}
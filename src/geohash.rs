@@ -0,0 +1,243 @@
+//
+// Copyright 2020, Gobsmacked Labs, LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Geohash encoding and decoding.
+//!
+//! A geohash is a Z-order over longitude and latitude with longitude in the leading
+//! bit, written five bits per character in a base32 alphabet. `ZCurve2D` with WGS84
+//! bounds and a power of two resolution can translate between its indexes and
+//! geohashes.
+//! ```
+//! use space_time::{geohash, zorder::z_curve_2d::ZCurve2D};
+//!
+//! assert_eq!(
+//!     geohash::encode(10.40744, 57.64911, 11),
+//!     Ok("u4pruydqqvj".into())
+//! );
+//!
+//! let curve = ZCurve2D::new(1 << 20, -180.0, -90.0, 180.0, 90.0);
+//! let index = curve.index(10.40744, 57.64911);
+//! assert_eq!(curve.geohash(index, 8), Ok("u4pruydq".into()));
+//! assert!(curve
+//!     .geohash_ranges("u4pr")
+//!     .unwrap()
+//!     .iter()
+//!     .any(|r| r.lower() <= index && index <= r.upper()));
+//! ```
+
+use crate::{
+    error::{check_value, Error},
+    geometry::Relation,
+    index_range::KeyRange,
+};
+use alloc::string::String;
+
+const BASE32: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// The longest geohash, 60 bits.
+pub const MAX_LEN: usize = 12;
+
+/// Encode the `x` longitude and `y` latitude as a geohash of `len` characters.
+/// Returns `Error::InvalidResolution` if `len` is more than `MAX_LEN`.
+pub fn encode(x: f64, y: f64, len: usize) -> Result<String, Error> {
+    if len > MAX_LEN {
+        return Err(Error::InvalidResolution);
+    }
+    check_value(x, -180.0, 180.0)?;
+    check_value(y, -90.0, 90.0)?;
+
+    let (lon_bits, lat_bits) = bits(len);
+    Ok(from_cells(
+        cell(x, -180.0, 180.0, lon_bits),
+        cell(y, -90.0, 90.0, lat_bits),
+        len,
+    ))
+}
+
+/// Decode a geohash into its `(x_min, y_min, x_max, y_max)` bounding box.
+pub fn decode(hash: &str) -> Result<(f64, f64, f64, f64), Error> {
+    let (lon, lat, len) = to_cells(hash)?;
+    let (lon_bits, lat_bits) = bits(len);
+    let width = 360.0 / (1_u64 << lon_bits) as f64;
+    let height = 180.0 / (1_u64 << lat_bits) as f64;
+
+    let x_min = -180.0 + f64::from(lon) * width;
+    let y_min = -90.0 + f64::from(lat) * height;
+    Ok((x_min, y_min, x_min + width, y_min + height))
+}
+
+/// The number of longitude and latitude bits in a geohash of `len` characters.
+pub(crate) fn bits(len: usize) -> (u32, u32) {
+    let bits = 5 * len as u32;
+    (bits.div_ceil(2), bits / 2)
+}
+
+/// The geohash of `len` characters for the longitude and latitude cells.
+pub(crate) fn from_cells(lon: u32, lat: u32, len: usize) -> String {
+    let (mut lon_bits, mut lat_bits) = bits(len);
+
+    let mut hash = String::with_capacity(len);
+    let mut char_bits = 0_usize;
+    for i in 0..5 * len {
+        let bit = if i % 2 == 0 {
+            lon_bits -= 1;
+            (lon >> lon_bits) & 1
+        } else {
+            lat_bits -= 1;
+            (lat >> lat_bits) & 1
+        };
+        char_bits = (char_bits << 1) | bit as usize;
+        if i % 5 == 4 {
+            hash.push(char::from(BASE32[char_bits]));
+            char_bits = 0;
+        }
+    }
+    hash
+}
+
+/// The longitude and latitude cells and length of a geohash.
+pub(crate) fn to_cells(hash: &str) -> Result<(u32, u32, usize), Error> {
+    if hash.len() > MAX_LEN {
+        return Err(Error::InvalidGeohash);
+    }
+
+    let (mut lon, mut lat) = (0_u32, 0_u32);
+    let mut i = 0;
+    for c in hash.bytes() {
+        let value = BASE32
+            .iter()
+            .position(|&b| b == c.to_ascii_lowercase())
+            .ok_or(Error::InvalidGeohash)? as u32;
+        for shift in (0..5).rev() {
+            let bit = (value >> shift) & 1;
+            if i % 2 == 0 {
+                lon = (lon << 1) | bit;
+            } else {
+                lat = (lat << 1) | bit;
+            }
+            i += 1;
+        }
+    }
+    Ok((lon, lat, hash.len()))
+}
+
+/// How the keys of a geohash cell relate to sorted, disjoint and merged ranges.
+pub(crate) fn relate(ranges: &[KeyRange], cell: &[KeyRange]) -> Relation {
+    let mut inside = true;
+    let mut outside = true;
+    for key_range in cell {
+        let next = ranges.partition_point(|r| r.upper() < key_range.lower());
+        match ranges.get(next) {
+            Some(r) if r.lower() <= key_range.lower() && key_range.upper() <= r.upper() => {
+                outside = false;
+            }
+            Some(r) if r.lower() <= key_range.upper() => {
+                inside = false;
+                outside = false;
+            }
+            _ => inside = false,
+        }
+    }
+    if outside {
+        Relation::Outside
+    } else if inside {
+        Relation::Inside
+    } else {
+        Relation::Crossing
+    }
+}
+
+/// The children of a geohash in order.
+pub(crate) fn children(hash: &str) -> impl DoubleEndedIterator<Item = String> + '_ {
+    BASE32.iter().map(move |&c| {
+        let mut child = String::with_capacity(hash.len() + 1);
+        child.push_str(hash);
+        child.push(char::from(c));
+        child
+    })
+}
+
+fn cell(value: f64, min: f64, max: f64, bits: u32) -> u32 {
+    let cells = 1_u64 << bits;
+    (((value - min) / (max - min) * cells as f64) as u64).min(cells - 1) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        assert_eq!(encode(-5.6, 42.6, 5), Ok("ezs42".into()));
+        assert_eq!(encode(10.40744, 57.64911, 11), Ok("u4pruydqqvj".into()));
+        assert_eq!(encode(-180.0, -90.0, 12), Ok("000000000000".into()));
+        assert_eq!(encode(180.0, 90.0, 12), Ok("zzzzzzzzzzzz".into()));
+        assert_eq!(encode(0.0, 0.0, 0), Ok("".into()));
+        assert_eq!(encode(0.0, 0.0, 13), Err(Error::InvalidResolution));
+        assert_eq!(encode(0.0, 91.0, 5), Err(Error::OutOfBounds));
+
+        let (x_min, y_min, x_max, y_max) = decode("ezs42").unwrap();
+        assert!(x_min <= -5.6 && -5.6 <= x_max && y_min <= 42.6 && 42.6 <= y_max);
+        assert_eq!(
+            (x_max - x_min, y_max - y_min),
+            (360.0 / 8192.0, 180.0 / 4096.0)
+        );
+        assert_eq!(decode("EZS42"), decode("ezs42"));
+        assert_eq!(decode(""), Ok((-180.0, -90.0, 180.0, 90.0)));
+        assert_eq!(decode("ezs4a"), Err(Error::InvalidGeohash));
+        assert_eq!(decode("0000000000000"), Err(Error::InvalidGeohash));
+    }
+
+    #[test]
+    fn test_relate() {
+        let ranges = [KeyRange::covered(10, 19), KeyRange::overlapping(30, 39)];
+        assert_eq!(
+            relate(&ranges, &[KeyRange::covered(12, 15)]),
+            Relation::Inside
+        );
+        assert_eq!(
+            relate(&ranges, &[KeyRange::covered(20, 29)]),
+            Relation::Outside
+        );
+        assert_eq!(
+            relate(&ranges, &[KeyRange::covered(15, 25)]),
+            Relation::Crossing
+        );
+        assert_eq!(
+            relate(
+                &ranges,
+                &[KeyRange::covered(10, 11), KeyRange::covered(32, 33)]
+            ),
+            Relation::Inside
+        );
+        assert_eq!(
+            relate(
+                &ranges,
+                &[KeyRange::covered(10, 11), KeyRange::covered(40, 41)]
+            ),
+            Relation::Crossing
+        );
+        assert_eq!(children("u4").count(), 32);
+        assert_eq!(children("u4").next_back(), Some("u4z".into()));
+        assert_eq!(bits(5), (13, 12));
+        assert_eq!(bits(4), (10, 10));
+    }
+
+    #[quickcheck]
+    fn test_cells_and_back(lon: u32, lat: u32) -> bool {
+        let (lon, lat) = (lon >> 2, lat >> 2);
+        to_cells(&from_cells(lon, lat, 12)) == Ok((lon, lat, 12))
+    }
+}
@@ -0,0 +1,508 @@
+//
+// Copyright 2020, Gobsmacked Labs, LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Geometry used to decompose queries that are not bounding boxes.
+//!
+//! A `Polygon` classifies curve cells as inside, outside or crossing its boundary in
+//! the plane, and a `Circle` classifies longitude and latitude cells against a
+//! distance from a point on the sphere.
+//! ```
+//! use space_time::{geometry::Polygon, zorder::z_curve_2d::ZCurve2D, RangeComputeHints};
+//!
+//! // A thin diagonal corridor.
+//! let corridor = Polygon::new(
+//!     vec![(0.0, 0.0), (1.0, 0.0), (41.0, 40.0), (40.0, 40.0)],
+//!     vec![],
+//! );
+//! let curve = ZCurve2D::default();
+//!
+//! let ranges = curve.ranges_for_polygon(&corridor, &[RangeComputeHints::MaxRecurse(12)]);
+//! let inside = curve.index(20.5, 20.2);
+//! let outside = curve.index(30.0, 10.0);
+//!
+//! assert!(ranges
+//!     .iter()
+//!     .any(|r| r.lower() <= inside && inside <= r.upper()));
+//! assert!(!ranges
+//!     .iter()
+//!     .any(|r| r.lower() <= outside && outside <= r.upper()));
+//! ```
+
+use crate::error::Error;
+use alloc::vec::Vec;
+use core::f64::consts::PI;
+#[allow(unused_imports)]
+use num_traits::Float;
+
+/// The mean radius of the Earth, in meters.
+pub const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// How a cell relates to a query region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relation {
+    /// The cell is entirely inside the region.
+    Inside,
+    /// The cell and the region do not intersect.
+    Outside,
+    /// The cell crosses the boundary of the region.
+    Crossing,
+}
+
+impl Relation {
+    /// The relation of a cell to the intersection of two regions.
+    pub(crate) fn and(self, other: Relation) -> Relation {
+        match (self, other) {
+            (Relation::Outside, _) | (_, Relation::Outside) => Relation::Outside,
+            (Relation::Inside, Relation::Inside) => Relation::Inside,
+            _ => Relation::Crossing,
+        }
+    }
+}
+
+/// A polygon with an exterior ring and any number of holes, in curve coordinates.
+///
+/// Rings may be open or closed, the last vertex is always joined to the first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon {
+    exterior: Vec<(f64, f64)>,
+    holes: Vec<Vec<(f64, f64)>>,
+}
+
+impl Polygon {
+    /// Constructor from an exterior ring and the rings of its holes.
+    ///
+    /// # NOTE:
+    ///   panics if any ring has fewer than three vertices.
+    #[must_use]
+    pub fn new(exterior: Vec<(f64, f64)>, holes: Vec<Vec<(f64, f64)>>) -> Self {
+        assert!(exterior.len() >= 3);
+        assert!(holes.iter().all(|h| h.len() >= 3));
+
+        Polygon { exterior, holes }
+    }
+
+    /// The exterior ring.
+    #[must_use]
+    pub fn exterior(&self) -> &[(f64, f64)] {
+        &self.exterior
+    }
+
+    /// The rings of the holes.
+    #[must_use]
+    pub fn holes(&self) -> &[Vec<(f64, f64)>] {
+        &self.holes
+    }
+
+    /// The `(x_min, y_min, x_max, y_max)` bounding box of the exterior ring.
+    #[must_use]
+    pub fn bounding_box(&self) -> (f64, f64, f64, f64) {
+        self.exterior.iter().fold(
+            (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+            |(x_min, y_min, x_max, y_max), &(x, y)| {
+                (x_min.min(x), y_min.min(y), x_max.max(x), y_max.max(y))
+            },
+        )
+    }
+
+    /// Test whether a point is inside the exterior ring and outside of every hole.
+    #[must_use]
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        ring_contains(&self.exterior, x, y) && !self.holes.iter().any(|h| ring_contains(h, x, y))
+    }
+
+    /// Classify a bounding box against the polygon. A box that touches the boundary
+    /// is `Relation::Crossing`.
+    #[must_use]
+    pub fn relate(&self, x_min: f64, y_min: f64, x_max: f64, y_max: f64) -> Relation {
+        let (p_x_min, p_y_min, p_x_max, p_y_max) = self.bounding_box();
+        if x_max < p_x_min || x_min > p_x_max || y_max < p_y_min || y_min > p_y_max {
+            return Relation::Outside;
+        }
+
+        let crosses = core::iter::once(&self.exterior)
+            .chain(self.holes.iter())
+            .flat_map(|ring| edges(ring))
+            .any(|(start, end)| segment_intersects_box(start, end, x_min, y_min, x_max, y_max));
+        if crosses {
+            return Relation::Crossing;
+        }
+
+        // No edge enters the box, so the box is entirely on one side of the boundary.
+        if self.contains((x_min + x_max) / 2.0, (y_min + y_max) / 2.0) {
+            Relation::Inside
+        } else {
+            Relation::Outside
+        }
+    }
+}
+
+/// The points within a great-circle distance of a center, in longitude and latitude
+/// degrees on a sphere of `EARTH_RADIUS`.
+///
+/// A circle may cross the antimeridian or cover a pole.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Circle {
+    lon: f64,
+    lat: f64,
+    meters: f64,
+}
+
+impl Circle {
+    /// Constructor from the center and the radius in meters. The longitude is wrapped
+    /// into -180 to 180 and the latitude clamped to -90 to 90.
+    ///
+    /// # NOTE:
+    ///   panics if any argument is not finite or `meters` is negative.
+    #[must_use]
+    pub fn new(lon: f64, lat: f64, meters: f64) -> Self {
+        assert!(lon.is_finite() && lat.is_finite() && meters.is_finite());
+        assert!(meters >= 0.0);
+
+        Circle {
+            lon: wrap_longitude(lon),
+            lat: lat.clamp(-90.0, 90.0),
+            meters,
+        }
+    }
+
+    /// Fallible constructor, returning `Error::NotFinite` if any argument is not
+    /// finite and `Error::OutOfBounds` if `meters` is negative.
+    pub fn try_new(lon: f64, lat: f64, meters: f64) -> Result<Self, Error> {
+        if !lon.is_finite() || !lat.is_finite() || !meters.is_finite() {
+            Err(Error::NotFinite)
+        } else if meters < 0.0 {
+            Err(Error::OutOfBounds)
+        } else {
+            Ok(Self::new(lon, lat, meters))
+        }
+    }
+
+    /// The `(lon, lat)` of the center.
+    #[must_use]
+    pub fn center(&self) -> (f64, f64) {
+        (self.lon, self.lat)
+    }
+
+    /// The radius in meters.
+    #[must_use]
+    pub fn radius(&self) -> f64 {
+        self.meters
+    }
+
+    /// The radius as an angle in radians.
+    fn angle(&self) -> f64 {
+        self.meters / EARTH_RADIUS
+    }
+
+    /// Test whether a point is within the radius of the center.
+    #[must_use]
+    pub fn contains(&self, lon: f64, lat: f64) -> bool {
+        central_angle(self.lon, self.lat, lon, lat) <= self.angle()
+    }
+
+    /// The `(lon_min, lat_min, lon_max, lat_max)` bounding box of the circle. The box
+    /// has `lon_min > lon_max` if it wraps across the antimeridian, and spans every
+    /// longitude if the circle covers a pole.
+    #[must_use]
+    pub fn bounding_box(&self) -> (f64, f64, f64, f64) {
+        let angle = self.angle().to_degrees();
+        let lat_min = self.lat - angle;
+        let lat_max = self.lat + angle;
+        if lat_min <= -90.0 || lat_max >= 90.0 {
+            return (-180.0, lat_min.max(-90.0), 180.0, lat_max.min(90.0));
+        }
+
+        let d_lon = (self.angle().sin() / self.lat.to_radians().cos())
+            .asin()
+            .to_degrees();
+        (
+            wrap_longitude(self.lon - d_lon),
+            lat_min,
+            wrap_longitude(self.lon + d_lon),
+            lat_max,
+        )
+    }
+
+    /// Classify a `(lon_min, lat_min, lon_max, lat_max)` box against the circle. A box
+    /// with `lon_min > lon_max` wraps across the antimeridian. A box that touches the
+    /// circle is `Relation::Crossing`.
+    #[must_use]
+    pub fn relate(&self, x_min: f64, y_min: f64, x_max: f64, y_max: f64) -> Relation {
+        let angle = self.angle();
+        if angle >= PI {
+            return Relation::Inside;
+        }
+        let (y_min, y_max) = (y_min.max(-90.0), y_max.min(90.0));
+
+        let near = extreme_angle(self.lon, self.lat, x_min, y_min, x_max, y_max, false);
+        if near > angle {
+            return Relation::Outside;
+        }
+        let far = extreme_angle(self.lon, self.lat, x_min, y_min, x_max, y_max, true);
+        if far <= angle {
+            Relation::Inside
+        } else {
+            Relation::Crossing
+        }
+    }
+}
+
+/// The smallest great-circle distance in meters from a `(lon, lat)` point to a
+/// `(lon_min, lat_min, lon_max, lat_max)` box, 0 if the box contains the point. A
+/// box with `lon_min > lon_max` wraps across the antimeridian.
+#[must_use]
+pub fn box_distance(lon: f64, lat: f64, x_min: f64, y_min: f64, x_max: f64, y_max: f64) -> f64 {
+    let (lon, lat) = (wrap_longitude(lon), lat.clamp(-90.0, 90.0));
+    let (y_min, y_max) = (y_min.max(-90.0), y_max.min(90.0));
+    extreme_angle(lon, lat, x_min, y_min, x_max, y_max, false) * EARTH_RADIUS
+}
+
+/// The smallest, or if `far` the largest, angle from a center to the box.
+///
+/// For a fixed latitude the angle grows with the longitude difference, so the
+/// extremes lie on the edges of the box or on the meridian through the center or its
+/// antipode. Along a meridian the cosine of the angle is a sinusoid of the latitude,
+/// so its extremes lie on the latitude edges or at one critical latitude.
+fn extreme_angle(
+    lon0: f64,
+    lat0: f64,
+    x_min: f64,
+    y_min: f64,
+    x_max: f64,
+    y_max: f64,
+    far: bool,
+) -> f64 {
+    let meridian = if far { lon0 + 180.0 } else { lon0 };
+    let lons = [
+        Some(x_min),
+        Some(x_max),
+        Some(meridian).filter(|&lon| lon_interval_contains(x_min, x_max, lon)),
+    ];
+
+    let (sin_lat0, cos_lat0) = (lat0.to_radians().sin(), lat0.to_radians().cos());
+    let (low, high) = (y_min.to_radians(), y_max.to_radians());
+
+    let mut extreme: f64 = if far { 0.0 } else { PI };
+    for &lon in lons.iter().flatten() {
+        let critical = sin_lat0.atan2(cos_lat0 * (lon - lon0).to_radians().cos());
+        let critical = match (far, critical > 0.0) {
+            (false, _) => critical,
+            (true, true) => critical - PI,
+            (true, false) => critical + PI,
+        };
+        let critical = critical.max(low).min(high).to_degrees();
+
+        for &lat in &[y_min, y_max, critical] {
+            let angle = central_angle(lon0, lat0, lon, lat);
+            extreme = if far {
+                extreme.max(angle)
+            } else {
+                extreme.min(angle)
+            };
+        }
+    }
+    extreme
+}
+
+/// The great-circle distance in meters between two `(lon, lat)` points in degrees,
+/// by the haversine formula on a sphere of `EARTH_RADIUS`.
+#[must_use]
+pub fn haversine_distance(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> f64 {
+    central_angle(lon1, lat1, lon2, lat2) * EARTH_RADIUS
+}
+
+/// The angle in radians between two `(lon, lat)` points in degrees.
+fn central_angle(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> f64 {
+    let (phi1, phi2) = (lat1.to_radians(), lat2.to_radians());
+    let d_phi = phi2 - phi1;
+    let d_lambda = (lon2 - lon1).to_radians();
+
+    let h = (d_phi / 2.0).sin().powi(2) + phi1.cos() * phi2.cos() * (d_lambda / 2.0).sin().powi(2);
+    2.0 * h.min(1.0).sqrt().asin()
+}
+
+/// Wrap a longitude into -180 to 180.
+fn wrap_longitude(lon: f64) -> f64 {
+    if (-180.0..=180.0).contains(&lon) {
+        lon
+    } else {
+        let offset = (lon + 180.0) % 360.0;
+        if offset < 0.0 {
+            180.0 + offset
+        } else {
+            offset - 180.0
+        }
+    }
+}
+
+/// Test whether a longitude interval, which wraps if `x_min > x_max`, contains `lon`.
+fn lon_interval_contains(x_min: f64, x_max: f64, lon: f64) -> bool {
+    let width = if x_min > x_max {
+        x_max - x_min + 360.0
+    } else {
+        x_max - x_min
+    };
+    let offset = (lon - x_min) % 360.0;
+    let offset = if offset < 0.0 { offset + 360.0 } else { offset };
+    width >= 360.0 || offset <= width
+}
+
+fn edges(ring: &[(f64, f64)]) -> impl Iterator<Item = ((f64, f64), (f64, f64))> + '_ {
+    ring.iter()
+        .zip(ring.iter().cycle().skip(1))
+        .map(|(start, end)| (*start, *end))
+}
+
+/// Even-odd test of a point against a single ring.
+fn ring_contains(ring: &[(f64, f64)], x: f64, y: f64) -> bool {
+    edges(ring).fold(false, |inside, ((x0, y0), (x1, y1))| {
+        if (y0 > y) != (y1 > y) && x < (x1 - x0) * (y - y0) / (y1 - y0) + x0 {
+            !inside
+        } else {
+            inside
+        }
+    })
+}
+
+/// Liang-Barsky clipping of a segment against a closed box.
+fn segment_intersects_box(
+    (x0, y0): (f64, f64),
+    (x1, y1): (f64, f64),
+    x_min: f64,
+    y_min: f64,
+    x_max: f64,
+    y_max: f64,
+) -> bool {
+    let (dx, dy) = (x1 - x0, y1 - y0);
+    let mut t0 = 0.0;
+    let mut t1 = 1.0;
+
+    for &(p, q) in &[
+        (-dx, x0 - x_min),
+        (dx, x_max - x0),
+        (-dy, y0 - y_min),
+        (dy, y_max - y0),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return false;
+            }
+        } else {
+            let r = q / p;
+            if p < 0.0 {
+                if r > t1 {
+                    return false;
+                }
+                t0 = r.max(t0);
+            } else {
+                if r < t0 {
+                    return false;
+                }
+                t1 = r.min(t1);
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn square_with_hole() -> Polygon {
+        Polygon::new(
+            vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)],
+            vec![vec![
+                (4.0, 4.0),
+                (6.0, 4.0),
+                (6.0, 6.0),
+                (4.0, 6.0),
+                (4.0, 4.0),
+            ]],
+        )
+    }
+
+    #[test]
+    fn test_contains() {
+        let polygon = square_with_hole();
+        assert!(polygon.contains(1.0, 1.0));
+        assert!(!polygon.contains(5.0, 5.0));
+        assert!(!polygon.contains(11.0, 5.0));
+        assert_eq!(polygon.bounding_box(), (0.0, 0.0, 10.0, 10.0));
+    }
+
+    #[test]
+    fn test_relate() {
+        let polygon = square_with_hole();
+        assert_eq!(polygon.relate(1.0, 1.0, 3.0, 3.0), Relation::Inside);
+        assert_eq!(polygon.relate(4.5, 4.5, 5.5, 5.5), Relation::Outside);
+        assert_eq!(polygon.relate(20.0, 20.0, 30.0, 30.0), Relation::Outside);
+        assert_eq!(polygon.relate(3.0, 3.0, 5.0, 5.0), Relation::Crossing);
+        assert_eq!(polygon.relate(9.0, 1.0, 11.0, 2.0), Relation::Crossing);
+        assert_eq!(polygon.relate(-1.0, -1.0, 11.0, 11.0), Relation::Crossing);
+
+        let triangle = Polygon::new(vec![(0.0, 0.0), (10.0, 0.0), (0.0, 10.0)], vec![]);
+        assert_eq!(triangle.relate(7.0, 7.0, 9.0, 9.0), Relation::Outside);
+        assert_eq!(triangle.relate(1.0, 1.0, 2.0, 2.0), Relation::Inside);
+    }
+
+    #[test]
+    fn test_haversine_distance() {
+        let paris_to_london = haversine_distance(2.3522, 48.8566, -0.1276, 51.5072);
+        assert!((paris_to_london - 343_500.0).abs() < 1_000.0);
+        assert!(haversine_distance(179.5, 0.0, -179.5, 0.0) < 112_000.0);
+
+        assert_eq!(box_distance(0.5, 0.5, 0.0, 0.0, 1.0, 1.0), 0.0);
+        let to_edge = box_distance(0.0, 0.0, 1.0, -1.0, 2.0, 1.0);
+        assert!((to_edge - haversine_distance(0.0, 0.0, 1.0, 0.0)).abs() < 1e-6);
+        let across = box_distance(179.5, 0.0, -180.0, -1.0, -179.0, 1.0);
+        assert!((across - haversine_distance(179.5, 0.0, 180.0, 0.0)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_circle() {
+        let circle = Circle::new(0.0, 0.0, 111_195.0);
+        assert_eq!(Circle::try_new(0.0, 0.0, 111_195.0), Ok(circle));
+        assert_eq!(Circle::try_new(f64::NAN, 0.0, 1.0), Err(Error::NotFinite));
+        assert_eq!(Circle::try_new(0.0, 0.0, -1.0), Err(Error::OutOfBounds));
+        assert!(circle.contains(0.99, 0.0));
+        assert!(!circle.contains(0.75, 0.75));
+        assert_eq!(circle.relate(-0.5, -0.5, 0.5, 0.5), Relation::Inside);
+        assert_eq!(circle.relate(0.5, 0.5, 1.5, 1.5), Relation::Crossing);
+        assert_eq!(circle.relate(0.8, 0.8, 1.5, 1.5), Relation::Outside);
+
+        let antimeridian = Circle::new(179.5, 0.0, 111_195.0);
+        let (x_min, _, x_max, _) = antimeridian.bounding_box();
+        assert!(x_min > x_max);
+        assert!(antimeridian.contains(-179.6, 0.0));
+        assert_eq!(
+            antimeridian.relate(-179.8, -0.1, -179.7, 0.1),
+            Relation::Inside
+        );
+        assert_eq!(
+            antimeridian.relate(170.0, -0.1, 170.1, 0.1),
+            Relation::Outside
+        );
+
+        let pole = Circle::new(45.0, 89.5, 111_195.0);
+        assert_eq!(pole.bounding_box().0, -180.0);
+        assert_eq!(pole.bounding_box().2, 180.0);
+        assert!(pole.contains(-135.0, 89.6));
+        assert_eq!(pole.relate(-180.0, 89.9, 180.0, 90.0), Relation::Inside);
+        assert_eq!(pole.relate(-140.0, 89.4, -130.0, 89.6), Relation::Crossing);
+        assert_eq!(pole.relate(-140.0, 80.0, -130.0, 85.0), Relation::Outside);
+    }
+}
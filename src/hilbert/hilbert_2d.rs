@@ -0,0 +1,281 @@
+//
+// Copyright 2020, Gobsmacked Labs, LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A two dimensional Hilbert curve.
+
+use crate::{
+    bounds::split_x_interval,
+    curve::SpaceFillingCurve2D,
+    hilbert::{axes_to_transpose, bits_for, hilbert_ranges, transpose_to_axes, CellWindow},
+    index_range::KeyRange,
+    zorder::{z_2::Z2, z_n::ZN},
+    RangeComputeHints, RangeHints,
+};
+use alloc::vec::Vec;
+
+/// 2-Dimensional Hilbert curve, with x as longitude and y as latitude.
+pub struct HilbertCurve2D {
+    resolution: u32,
+    bits: u32,
+    x_min: f64,
+    x_max: f64,
+    y_min: f64,
+    y_max: f64,
+}
+
+impl Default for HilbertCurve2D {
+    fn default() -> Self {
+        HilbertCurve2D::new(1024, -180.0, -90.0, 180.0, 90.0)
+    }
+}
+
+impl HilbertCurve2D {
+    /// Max Recursion constant to use.
+    const MAX_RECURSION: usize = 32;
+
+    /// Constructor.
+    ///
+    /// # NOTE:
+    ///   panics if `resolution` is 0 or larger than 2^31.
+    #[must_use]
+    pub fn new(resolution: u32, x_min: f64, y_min: f64, x_max: f64, y_max: f64) -> Self {
+        assert!(resolution > 0 && u64::from(resolution) <= Z2::MAX_MASK + 1);
+
+        HilbertCurve2D {
+            resolution,
+            bits: bits_for(resolution),
+            x_min,
+            x_max,
+            y_min,
+            y_max,
+        }
+    }
+
+    fn cell_width(&self) -> f64 {
+        (self.x_max - self.x_min) / f64::from(self.resolution)
+    }
+
+    fn cell_height(&self) -> f64 {
+        (self.y_max - self.y_min) / f64::from(self.resolution)
+    }
+
+    fn map_to_col(&self, x: f64) -> u32 {
+        (((x - self.x_min) / self.cell_width()) as u32).min(self.resolution - 1)
+    }
+
+    fn map_to_row(&self, y: f64) -> u32 {
+        (((y - self.y_min) / self.cell_height()) as u32).min(self.resolution - 1)
+    }
+
+    fn col_to_map(&self, col: u32) -> f64 {
+        (f64::from(col) * self.cell_width() + self.x_min + self.cell_width() / 2.0)
+            .min(self.x_max)
+            .max(self.x_min)
+    }
+
+    fn row_to_map(&self, row: u32) -> f64 {
+        (f64::from(row) * self.cell_height() + self.y_min + self.cell_height() / 2.0)
+            .min(self.y_max)
+            .max(self.y_min)
+    }
+
+    fn encode(cell: [u32; 2], bits: u32) -> u64 {
+        let mut x = cell;
+        axes_to_transpose(&mut x, bits);
+        Z2::split(x[0]) << 1 | Z2::split(x[1])
+    }
+
+    fn decode(index: u64, bits: u32) -> [u32; 2] {
+        let mut x = [Z2::combine(index >> 1), Z2::combine(index)];
+        transpose_to_axes(&mut x, bits);
+        x
+    }
+
+    /// Get the index for a point.
+    #[must_use]
+    pub fn index(&self, x: f64, y: f64) -> u64 {
+        Self::encode([self.map_to_col(x), self.map_to_row(y)], self.bits)
+    }
+
+    /// Get the point for an index.
+    #[must_use]
+    pub fn point(&self, index: u64) -> (f64, f64) {
+        let [col, row] = Self::decode(index, self.bits);
+        (self.col_to_map(col), self.row_to_map(row))
+    }
+
+    /// Get the index ranges for a bounding box. A box with `x_min > x_max` wraps
+    /// across the x bounds, e.g. a window over the antimeridian.
+    #[must_use]
+    pub fn ranges(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        let windows = split_x_interval(x_min, x_max, self.x_min, self.x_max)
+            .map(|(x_min, x_max)| CellWindow {
+                min: [self.map_to_col(x_min), self.map_to_row(y_min)],
+                max: [self.map_to_col(x_max), self.map_to_row(y_max)],
+            })
+            .collect::<Vec<_>>();
+
+        let hints = RangeHints::new(hints);
+        hints.coalesce(hilbert_ranges(
+            &windows,
+            self.bits,
+            hints.max_recurse(Self::MAX_RECURSION),
+            Self::encode,
+        ))
+    }
+}
+
+impl SpaceFillingCurve2D for HilbertCurve2D {
+    fn index(&self, x: f64, y: f64) -> u64 {
+        HilbertCurve2D::index(self, x, y)
+    }
+
+    fn bounds(&self) -> (f64, f64, f64, f64) {
+        (self.x_min, self.y_min, self.x_max, self.y_max)
+    }
+
+    fn ranges(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        HilbertCurve2D::ranges(self, x_min, y_min, x_max, y_max, hints)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hilbert_order_2() {
+        // The first order curve visits (0, 0), (0, 1), (1, 1), (1, 0).
+        assert_eq!(HilbertCurve2D::encode([0, 0], 1), 0);
+        assert_eq!(HilbertCurve2D::encode([0, 1], 1), 1);
+        assert_eq!(HilbertCurve2D::encode([1, 1], 1), 2);
+        assert_eq!(HilbertCurve2D::encode([1, 0], 1), 3);
+    }
+
+    #[quickcheck]
+    fn test_encode_decode(x: u32, y: u32) -> bool {
+        let (x, y) = (x & 0xffff, y & 0xffff);
+        HilbertCurve2D::decode(HilbertCurve2D::encode([x, y], 16), 16) == [x, y]
+    }
+
+    #[test]
+    fn test_consecutive_indices_are_adjacent() {
+        let bits = 4;
+        for i in 0..(1_u64 << (2 * bits)) - 1 {
+            let [x0, y0] = HilbertCurve2D::decode(i, bits);
+            let [x1, y1] = HilbertCurve2D::decode(i + 1, bits);
+            assert_eq!(
+                (x0 as i64 - x1 as i64).abs() + (y0 as i64 - y1 as i64).abs(),
+                1
+            );
+        }
+    }
+
+    #[test]
+    fn point_to_index_to_point() {
+        let curve = HilbertCurve2D::default();
+        let index = curve.index(-45.0, -45.0);
+        let point = curve.point(index);
+        assert!(point > (-45.0 - 1.0, -45.0 - 1.0));
+        assert!(point < (-45.0 + 1.0, -45.0 + 1.0));
+    }
+
+    #[test]
+    fn test_ranges_are_exact() {
+        let curve = HilbertCurve2D::new(16, 0.0, 0.0, 16.0, 16.0);
+        let ranges = curve.ranges(3.5, 2.5, 9.5, 7.5, &[RangeComputeHints::MaxRecurse(32)]);
+
+        assert!(ranges.iter().all(|r| r.contained()));
+        assert!(ranges.windows(2).all(|w| w[0].upper() + 1 < w[1].lower()));
+
+        let mut count = 0;
+        for x in 0..16 {
+            for y in 0..16 {
+                let index = curve.index(f64::from(x) + 0.5, f64::from(y) + 0.5);
+                let inside = (3..=9).contains(&x) && (2..=7).contains(&y);
+                let found = ranges
+                    .iter()
+                    .any(|r| r.lower() <= index && index <= r.upper());
+                assert_eq!(inside, found);
+                count += inside as u64;
+            }
+        }
+        assert_eq!(
+            ranges
+                .iter()
+                .map(|r| r.upper() - r.lower() + 1)
+                .sum::<u64>(),
+            count
+        );
+    }
+
+    #[test]
+    fn test_antimeridian_ranges() {
+        let curve = HilbertCurve2D::new(16, 0.0, 0.0, 16.0, 16.0);
+        let ranges = curve.ranges(13.5, 2.5, 2.5, 7.5, &[RangeComputeHints::MaxRecurse(32)]);
+
+        assert!(ranges.windows(2).all(|w| w[0].upper() + 1 < w[1].lower()));
+        for x in 0..16 {
+            for y in 0..16 {
+                let index = curve.index(f64::from(x) + 0.5, f64::from(y) + 0.5);
+                let inside = (x >= 13 || x <= 2) && (2..=7).contains(&y);
+                let found = ranges
+                    .iter()
+                    .any(|r| r.lower() <= index && index <= r.upper());
+                assert_eq!(inside, found);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sweep_through_map() {
+        let curve = HilbertCurve2D::default();
+
+        let mut lon = -180.0;
+        while lon < 180.0 {
+            let mut lat = -90.0;
+            while lat < 90.0 {
+                let indexed_point = curve.index(lon, lat);
+                let range = curve.ranges(
+                    (lon - 10.0).max(-180.0),
+                    (lat - 10.0).max(-90.0),
+                    (lon + 10.0).min(180.0),
+                    (lat + 10.0).min(90.0),
+                    &[],
+                );
+                assert!(range
+                    .iter()
+                    .any(|r| r.lower() <= indexed_point && indexed_point <= r.upper()));
+
+                lat += 7.0;
+            }
+            lon += 7.0;
+        }
+    }
+}
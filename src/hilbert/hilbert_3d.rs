@@ -0,0 +1,294 @@
+//
+// Copyright 2020, Gobsmacked Labs, LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A three dimensional Hilbert curve.
+
+use crate::{
+    bounds::split_x_interval,
+    curve::SpaceTimeFillingCurve,
+    hilbert::{axes_to_transpose, bits_for, hilbert_ranges, transpose_to_axes, CellWindow},
+    index_range::KeyRange,
+    zorder::{z_3::Z3, z_n::ZN},
+    RangeComputeHints, RangeHints,
+};
+use alloc::vec::Vec;
+
+/// A Hilbert curve to index a point and time.
+pub struct HilbertCurve3D {
+    g: u32,
+    bits: u32,
+    x_min: f64,
+    x_max: f64,
+    y_min: f64,
+    y_max: f64,
+    z_max: f64,
+}
+
+const MAX_RECURSION: usize = 32;
+
+impl Default for HilbertCurve3D {
+    fn default() -> HilbertCurve3D {
+        HilbertCurve3D::new(10_000, -180.0, -90.0, 180.0, 90.0, 2_556_057_600.0)
+    }
+}
+
+impl HilbertCurve3D {
+    /// Constructor with bounds on the space-time that this index will act on.
+    ///
+    /// # NOTE:
+    ///   panics if `g` is 0 or larger than 2^21.
+    #[must_use]
+    pub fn new(g: u32, x_min: f64, y_min: f64, x_max: f64, y_max: f64, z_max: f64) -> Self {
+        assert!(g > 0 && u64::from(g) <= Z3::MAX_MASK + 1);
+
+        HilbertCurve3D {
+            g,
+            bits: bits_for(g),
+            x_min,
+            x_max,
+            y_min,
+            y_max,
+            z_max,
+        }
+    }
+
+    fn cell_height(&self) -> f64 {
+        (self.y_max - self.y_min) / f64::from(self.g)
+    }
+
+    fn cell_width(&self) -> f64 {
+        (self.x_max - self.x_min) / f64::from(self.g)
+    }
+
+    fn cell_depth(&self) -> f64 {
+        self.z_max / f64::from(self.g)
+    }
+
+    fn map_to_col(&self, x: f64) -> u32 {
+        (((x - self.x_min) / self.cell_width()) as u32).min(self.g - 1)
+    }
+
+    fn map_to_row(&self, y: f64) -> u32 {
+        (((y - self.y_min) / self.cell_height()) as u32).min(self.g - 1)
+    }
+
+    fn time_to_depth(&self, z: f64) -> u32 {
+        ((z / self.cell_depth()) as u32).min(self.g - 1)
+    }
+
+    fn col_to_map(&self, col: u32) -> f64 {
+        (f64::from(col) * self.cell_width() + self.x_min + self.cell_width() / 2.0)
+            .min(self.x_max)
+            .max(self.x_min)
+    }
+
+    fn row_to_map(&self, row: u32) -> f64 {
+        (f64::from(row) * self.cell_height() + self.y_min + self.cell_height() / 2.0)
+            .min(self.y_max)
+            .max(self.y_min)
+    }
+
+    fn depth_to_time(&self, depth: u32) -> f64 {
+        (f64::from(depth) * self.cell_depth() + self.cell_depth() / 2.0)
+            .min(self.z_max)
+            .max(0.0)
+    }
+
+    fn encode(cell: [u32; 3], bits: u32) -> u64 {
+        let mut x = cell;
+        axes_to_transpose(&mut x, bits);
+        Z3::split(x[0]) << 2 | Z3::split(x[1]) << 1 | Z3::split(x[2])
+    }
+
+    fn decode(index: u64, bits: u32) -> [u32; 3] {
+        let mut x = [
+            Z3::combine(index >> 2),
+            Z3::combine(index >> 1),
+            Z3::combine(index),
+        ];
+        transpose_to_axes(&mut x, bits);
+        x
+    }
+
+    /// Index a `x` longitude, `y` latitude, and a timestamp `t`.
+    #[must_use]
+    pub fn index(&self, x: f64, y: f64, t: f64) -> u64 {
+        Self::encode(
+            [
+                self.map_to_col(x),
+                self.map_to_row(y),
+                self.time_to_depth(t),
+            ],
+            self.bits,
+        )
+    }
+
+    /// Return the x,y,t from an index.
+    #[must_use]
+    pub fn invert(&self, i: u64) -> (f64, f64, f64) {
+        let [col, row, depth] = Self::decode(i, self.bits);
+        (
+            self.col_to_map(col),
+            self.row_to_map(row),
+            self.depth_to_time(depth),
+        )
+    }
+
+    /// Return the `KeyRange`s that cover the bounding box and time range. A box with
+    /// `x_min > x_max` wraps across the x bounds, e.g. a window over the antimeridian.
+    #[must_use]
+    #[allow(clippy::too_many_arguments)]
+    pub fn ranges(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        t_min: f64,
+        t_max: f64,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        let windows = split_x_interval(x_min, x_max, self.x_min, self.x_max)
+            .map(|(x_min, x_max)| CellWindow {
+                min: [
+                    self.map_to_col(x_min),
+                    self.map_to_row(y_min),
+                    self.time_to_depth(t_min),
+                ],
+                max: [
+                    self.map_to_col(x_max),
+                    self.map_to_row(y_max),
+                    self.time_to_depth(t_max),
+                ],
+            })
+            .collect::<Vec<_>>();
+
+        let hints = RangeHints::new(hints);
+        hints.coalesce(hilbert_ranges(
+            &windows,
+            self.bits,
+            hints.max_recurse(MAX_RECURSION),
+            Self::encode,
+        ))
+    }
+}
+
+impl SpaceTimeFillingCurve for HilbertCurve3D {
+    fn index(&self, x: f64, y: f64, t: f64) -> u64 {
+        HilbertCurve3D::index(self, x, y, t)
+    }
+
+    fn bounds(&self) -> (f64, f64, f64, f64) {
+        (self.x_min, self.y_min, self.x_max, self.y_max)
+    }
+
+    fn ranges(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        t_min: f64,
+        t_max: f64,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        HilbertCurve3D::ranges(self, x_min, y_min, x_max, y_max, t_min, t_max, hints)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[quickcheck]
+    fn test_encode_decode(x: u16, y: u16, z: u16) -> bool {
+        let cell = [x.into(), y.into(), z.into()];
+        HilbertCurve3D::decode(HilbertCurve3D::encode(cell, 16), 16) == cell
+    }
+
+    #[test]
+    fn test_consecutive_indices_are_adjacent() {
+        let bits = 3;
+        for i in 0..(1_u64 << (3 * bits)) - 1 {
+            let a = HilbertCurve3D::decode(i, bits);
+            let b = HilbertCurve3D::decode(i + 1, bits);
+            let distance: i64 = (0..3).map(|d| (a[d] as i64 - b[d] as i64).abs()).sum();
+            assert_eq!(distance, 1);
+        }
+    }
+
+    #[test]
+    fn test_hilbert_time_curve() {
+        let curve = HilbertCurve3D::new(1024, -180.0, -90.0, 180.0, 90.0, 1207632712000.0);
+
+        let minneapolis_1995 = curve.index(-93.2650, 44.9778, 792013512000.0);
+        let minneapolis_2005 = curve.index(-93.2650, 44.9778, 1107632712000.0);
+
+        let minneapolis_1995_query = curve.ranges(
+            -93.266,
+            44.9777,
+            -93.264,
+            44.9779,
+            792013412000.0,
+            792013612000.0,
+            &[],
+        );
+
+        assert!(minneapolis_1995_query
+            .iter()
+            .any(|r| r.lower() <= minneapolis_1995 && r.upper() >= minneapolis_1995));
+        assert!(!minneapolis_1995_query
+            .iter()
+            .any(|r| r.lower() <= minneapolis_2005 && r.upper() >= minneapolis_2005));
+    }
+
+    #[test]
+    fn test_ranges_are_exact() {
+        let curve = HilbertCurve3D::new(8, 0.0, 0.0, 8.0, 8.0, 8.0);
+        let ranges = curve.ranges(
+            1.5,
+            2.5,
+            5.5,
+            6.5,
+            0.5,
+            3.5,
+            &[RangeComputeHints::MaxRecurse(32)],
+        );
+
+        let mut count = 0;
+        for x in 0..8 {
+            for y in 0..8 {
+                for t in 0..8 {
+                    let index =
+                        curve.index(f64::from(x) + 0.5, f64::from(y) + 0.5, f64::from(t) + 0.5);
+                    let inside =
+                        (1..=5).contains(&x) && (2..=6).contains(&y) && (0..=3).contains(&t);
+                    let found = ranges
+                        .iter()
+                        .any(|r| r.lower() <= index && index <= r.upper());
+                    assert_eq!(inside, found);
+                    count += inside as u64;
+                }
+            }
+        }
+        assert_eq!(
+            ranges
+                .iter()
+                .map(|r| r.upper() - r.lower() + 1)
+                .sum::<u64>(),
+            count
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//
+// Copyright 2020, Gobsmacked Labs, LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Hilbert curve implementations for indexing points.
+//!
+//! Encoding and decoding use John Skilling's transform from 'Programming the Hilbert
+//! curve' (2004). Every aligned quad/oct tree cell covers a contiguous run of Hilbert
+//! indices, which is what the range decomposition relies on.
+
+pub mod hilbert_2d;
+pub mod hilbert_3d;
+
+use crate::index_range::{merge_ranges, KeyRange};
+use alloc::vec::Vec;
+
+const DEFAULT_RECURSE: usize = 7;
+
+/// Convert axes coordinates to the transposed Hilbert index, in place.
+fn axes_to_transpose(x: &mut [u32], bits: u32) {
+    let n = x.len();
+    let m = 1_u32 << (bits - 1);
+
+    let mut q = m;
+    while q > 1 {
+        let p = q - 1;
+        for i in 0..n {
+            if x[i] & q != 0 {
+                x[0] ^= p;
+            } else {
+                let t = (x[0] ^ x[i]) & p;
+                x[0] ^= t;
+                x[i] ^= t;
+            }
+        }
+        q >>= 1;
+    }
+
+    for i in 1..n {
+        x[i] ^= x[i - 1];
+    }
+
+    let mut t = 0;
+    let mut q = m;
+    while q > 1 {
+        if x[n - 1] & q != 0 {
+            t ^= q - 1;
+        }
+        q >>= 1;
+    }
+
+    for v in x.iter_mut() {
+        *v ^= t;
+    }
+}
+
+/// Convert the transposed Hilbert index back to axes coordinates, in place.
+fn transpose_to_axes(x: &mut [u32], bits: u32) {
+    let n = x.len();
+    let end = 2_u64 << (bits - 1);
+
+    let t = x[n - 1] >> 1;
+    for i in (1..n).rev() {
+        x[i] ^= x[i - 1];
+    }
+    x[0] ^= t;
+
+    let mut q = 2_u64;
+    while q != end {
+        let p = (q - 1) as u32;
+        for i in (0..n).rev() {
+            if u64::from(x[i]) & q != 0 {
+                x[0] ^= p;
+            } else {
+                let t = (x[0] ^ x[i]) & p;
+                x[0] ^= t;
+                x[i] ^= t;
+            }
+        }
+        q <<= 1;
+    }
+}
+
+/// Number of bits needed to address `resolution` cells in a dimension.
+fn bits_for(resolution: u32) -> u32 {
+    (32 - resolution.saturating_sub(1).leading_zeros()).max(1)
+}
+
+/// An inclusive window of cells.
+#[derive(Debug, Clone, Copy)]
+struct CellWindow<const D: usize> {
+    min: [u32; D],
+    max: [u32; D],
+}
+
+impl<const D: usize> CellWindow<D> {
+    fn contains(&self, min: &[u32; D], side: u32) -> bool {
+        (0..D).all(|i| self.min[i] <= min[i] && min[i] + (side - 1) <= self.max[i])
+    }
+
+    fn overlaps(&self, min: &[u32; D], side: u32) -> bool {
+        (0..D).all(|i| self.min[i] <= min[i] + (side - 1) && min[i] <= self.max[i])
+    }
+}
+
+/// Recursive decomposition of a Hilbert curve into the index ranges covering
+/// `windows`.
+///
+/// Cells are refined until they are contained in a window or `max_recurse` levels
+/// below the smallest cell that contains every window have been visited.
+fn hilbert_ranges<const D: usize>(
+    windows: &[CellWindow<D>],
+    bits: u32,
+    max_recurse: Option<usize>,
+    encode: fn([u32; D], u32) -> u64,
+) -> Vec<KeyRange> {
+    let max_recurse = max_recurse.unwrap_or(DEFAULT_RECURSE);
+    let mut ranges: Vec<KeyRange> = Vec::with_capacity(100);
+
+    // Descend to the smallest cell that still holds every window, the equivalent of
+    // the longest common prefix in `ZN::zranges`.
+    let mut corner = [0_u32; D];
+    let mut level = 0;
+    while level < bits {
+        let half = 1_u32 << (bits - level - 1);
+        let mut overlapping =
+            child_corners(corner, half).filter(|c| windows.iter().any(|w| w.overlaps(c, half)));
+        match (overlapping.next(), overlapping.next()) {
+            (Some(child), None) => {
+                corner = child;
+                level += 1;
+            }
+            _ => break,
+        }
+    }
+
+    let mut remaining = Vec::with_capacity(100);
+    remaining.push((corner, level, 0));
+
+    while let Some((corner, level, depth)) = remaining.pop() {
+        let side = 1_u32 << (bits - level);
+        let mask = (1_u64 << (D as u32 * (bits - level))) - 1;
+        let lower = encode(corner, bits) & !mask;
+        let upper = lower | mask;
+
+        if windows.iter().any(|w| w.contains(&corner, side)) {
+            ranges.push(KeyRange::covered(lower, upper));
+        } else if windows.iter().any(|w| w.overlaps(&corner, side)) {
+            if level == bits || depth >= max_recurse {
+                ranges.push(KeyRange::overlapping(lower, upper));
+            } else {
+                for child in child_corners(corner, side / 2) {
+                    remaining.push((child, level + 1, depth + 1));
+                }
+            }
+        }
+    }
+
+    merge_ranges(ranges, true)
+}
+
+fn child_corners<const D: usize>(corner: [u32; D], half: u32) -> impl Iterator<Item = [u32; D]> {
+    (0..(1_u32 << D)).map(move |quadrant| {
+        let mut child = corner;
+        for (i, c) in child.iter_mut().enumerate() {
+            if quadrant & (1 << i) != 0 {
+                *c += half;
+            }
+        }
+        child
+    })
+}
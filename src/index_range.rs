@@ -13,12 +13,24 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-//! Contains trait `IndexRange` and concrete structs `CoveredRange` and
-//! `OverlappingRange`. `IndexRange` has `Ord` so is sortable.
+//! Contains `KeyRange`, the range type returned by every curve, `RangeSet` for
+//! combining range lists, and the compatibility trait `IndexRange` with concrete
+//! structs `CoveredRange` and `OverlappingRange`. `IndexRange` has `Ord` so is
+//! sortable.
+//! ```
+//! use space_time::{index_range::KeyRange, zorder::z_curve_2d::ZCurve2D};
+//!
+//! let ranges: Vec<KeyRange> = ZCurve2D::default().ranges(2.35, 48.85, 2.354, 48.857, &[]);
+//! let copied = ranges.clone();
+//!
+//! assert_eq!(ranges, copied);
+//! assert!(ranges.iter().all(|r| r.lower() <= r.upper()));
+//! ```
 
+use alloc::{vec, vec::Vec};
 use core::cmp::{Ord, Ordering};
 
-/// Sortable Range trait.
+/// Sortable Range trait, kept for compatibility. Implemented by `KeyRange`.
 pub trait IndexRange: core::fmt::Debug {
     /// The lower index.
     fn lower(&self) -> u64;
@@ -67,6 +79,446 @@ impl PartialEq for dyn IndexRange {
 
 impl Eq for dyn IndexRange {}
 
+/// A range of indexes from `lower` to `upper` inclusive. The range is `contained`
+/// if every index in it is inside the query, otherwise it only overlaps the query.
+///
+/// Ordered by `lower`, then `upper`, then `contained`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct KeyRange {
+    lower: u64,
+    upper: u64,
+    contained: bool,
+}
+
+impl KeyRange {
+    /// Constructor.
+    #[must_use]
+    pub const fn new(lower: u64, upper: u64, contained: bool) -> Self {
+        KeyRange {
+            lower,
+            upper,
+            contained,
+        }
+    }
+
+    /// A range that is contained in the query.
+    #[must_use]
+    pub const fn covered(lower: u64, upper: u64) -> Self {
+        Self::new(lower, upper, true)
+    }
+
+    /// A range that overlaps the query.
+    #[must_use]
+    pub const fn overlapping(lower: u64, upper: u64) -> Self {
+        Self::new(lower, upper, false)
+    }
+
+    /// The lower index.
+    #[must_use]
+    pub const fn lower(&self) -> u64 {
+        self.lower
+    }
+
+    /// The upper index.
+    #[must_use]
+    pub const fn upper(&self) -> u64 {
+        self.upper
+    }
+
+    /// Contained.
+    #[must_use]
+    pub const fn contained(&self) -> bool {
+        self.contained
+    }
+
+    /// Returns all three (lower, upper, contained) as a tuple.
+    #[must_use]
+    pub const fn tuple(&self) -> (u64, u64, bool) {
+        (self.lower, self.upper, self.contained)
+    }
+}
+
+impl IndexRange for KeyRange {
+    fn lower(&self) -> u64 {
+        self.lower
+    }
+
+    fn upper(&self) -> u64 {
+        self.upper
+    }
+
+    fn contained(&self) -> bool {
+        self.contained
+    }
+}
+
+impl From<CoveredRange> for KeyRange {
+    fn from(range: CoveredRange) -> Self {
+        KeyRange::covered(range.lower, range.upper)
+    }
+}
+
+impl From<OverlappingRange> for KeyRange {
+    fn from(range: OverlappingRange) -> Self {
+        KeyRange::overlapping(range.lower, range.upper)
+    }
+}
+
+/// Sort `ranges` and merge the ones that overlap or are adjacent. Adjacent ranges of
+/// different containment are only merged if `merge_mixed` is set.
+pub(crate) fn merge_ranges(mut ranges: Vec<KeyRange>, merge_mixed: bool) -> Vec<KeyRange> {
+    ranges.sort_unstable();
+
+    let mut results: Vec<KeyRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match results.last_mut() {
+            Some(cur)
+                if range.lower <= cur.upper
+                    || (range.lower == cur.upper + 1
+                        && (merge_mixed || cur.contained == range.contained)) =>
+            {
+                cur.upper = cur.upper.max(range.upper);
+                cur.contained = cur.contained && range.contained;
+            }
+            _ => results.push(range),
+        }
+    }
+    results
+}
+
+/// Merge sorted, disjoint ranges across the smallest gaps until at most `max_ranges`
+/// remain, and across any gap of at most `gap_tolerance` keys. A range merged across
+/// a gap is not `contained`.
+pub(crate) fn coalesce_ranges(
+    ranges: Vec<KeyRange>,
+    max_ranges: usize,
+    gap_tolerance: u64,
+) -> Vec<KeyRange> {
+    let mut gaps = ranges
+        .windows(2)
+        .enumerate()
+        .map(|(i, w)| (w[1].lower - w[0].upper - 1, i))
+        .collect::<Vec<_>>();
+    gaps.sort_unstable();
+
+    // join[i] merges range i + 1 into range i.
+    let excess = ranges.len().saturating_sub(max_ranges);
+    let mut join = vec![false; gaps.len()];
+    for (n, &(gap, i)) in gaps.iter().enumerate() {
+        join[i] = n < excess || (gap > 0 && gap <= gap_tolerance);
+    }
+
+    let mut results: Vec<KeyRange> = Vec::with_capacity(ranges.len() - excess);
+    for (i, range) in ranges.into_iter().enumerate() {
+        match results.last_mut() {
+            Some(cur)
+                if join[i - 1]
+                    || (cur.upper + 1 == range.lower && cur.contained == range.contained) =>
+            {
+                cur.contained = cur.contained && range.contained && cur.upper + 1 == range.lower;
+                cur.upper = range.upper;
+            }
+            _ => results.push(range),
+        }
+    }
+    results
+}
+
+/// A normalized set of `KeyRange`s: sorted, disjoint, and with adjacent ranges of the
+/// same containment merged.
+///
+/// Each key in the set is either covered, every row with that key matches, or
+/// overlapping, rows with that key may match. The operations follow the per-key
+/// rules below, where covered > overlapping > absent.
+///
+/// - `union`: the greater of the two states.
+/// - `intersection`: the lesser of the two states.
+/// - `difference`: absent where the other set is covered, at most overlapping where the
+///   other set is overlapping.
+/// - `complement`: the difference from the whole key space.
+/// ```
+/// use space_time::index_range::{KeyRange, RangeSet};
+///
+/// let region: RangeSet = vec![KeyRange::covered(0, 99)].into_iter().collect();
+/// let exclusion: RangeSet = vec![KeyRange::overlapping(40, 59)].into_iter().collect();
+///
+/// let remaining = region.difference(&exclusion);
+/// assert_eq!(
+///     remaining.ranges(),
+///     &[
+///         KeyRange::covered(0, 39),
+///         KeyRange::overlapping(40, 59),
+///         KeyRange::covered(60, 99)
+///     ]
+/// );
+/// assert!(remaining.contains(50));
+/// assert!(!remaining.contains(100));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct RangeSet {
+    ranges: Vec<KeyRange>,
+}
+
+impl RangeSet {
+    /// An empty set.
+    #[must_use]
+    pub fn new() -> Self {
+        RangeSet { ranges: Vec::new() }
+    }
+
+    /// The normalized ranges, in ascending order.
+    #[must_use]
+    pub fn ranges(&self) -> &[KeyRange] {
+        &self.ranges
+    }
+
+    /// The normalized ranges, in ascending order.
+    #[must_use]
+    pub fn into_ranges(self) -> Vec<KeyRange> {
+        self.ranges
+    }
+
+    /// Whether the set has no keys.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Test whether `key` is in the set.
+    #[must_use]
+    pub fn contains(&self, key: u64) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Get the range holding `key`, if any.
+    #[must_use]
+    pub fn get(&self, key: u64) -> Option<KeyRange> {
+        let i = self.ranges.partition_point(|r| r.upper < key);
+        self.ranges.get(i).filter(|r| r.lower <= key).copied()
+    }
+
+    /// Keys in either set.
+    #[must_use]
+    pub fn union(&self, other: &RangeSet) -> RangeSet {
+        combine(&self.ranges, &other.ranges, Ord::max)
+    }
+
+    /// Keys in both sets.
+    #[must_use]
+    pub fn intersection(&self, other: &RangeSet) -> RangeSet {
+        combine(&self.ranges, &other.ranges, Ord::min)
+    }
+
+    /// Keys in this set that other does not cover.
+    #[must_use]
+    pub fn difference(&self, other: &RangeSet) -> RangeSet {
+        combine(&self.ranges, &other.ranges, |a, b| match b {
+            Some(true) => None,
+            Some(false) => a.and(Some(false)),
+            None => a,
+        })
+    }
+
+    /// Keys from `lower` to `upper` inclusive, usually the whole key space of a curve,
+    /// that this set does not cover.
+    #[must_use]
+    pub fn complement(&self, lower: u64, upper: u64) -> RangeSet {
+        core::iter::once(KeyRange::covered(lower, upper))
+            .collect::<RangeSet>()
+            .difference(self)
+    }
+}
+
+impl core::iter::FromIterator<KeyRange> for RangeSet {
+    /// Normalize any ranges, a key in both a covered and an overlapping range is
+    /// covered. Ranges with `lower > upper` are ignored.
+    fn from_iter<I: IntoIterator<Item = KeyRange>>(iter: I) -> Self {
+        let (covered, overlapping): (Vec<_>, Vec<_>) = iter
+            .into_iter()
+            .filter(|r| r.lower <= r.upper)
+            .partition(|r| r.contained);
+
+        combine(
+            &merge_ranges(covered, true),
+            &merge_ranges(overlapping, true),
+            Ord::max,
+        )
+    }
+}
+
+impl From<Vec<KeyRange>> for RangeSet {
+    fn from(ranges: Vec<KeyRange>) -> Self {
+        ranges.into_iter().collect()
+    }
+}
+
+/// The state of `key` in sorted, disjoint ranges: `Some(contained)` or `None` if
+/// absent.
+fn state(ranges: &[KeyRange], key: u64) -> Option<bool> {
+    let i = ranges.partition_point(|r| r.upper < key);
+    ranges
+        .get(i)
+        .filter(|r| r.lower <= key)
+        .map(|r| r.contained)
+}
+
+/// Apply `op` to the states of every key in two sorted, disjoint range lists. `op`
+/// must map two absent states to absent.
+fn combine(
+    a: &[KeyRange],
+    b: &[KeyRange],
+    op: impl Fn(Option<bool>, Option<bool>) -> Option<bool>,
+) -> RangeSet {
+    // Every key between two consecutive boundaries has the same state in both lists.
+    let mut bounds = a
+        .iter()
+        .chain(b.iter())
+        .flat_map(|r| core::iter::once(r.lower).chain(r.upper.checked_add(1)))
+        .collect::<Vec<u64>>();
+    bounds.sort_unstable();
+    bounds.dedup();
+
+    let mut ranges: Vec<KeyRange> = Vec::with_capacity(bounds.len() / 2);
+    for (i, &lower) in bounds.iter().enumerate() {
+        let upper = bounds.get(i + 1).map_or(u64::MAX, |next| next - 1);
+        if let Some(contained) = op(state(a, lower), state(b, lower)) {
+            match ranges.last_mut() {
+                Some(cur) if cur.upper + 1 == lower && cur.contained == contained => {
+                    cur.upper = upper;
+                }
+                _ => ranges.push(KeyRange::new(lower, upper, contained)),
+            }
+        }
+    }
+    RangeSet { ranges }
+}
+
+/// How much work a range cursor may do in one batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Budget {
+    /// Stop after visiting this many tree nodes. Ranges found so far are returned,
+    /// so a range may be split between two batches.
+    Nodes(usize),
+    /// Stop after emitting this many ranges.
+    Ranges(usize),
+}
+
+/// Merges ranges that arrive in ascending order of `lower`, emitting each range once
+/// it can no longer grow. Emitted ranges never overlap.
+#[derive(Debug, Clone)]
+pub(crate) struct RangeMerger {
+    pending: Option<KeyRange>,
+    last_upper: Option<u64>,
+    merge_mixed: bool,
+}
+
+impl RangeMerger {
+    pub(crate) fn new(merge_mixed: bool) -> Self {
+        RangeMerger {
+            pending: None,
+            last_upper: None,
+            merge_mixed,
+        }
+    }
+
+    /// Add the next range, returning the previous range if it is complete.
+    pub(crate) fn push(&mut self, mut range: KeyRange) -> Option<KeyRange> {
+        // A range may start inside one that was already flushed.
+        if let Some(last) = self.last_upper {
+            if range.upper <= last {
+                return None;
+            }
+            range.lower = range.lower.max(last + 1);
+        }
+
+        match self.pending.as_mut() {
+            Some(cur)
+                if range.lower <= cur.upper
+                    || (range.lower == cur.upper + 1
+                        && (self.merge_mixed || cur.contained == range.contained)) =>
+            {
+                cur.upper = cur.upper.max(range.upper);
+                cur.contained = cur.contained && range.contained;
+                None
+            }
+            _ => self.pending.replace(range).map(|r| self.emit(r)),
+        }
+    }
+
+    /// Whether there is no pending range.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pending.is_none()
+    }
+
+    /// Emit the pending range, if any.
+    pub(crate) fn flush(&mut self) -> Option<KeyRange> {
+        self.pending.take().map(|r| self.emit(r))
+    }
+
+    fn emit(&mut self, range: KeyRange) -> KeyRange {
+        self.last_upper = Some(range.upper);
+        range
+    }
+}
+
+/// The traversal behind a range cursor.
+pub(crate) trait RangeWalk {
+    /// Visit the next node, passing any range it produces to `emit`. Returns false
+    /// once every node has been visited.
+    fn step(&mut self, emit: &mut dyn FnMut(KeyRange)) -> bool;
+}
+
+/// Drive `walk` until it produces a complete range or is exhausted.
+pub(crate) fn next_range<W: RangeWalk>(walk: &mut W, merger: &mut RangeMerger) -> Option<KeyRange> {
+    loop {
+        let mut done = None;
+        let more = walk.step(&mut |range| {
+            if let Some(r) = merger.push(range) {
+                done = Some(r);
+            }
+        });
+        if done.is_some() {
+            return done;
+        }
+        if !more {
+            return merger.flush();
+        }
+    }
+}
+
+/// Drive `walk` within `budget`, returning the complete ranges.
+pub(crate) fn next_batch<W: RangeWalk>(
+    walk: &mut W,
+    merger: &mut RangeMerger,
+    budget: Budget,
+) -> Vec<KeyRange> {
+    let mut results = Vec::new();
+    match budget {
+        Budget::Ranges(count) => {
+            while results.len() < count {
+                match next_range(walk, merger) {
+                    Some(range) => results.push(range),
+                    None => break,
+                }
+            }
+        }
+        Budget::Nodes(count) => {
+            for _ in 0..count {
+                let more = walk.step(&mut |range| {
+                    if let Some(r) = merger.push(range) {
+                        results.push(r);
+                    }
+                });
+                if !more {
+                    break;
+                }
+            }
+            results.extend(merger.flush());
+        }
+    }
+    results
+}
+
 /// A covered range.
 #[derive(Debug, PartialEq, Eq)]
 pub struct CoveredRange {
@@ -124,3 +576,174 @@ impl IndexRange for OverlappingRange {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_key_range() {
+        let range = KeyRange::covered(3, 7);
+        assert_eq!(range.tuple(), (3, 7, true));
+        assert_eq!(
+            KeyRange::from(OverlappingRange::new(3, 7)),
+            KeyRange::new(3, 7, false)
+        );
+
+        let boxed: &dyn IndexRange = &range;
+        assert_eq!(boxed.tuple(), range.tuple());
+    }
+
+    #[test]
+    fn test_merge_ranges() {
+        let ranges = vec![
+            KeyRange::covered(10, 12),
+            KeyRange::overlapping(0, 3),
+            KeyRange::covered(4, 5),
+            KeyRange::covered(13, 14),
+            KeyRange::overlapping(11, 20),
+        ];
+        assert_eq!(
+            merge_ranges(ranges.clone(), true),
+            vec![KeyRange::overlapping(0, 5), KeyRange::overlapping(10, 20)]
+        );
+        assert_eq!(
+            merge_ranges(ranges, false),
+            vec![
+                KeyRange::overlapping(0, 3),
+                KeyRange::covered(4, 5),
+                KeyRange::overlapping(10, 20)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_range_merger() {
+        let mut merger = RangeMerger::new(false);
+        assert_eq!(merger.push(KeyRange::covered(1, 3)), None);
+        assert_eq!(merger.push(KeyRange::covered(4, 5)), None);
+        assert_eq!(
+            merger.push(KeyRange::overlapping(6, 8)),
+            Some(KeyRange::covered(1, 5))
+        );
+        assert_eq!(merger.flush(), Some(KeyRange::overlapping(6, 8)));
+        assert!(merger.is_empty());
+
+        // Ranges already emitted are clipped away.
+        assert_eq!(merger.push(KeyRange::overlapping(7, 8)), None);
+        assert!(merger.is_empty());
+        assert_eq!(merger.push(KeyRange::covered(8, 10)), None);
+        assert_eq!(merger.flush(), Some(KeyRange::covered(9, 10)));
+    }
+
+    fn set(ranges: &[(u64, u64, bool)]) -> RangeSet {
+        ranges
+            .iter()
+            .map(|&(lower, upper, contained)| KeyRange::new(lower, upper, contained))
+            .collect()
+    }
+
+    #[test]
+    fn test_range_set_normalize() {
+        let normal = set(&[
+            (10, 20, false),
+            (0, 5, true),
+            (15, 16, true),
+            (21, 30, false),
+        ]);
+        assert_eq!(
+            normal.ranges(),
+            &[
+                KeyRange::covered(0, 5),
+                KeyRange::overlapping(10, 14),
+                KeyRange::covered(15, 16),
+                KeyRange::overlapping(17, 30)
+            ]
+        );
+        assert_eq!(normal.get(16), Some(KeyRange::covered(15, 16)));
+        assert!(normal.contains(30));
+        assert!(!normal.contains(7));
+        assert!(set(&[(5, 4, true)]).is_empty());
+        assert_eq!(
+            set(&[(u64::MAX - 1, u64::MAX, true)]).ranges(),
+            &[KeyRange::covered(u64::MAX - 1, u64::MAX)]
+        );
+    }
+
+    #[test]
+    fn test_range_set_algebra() {
+        let a = set(&[(0, 9, true), (20, 29, false)]);
+        let b = set(&[(5, 24, false)]);
+
+        assert_eq!(a.union(&b), set(&[(0, 9, true), (10, 29, false)]));
+        assert_eq!(a.intersection(&b), set(&[(5, 9, false), (20, 24, false)]));
+        assert_eq!(
+            a.difference(&b),
+            set(&[(0, 4, true), (5, 9, false), (20, 29, false)])
+        );
+        assert_eq!(b.difference(&set(&[(0, 9, true)])), set(&[(10, 24, false)]));
+        assert_eq!(
+            a.complement(0, 39),
+            set(&[(10, 19, true), (20, 29, false), (30, 39, true)])
+        );
+        assert_eq!(
+            a.complement(0, 39).complement(0, 39),
+            set(&[(0, 9, true), (20, 29, false)])
+        );
+        assert_eq!(
+            RangeSet::new().complement(0, u64::MAX),
+            set(&[(0, u64::MAX, true)])
+        );
+    }
+
+    #[quickcheck]
+    fn test_range_set_membership(a: Vec<(u16, u16)>, b: Vec<(u16, u16)>, key: u16) -> bool {
+        let to_set = |ranges: Vec<(u16, u16)>| {
+            ranges
+                .into_iter()
+                .map(|(lower, len)| {
+                    KeyRange::new(
+                        lower.into(),
+                        u64::from(lower) + u64::from(len % 64),
+                        len % 2 == 0,
+                    )
+                })
+                .collect::<RangeSet>()
+        };
+        let (a, b, key) = (to_set(a), to_set(b), u64::from(key));
+
+        a.union(&b).contains(key) == (a.contains(key) || b.contains(key))
+            && a.intersection(&b).contains(key) == (a.contains(key) && b.contains(key))
+            && a.ranges().windows(2).all(|w| w[0].upper() < w[1].lower())
+    }
+
+    #[test]
+    fn test_coalesce_ranges() {
+        let ranges = vec![
+            KeyRange::covered(0, 9),
+            KeyRange::covered(12, 19),
+            KeyRange::overlapping(20, 29),
+            KeyRange::covered(40, 49),
+            KeyRange::covered(100, 109),
+        ];
+
+        assert_eq!(coalesce_ranges(ranges.clone(), usize::MAX, 0), ranges);
+        assert_eq!(
+            coalesce_ranges(ranges.clone(), usize::MAX, 10),
+            vec![KeyRange::overlapping(0, 49), KeyRange::covered(100, 109)]
+        );
+        assert_eq!(
+            coalesce_ranges(ranges.clone(), 3, 0),
+            vec![
+                KeyRange::overlapping(0, 29),
+                KeyRange::covered(40, 49),
+                KeyRange::covered(100, 109)
+            ]
+        );
+        assert_eq!(
+            coalesce_ranges(ranges, 1, 0),
+            vec![KeyRange::overlapping(0, 109)]
+        );
+    }
+}
@@ -0,0 +1,345 @@
+//
+// Copyright 2020, Gobsmacked Labs, LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sortable byte keys for key-value stores, in the style of the key spaces of
+//! geomesa's Z2 and Z3 indexes.
+//!
+//! A key is an optional prefix, such as a table or tenant id, an optional shard byte,
+//! an optional big-endian `u16` time bin and the big-endian `u64` curve index. Byte
+//! order of keys matches the order of `(shard, bin, index)`.
+//! ```
+//! use space_time::{keys::KeySpace, zorder::z_curve_2d::ZCurve2D};
+//!
+//! let curve = ZCurve2D::default();
+//! let keys = KeySpace::new().with_prefix(b"poi").with_shards(4);
+//!
+//! let index = curve.index(2.3522, 48.8566);
+//! let key = keys.key(keys.shard(b"eiffel-tower"), None, index);
+//!
+//! let scans = keys.scan_ranges(None, &curve.ranges(2.3, 48.8, 2.4, 48.9, &[]));
+//! assert_eq!(scans.len() % 4, 0);
+//! assert!(scans.iter().any(|s| s.contains(&key)));
+//! ```
+
+use crate::{index_range::IndexRange, zorder::binned_z_3::BinnedRanges};
+use alloc::vec::Vec;
+
+/// The layout of keys for one index.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct KeySpace {
+    prefix: Vec<u8>,
+    shards: u8,
+    binned: bool,
+}
+
+/// A scan over the keys from `start` inclusive to `end` exclusive.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ScanRange {
+    start: Vec<u8>,
+    end: Vec<u8>,
+    contained: bool,
+}
+
+impl ScanRange {
+    /// The first key of the scan.
+    #[must_use]
+    pub fn start(&self) -> &[u8] {
+        &self.start
+    }
+
+    /// The key after the last key of the scan. Empty if the scan has no end.
+    #[must_use]
+    pub fn end(&self) -> &[u8] {
+        &self.end
+    }
+
+    /// Whether every key of the scan matches the query.
+    #[must_use]
+    pub fn contained(&self) -> bool {
+        self.contained
+    }
+
+    /// Test whether `key` is in the scan.
+    #[must_use]
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.start.as_slice() <= key && (self.end.is_empty() || key < self.end.as_slice())
+    }
+}
+
+impl KeySpace {
+    /// A key space with no prefix, shards or time bins, so keys are just the
+    /// big-endian index.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start every key with `prefix`.
+    #[must_use]
+    pub fn with_prefix(mut self, prefix: &[u8]) -> Self {
+        self.prefix = prefix.to_vec();
+        self
+    }
+
+    /// Spread keys over `shards` shards with a leading shard byte. One or zero shards
+    /// leave out the shard byte.
+    #[must_use]
+    pub fn with_shards(mut self, shards: u8) -> Self {
+        self.shards = shards;
+        self
+    }
+
+    /// Add a time bin, e.g. from a `BinnedZCurve3D`, between the shard and the index.
+    #[must_use]
+    pub fn with_bins(mut self) -> Self {
+        self.binned = true;
+        self
+    }
+
+    /// The prefix of every key.
+    #[must_use]
+    pub fn prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+
+    /// The number of shards, at least one.
+    #[must_use]
+    pub fn shards(&self) -> u8 {
+        self.shards.max(1)
+    }
+
+    /// Whether keys have a time bin.
+    #[must_use]
+    pub fn is_binned(&self) -> bool {
+        self.binned
+    }
+
+    /// The length of every key.
+    #[must_use]
+    pub fn key_len(&self) -> usize {
+        self.prefix.len() + usize::from(self.shards > 1) + if self.binned { 2 } else { 0 } + 8
+    }
+
+    /// The shard of a feature id, from a stable FNV-1a hash of the id.
+    #[must_use]
+    pub fn shard(&self, id: &[u8]) -> u8 {
+        let hash = id.iter().fold(0x811c_9dc5_u32, |hash, &b| {
+            (hash ^ u32::from(b)).wrapping_mul(0x0100_0193)
+        });
+        (hash % u32::from(self.shards())) as u8
+    }
+
+    /// The key of `index` in `shard` and time `bin`.
+    ///
+    /// # NOTE:
+    ///   panics if `shard` is not less than the number of shards, or `bin` is given
+    ///   for a key space without bins or missing for one with bins.
+    #[must_use]
+    pub fn key(&self, shard: u8, bin: Option<u16>, index: u64) -> Vec<u8> {
+        let mut key = self.key_prefix(shard, bin);
+        key.extend_from_slice(&index.to_be_bytes());
+        key
+    }
+
+    /// Split a key into its shard, time bin and index. Returns `None` if the key does
+    /// not belong to this key space.
+    #[must_use]
+    pub fn decode(&self, key: &[u8]) -> Option<(u8, Option<u16>, u64)> {
+        if key.len() != self.key_len() || !key.starts_with(&self.prefix) {
+            return None;
+        }
+        let mut rest = &key[self.prefix.len()..];
+
+        let shard = if self.shards > 1 {
+            let shard = rest[0];
+            rest = &rest[1..];
+            if shard >= self.shards {
+                return None;
+            }
+            shard
+        } else {
+            0
+        };
+
+        let bin = if self.binned {
+            let bin = u16::from_be_bytes([rest[0], rest[1]]);
+            rest = &rest[2..];
+            Some(bin)
+        } else {
+            None
+        };
+
+        let mut index = [0_u8; 8];
+        index.copy_from_slice(rest);
+        Some((shard, bin, u64::from_be_bytes(index)))
+    }
+
+    /// The scans over every shard for the index ranges in time `bin`, in key order.
+    ///
+    /// # NOTE:
+    ///   panics if `bin` is given for a key space without bins or missing for one
+    ///   with bins.
+    #[must_use]
+    pub fn scan_ranges<R: IndexRange>(&self, bin: Option<u16>, ranges: &[R]) -> Vec<ScanRange> {
+        let mut scans = Vec::with_capacity(ranges.len() * usize::from(self.shards()));
+        for shard in 0..self.shards() {
+            self.push_scans(shard, bin, ranges, &mut scans);
+        }
+        scans
+    }
+
+    /// The scans over every shard for the ranges of each time bin of a
+    /// `BinnedZCurve3D` query, in key order.
+    ///
+    /// # NOTE:
+    ///   panics if the key space has no bins.
+    #[must_use]
+    pub fn binned_scan_ranges(&self, ranges: &[BinnedRanges]) -> Vec<ScanRange> {
+        let mut scans = Vec::new();
+        for shard in 0..self.shards() {
+            for binned in ranges {
+                for bin in binned.bins.clone() {
+                    self.push_scans(shard, Some(bin), &binned.ranges, &mut scans);
+                }
+            }
+        }
+        scans
+    }
+
+    fn push_scans<R: IndexRange>(
+        &self,
+        shard: u8,
+        bin: Option<u16>,
+        ranges: &[R],
+        scans: &mut Vec<ScanRange>,
+    ) {
+        let prefix = self.key_prefix(shard, bin);
+        scans.extend(ranges.iter().map(|r| {
+            let mut start = prefix.clone();
+            start.extend_from_slice(&r.lower().to_be_bytes());
+            let mut end = prefix.clone();
+            end.extend_from_slice(&r.upper().to_be_bytes());
+
+            ScanRange {
+                start,
+                end: successor(end),
+                contained: r.contained(),
+            }
+        }));
+    }
+
+    fn key_prefix(&self, shard: u8, bin: Option<u16>) -> Vec<u8> {
+        assert!(shard < self.shards());
+        assert_eq!(bin.is_some(), self.binned);
+
+        let mut key = Vec::with_capacity(self.key_len());
+        key.extend_from_slice(&self.prefix);
+        if self.shards > 1 {
+            key.push(shard);
+        }
+        if let Some(bin) = bin {
+            key.extend_from_slice(&bin.to_be_bytes());
+        }
+        key
+    }
+}
+
+/// The first key after every key that starts with `key`. Empty if there is none.
+fn successor(mut key: Vec<u8>) -> Vec<u8> {
+    while let Some(last) = key.pop() {
+        if last < u8::MAX {
+            key.push(last + 1);
+            return key;
+        }
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index_range::KeyRange;
+    use alloc::vec;
+
+    #[test]
+    fn test_keys() {
+        let keys = KeySpace::new()
+            .with_prefix(b"t1")
+            .with_shards(4)
+            .with_bins();
+        assert_eq!(keys.key_len(), 2 + 1 + 2 + 8);
+
+        let key = keys.key(3, Some(2600), 0x0102_0304_0506_0708);
+        assert_eq!(key, vec![b't', b'1', 3, 0x0a, 0x28, 1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(
+            keys.decode(&key),
+            Some((3, Some(2600), 0x0102_0304_0506_0708))
+        );
+        assert_eq!(keys.decode(&key[1..]), None);
+        assert_eq!(KeySpace::new().decode(&key), None);
+
+        assert!(keys.key(0, Some(1), u64::MAX) < keys.key(0, Some(2), 0));
+        assert!(keys.key(0, Some(u16::MAX), u64::MAX) < keys.key(1, Some(0), 0));
+
+        assert_eq!(
+            KeySpace::new().key(0, None, 1),
+            1_u64.to_be_bytes().to_vec()
+        );
+        assert_eq!(keys.shard(b"feature-1"), keys.shard(b"feature-1"));
+        assert!((0..100_u8).all(|i| keys.shard(&[i]) < 4));
+    }
+
+    #[test]
+    fn test_scan_ranges() {
+        let keys = KeySpace::new().with_shards(2);
+        let ranges = [
+            KeyRange::covered(10, 19),
+            KeyRange::overlapping(30, u64::MAX),
+        ];
+        let scans = keys.scan_ranges(None, &ranges);
+
+        assert_eq!(scans.len(), 4);
+        assert!(scans.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(scans[0].start(), keys.key(0, None, 10).as_slice());
+        assert_eq!(scans[0].end(), keys.key(0, None, 20).as_slice());
+        assert!(scans[0].contained());
+        assert_eq!(scans[1].end(), &[1]);
+        assert_eq!(scans[3].end(), &[2]);
+
+        assert!(scans[0].contains(&keys.key(0, None, 19)));
+        assert!(!scans[0].contains(&keys.key(0, None, 20)));
+        assert!(!scans.iter().any(|s| s.contains(&keys.key(1, None, 25))));
+
+        let unbounded = KeySpace::new().scan_ranges(None, &[KeyRange::covered(0, u64::MAX)]);
+        assert!(unbounded[0].end().is_empty());
+        assert!(unbounded[0].contains(&[0xff; 8]));
+    }
+
+    #[test]
+    fn test_binned_scan_ranges() {
+        use crate::{binned_time::TimePeriod, zorder::binned_z_3::BinnedZCurve3D};
+
+        let curve = BinnedZCurve3D::new(1024, -180.0, -90.0, 180.0, 90.0, TimePeriod::Week);
+        let keys = KeySpace::new().with_shards(3).with_bins();
+        let week = 7 * 86_400_000;
+        let ranges = curve.ranges(10.0, 10.0, 11.0, 11.0, 10 * week, 12 * week + 5, &[]);
+        let scans = keys.binned_scan_ranges(&ranges);
+
+        assert!(scans.windows(2).all(|w| w[0] < w[1]));
+        let (bin, index) = curve.index(10.5, 10.5, 11 * week);
+        let key = keys.key(keys.shard(b"id"), Some(bin), index);
+        assert!(scans.iter().any(|s| s.contains(&key)));
+    }
+}
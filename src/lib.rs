@@ -38,13 +38,57 @@
 //! ```
 //! use space_time::SpaceTimeFillingCurves;
 //!
-//! let curve = SpaceTimeFillingCurves::get_point_curve(1024, -180.0, -90.0, 180.0, 90.0, 159753997829.0);
+//! let curve = SpaceTimeFillingCurves::get_point_curve(1024, -180.0, -90.0, 180.0, 90.0, 0.0, 159753997829.0);
 //! let indexed_point_in_time = curve.index(2.3522, 48.8566, 1587583997829.0); // Paris, France. April 22, 2020 as milliseconds since Unix Epoch.
 //! let range_of_index = curve.ranges(2.3522, 48.85, 2.354, 48.857, 1587583997828.0, 1587583997828.0, &[]);
 //!
 //! assert!(range_of_index.iter().any(|r| r.lower() <= indexed_point_in_time && r.upper() >= indexed_point_in_time));
 //! ```
 //!
+//! A binned Z3 curve splits time into periods, like geomesa's Z3 index, and indexes
+//! the offset within each period.
+//! ```
+//! use space_time::{binned_time::TimePeriod, SpaceTimeFillingCurves};
+//!
+//! let curve = SpaceTimeFillingCurves::get_binned_point_curve(
+//!     1024,
+//!     -180.0,
+//!     -90.0,
+//!     180.0,
+//!     90.0,
+//!     TimePeriod::Week,
+//! );
+//! let (bin, indexed_point_in_time) = curve.index(2.3522, 48.8566, 1587583997829);
+//! let ranges_by_bin = curve.ranges(
+//!     2.35,
+//!     48.85,
+//!     2.354,
+//!     48.857,
+//!     1587583997828,
+//!     1587583997830,
+//!     &[],
+//! );
+//!
+//! assert!(ranges_by_bin.iter().any(|b| b.bins.contains(&bin)
+//!     && b.ranges
+//!         .iter()
+//!         .any(|r| r.lower() <= indexed_point_in_time && r.upper() >= indexed_point_in_time)));
+//! ```
+//!
+//! Hilbert curves index the same points with better locality, so a bounding box
+//! usually decomposes into fewer ranges.
+//! ```
+//! use space_time::hilbert::hilbert_2d::HilbertCurve2D;
+//!
+//! let curve = HilbertCurve2D::new(1024, -180.0, -90.0, 180.0, 90.0);
+//! let indexed_point = curve.index(2.3522, 48.8566);
+//! let range_of_index = curve.ranges(2.35, 48.85, 2.354, 48.857, &[]);
+//!
+//! assert!(range_of_index
+//!     .iter()
+//!     .any(|r| r.lower() <= indexed_point && r.upper() >= indexed_point));
+//! ```
+//!
 //! Extended Z-order curves are used for non-points.
 //! `XZ2SFC` for spatial indexing of non-points.
 //! ```
@@ -52,7 +96,7 @@
 //!
 //! let curve = SpaceFillingCurves::get_non_point_curve(12, -180.0, -90.0, 180.0, 90.0);
 //! let indexed_polygon = curve.index(2.3522, 48.8466, 2.39, 49.9325);
-//! let range_of_index = curve.ranges(2.0, 48.0, 3.0, 50.0, None);
+//! let range_of_index = curve.ranges(2.0, 48.0, 3.0, 50.0, &[]);
 //!
 //! assert!(range_of_index
 //!     .iter()
@@ -82,14 +126,25 @@
 //!     1_556_496_000.0,
 //! );
 //!
-//! let range_of_index = curve.ranges(2.0, 48.0, 1_556_300_000.0, 3.0, 50.0, 1_557_496_000.0, None);
+//! let range_of_index = curve.ranges(2.0, 48.0, 1_556_300_000.0, 3.0, 50.0, 1_557_496_000.0, &[]);
 //!
 //! assert!(range_of_index
 //!     .iter()
 //!     .any(|r| r.lower() <= indexed_polygon && r.upper() >= indexed_polygon));
 //! ```
 
+pub mod binned_time;
+pub mod bounds;
+pub mod curve;
+pub mod error;
+pub mod geohash;
+pub mod geometry;
+pub mod hilbert;
 pub mod index_range;
+pub mod keys;
+pub mod projection;
+pub mod spatial_index;
+pub mod tiles;
 pub mod xzorder;
 pub mod zorder;
 
@@ -102,8 +157,33 @@ extern crate quickcheck_macros;
 
 extern crate alloc;
 
+pub use curve::{
+    SpaceFillingCurve2D, SpaceFillingCurve2DExtent, SpaceTimeFillingCurve,
+    SpaceTimeFillingCurveExtent,
+};
+
+use alloc::{boxed::Box, vec::Vec};
+use binned_time::TimePeriod;
+use hilbert::{hilbert_2d::HilbertCurve2D, hilbert_3d::HilbertCurve3D};
+use index_range::{coalesce_ranges, KeyRange};
 use xzorder::{xz2_sfc::XZ2SFC, xz3_sfc::XZ3SFC};
-use zorder::{z_3::ZCurve3D, z_curve_2d::ZCurve2D};
+use zorder::{binned_z_3::BinnedZCurve3D, z_3::ZCurve3D, z_curve_2d::ZCurve2D};
+
+/// The kind of point curve the factories return as a trait object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointCurveKind {
+    /// A Z-order curve, `ZCurve2D` or `ZCurve3D`.
+    ZOrder,
+    /// A Hilbert curve, `HilbertCurve2D` or `HilbertCurve3D`.
+    Hilbert,
+}
+
+/// The kind of non-point curve the factories return as a trait object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NonPointCurveKind {
+    /// An extended Z-order curve, `XZ2SFC` or `XZ3SFC`.
+    XZ,
+}
 
 /// Factory providing space filling curves
 pub struct SpaceFillingCurves;
@@ -132,13 +212,51 @@ impl SpaceFillingCurves {
     ) -> XZ2SFC {
         XZ2SFC::new(resolution, x_min, y_min, x_max, y_max)
     }
+
+    /// Return a point indexing curve of `kind`, so the curve can be chosen by
+    /// configuration.
+    #[must_use]
+    pub fn get_boxed_point_curve(
+        kind: PointCurveKind,
+        resolution: u32,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+    ) -> Box<dyn SpaceFillingCurve2D> {
+        match kind {
+            PointCurveKind::ZOrder => Box::new(Self::get_point_curve(
+                resolution, x_min, y_min, x_max, y_max,
+            )),
+            PointCurveKind::Hilbert => {
+                Box::new(HilbertCurve2D::new(resolution, x_min, y_min, x_max, y_max))
+            }
+        }
+    }
+
+    /// Return a non-point indexing curve of `kind`.
+    #[must_use]
+    pub fn get_boxed_non_point_curve(
+        kind: NonPointCurveKind,
+        resolution: u32,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+    ) -> Box<dyn SpaceFillingCurve2DExtent> {
+        match kind {
+            NonPointCurveKind::XZ => Box::new(Self::get_non_point_curve(
+                resolution, x_min, y_min, x_max, y_max,
+            )),
+        }
+    }
 }
 
 /// Factory providing space-time filling curves
 pub struct SpaceTimeFillingCurves;
 
 impl SpaceTimeFillingCurves {
-    /// Return point-time indexing curve.
+    /// Return point-time indexing curve for times from `t_min` to `t_max`.
     #[must_use]
     pub fn get_point_curve(
         resolution: u32,
@@ -146,9 +264,23 @@ impl SpaceTimeFillingCurves {
         y_min: f64,
         x_max: f64,
         y_max: f64,
+        t_min: f64,
         t_max: f64,
     ) -> ZCurve3D {
-        ZCurve3D::new(resolution, x_min, y_min, x_max, y_max, t_max)
+        ZCurve3D::new(resolution, x_min, y_min, x_max, y_max, t_min, t_max)
+    }
+
+    /// Return point-time indexing curve with time split into bins of `period`.
+    #[must_use]
+    pub fn get_binned_point_curve(
+        resolution: u32,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        period: TimePeriod,
+    ) -> BinnedZCurve3D {
+        BinnedZCurve3D::new(resolution, x_min, y_min, x_max, y_max, period)
     }
 
     /// Return a nonpoint space-time indexing curve.
@@ -164,10 +296,125 @@ impl SpaceTimeFillingCurves {
     ) -> XZ3SFC {
         XZ3SFC::new(resolution, x_min, y_min, z_min, x_max, y_max, z_max)
     }
+
+    /// Return a point-time indexing curve of `kind` for times from `t_min` to
+    /// `t_max`.
+    ///
+    /// # NOTE:
+    ///   panics if `kind` is `PointCurveKind::Hilbert` and `t_min` is not 0, as
+    ///   `HilbertCurve3D` times start at 0.
+    #[must_use]
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_boxed_point_curve(
+        kind: PointCurveKind,
+        resolution: u32,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        t_min: f64,
+        t_max: f64,
+    ) -> Box<dyn SpaceTimeFillingCurve> {
+        match kind {
+            PointCurveKind::ZOrder => Box::new(Self::get_point_curve(
+                resolution, x_min, y_min, x_max, y_max, t_min, t_max,
+            )),
+            PointCurveKind::Hilbert => {
+                assert!(t_min == 0.0);
+                Box::new(HilbertCurve3D::new(
+                    resolution, x_min, y_min, x_max, y_max, t_max,
+                ))
+            }
+        }
+    }
+
+    /// Return a nonpoint space-time indexing curve of `kind`.
+    #[must_use]
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_boxed_non_point_curve(
+        kind: NonPointCurveKind,
+        resolution: u32,
+        x_min: f64,
+        y_min: f64,
+        z_min: f64,
+        x_max: f64,
+        y_max: f64,
+        z_max: f64,
+    ) -> Box<dyn SpaceTimeFillingCurveExtent> {
+        match kind {
+            NonPointCurveKind::XZ => Box::new(Self::get_non_point_curve(
+                resolution, x_min, y_min, z_min, x_max, y_max, z_max,
+            )),
+        }
+    }
 }
 
-/// Hints to the `range` function implementation for `SpacefillingCurve2D`s.
+/// Hints to the `range` function implementations of the curves. If a hint is given
+/// more than once, the first one is used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RangeComputeHints {
     /// Number of times to recurse.
     MaxRecurse(usize),
+    /// Return at most this many ranges. Refinement stops early once about this many
+    /// ranges are found, then the ranges separated by the smallest gaps are merged.
+    MaxRanges(usize),
+    /// Number of leading bits of a Z-index to refine; cells below this precision
+    /// that overlap the query are returned whole. XZ curves refine `bits /
+    /// dimensions` levels.
+    Precision(u64),
+    /// Merge ranges separated by at most this many keys. Merged ranges are not
+    /// `contained`.
+    GapTolerance(u64),
+}
+
+/// `RangeComputeHints` collected into one value.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RangeHints {
+    pub(crate) max_recurse: Option<usize>,
+    pub(crate) max_ranges: Option<usize>,
+    pub(crate) precision: u64,
+    pub(crate) gap_tolerance: u64,
+}
+
+impl RangeHints {
+    pub(crate) fn new(hints: &[RangeComputeHints]) -> Self {
+        RangeHints {
+            max_recurse: hints.iter().find_map(|h| match *h {
+                RangeComputeHints::MaxRecurse(max) => Some(max),
+                _ => None,
+            }),
+            max_ranges: hints.iter().find_map(|h| match *h {
+                RangeComputeHints::MaxRanges(max) => Some(max.max(1)),
+                _ => None,
+            }),
+            precision: hints
+                .iter()
+                .find_map(|h| match *h {
+                    RangeComputeHints::Precision(bits) => Some(bits.min(64)),
+                    _ => None,
+                })
+                .unwrap_or(64),
+            gap_tolerance: hints
+                .iter()
+                .find_map(|h| match *h {
+                    RangeComputeHints::GapTolerance(gap) => Some(gap),
+                    _ => None,
+                })
+                .unwrap_or(0),
+        }
+    }
+
+    /// The maximum recursion, capped at `limit`.
+    pub(crate) fn max_recurse(&self, limit: usize) -> Option<usize> {
+        self.max_recurse.map(|max| max.min(limit))
+    }
+
+    /// Apply `MaxRanges` and `GapTolerance` to sorted, disjoint ranges.
+    pub(crate) fn coalesce(&self, ranges: Vec<KeyRange>) -> Vec<KeyRange> {
+        coalesce_ranges(
+            ranges,
+            self.max_ranges.unwrap_or(usize::MAX),
+            self.gap_tolerance,
+        )
+    }
 }
@@ -0,0 +1,164 @@
+//
+// Copyright 2020, Gobsmacked Labs, LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Map projections that curves apply to x and y before normalizing them onto their
+//! grid.
+//!
+//! Curves keep their bounds, and take and return coordinates, in unprojected space,
+//! e.g. longitude and latitude. Projections are cylindrical: projected x only
+//! depends on x and projected y only on y, and both increase with the unprojected
+//! value, so every bounding box projects to a bounding box.
+//! ```
+//! use space_time::{projection::EqualArea, zorder::z_curve_2d::ZCurve2D};
+//!
+//! // Cells cover equal areas, so polar cells are taller in latitude.
+//! let curve = ZCurve2D::new(1024, -180.0, -90.0, 180.0, 90.0).with_projection(EqualArea);
+//! let (_, y_min, _, y_max) = curve.cell_bounds(curve.index(0.0, 89.9));
+//! assert!(y_max - y_min > 180.0 / 1024.0);
+//! ```
+
+#[allow(unused_imports)]
+use num_traits::Float;
+
+use core::f64::consts::FRAC_PI_4;
+
+/// The WGS84 semi-major axis, in meters.
+const EARTH_RADIUS: f64 = 6_378_137.0;
+
+/// The latitude where Web Mercator is square, `atan(sinh(pi))` in degrees.
+pub const MAX_MERCATOR_LATITUDE: f64 = 85.051_128_779_806_59;
+
+/// A cylindrical projection of x and y.
+pub trait Projection {
+    /// Project an x coordinate.
+    fn project_x(&self, x: f64) -> f64;
+
+    /// Project a y coordinate.
+    fn project_y(&self, y: f64) -> f64;
+
+    /// Invert `project_x`.
+    fn unproject_x(&self, x: f64) -> f64;
+
+    /// Invert `project_y`.
+    fn unproject_y(&self, y: f64) -> f64;
+}
+
+/// Coordinates are used as given.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Identity;
+
+impl Projection for Identity {
+    fn project_x(&self, x: f64) -> f64 {
+        x
+    }
+
+    fn project_y(&self, y: f64) -> f64 {
+        y
+    }
+
+    fn unproject_x(&self, x: f64) -> f64 {
+        x
+    }
+
+    fn unproject_y(&self, y: f64) -> f64 {
+        y
+    }
+}
+
+/// Web Mercator (EPSG:3857) from longitude and latitude to meters. Latitudes are
+/// clamped to `MAX_MERCATOR_LATITUDE`, so the poles project to the edges of the map.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WebMercator;
+
+impl Projection for WebMercator {
+    fn project_x(&self, x: f64) -> f64 {
+        EARTH_RADIUS * x.to_radians()
+    }
+
+    fn project_y(&self, y: f64) -> f64 {
+        let y = y
+            .clamp(-MAX_MERCATOR_LATITUDE, MAX_MERCATOR_LATITUDE)
+            .to_radians();
+        EARTH_RADIUS * (FRAC_PI_4 + y / 2.0).tan().ln()
+    }
+
+    fn unproject_x(&self, x: f64) -> f64 {
+        (x / EARTH_RADIUS).to_degrees()
+    }
+
+    fn unproject_y(&self, y: f64) -> f64 {
+        (y / EARTH_RADIUS).sinh().atan().to_degrees()
+    }
+}
+
+/// Lambert cylindrical equal-area from longitude and latitude to meters, so cells of
+/// a curve cover the same area at every latitude.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EqualArea;
+
+impl Projection for EqualArea {
+    fn project_x(&self, x: f64) -> f64 {
+        EARTH_RADIUS * x.to_radians()
+    }
+
+    fn project_y(&self, y: f64) -> f64 {
+        EARTH_RADIUS * y.clamp(-90.0, 90.0).to_radians().sin()
+    }
+
+    fn unproject_x(&self, x: f64) -> f64 {
+        (x / EARTH_RADIUS).to_degrees()
+    }
+
+    fn unproject_y(&self, y: f64) -> f64 {
+        (y / EARTH_RADIUS).clamp(-1.0, 1.0).asin().to_degrees()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tiles::WEB_MERCATOR_EXTENT;
+
+    #[test]
+    fn test_web_mercator() {
+        let p = WebMercator;
+        assert!((p.project_x(180.0) - WEB_MERCATOR_EXTENT).abs() < 1e-6);
+        assert!((p.project_y(MAX_MERCATOR_LATITUDE) - WEB_MERCATOR_EXTENT).abs() < 1e-3);
+        assert_eq!(p.project_y(90.0), p.project_y(MAX_MERCATOR_LATITUDE));
+        assert!((p.project_y(-90.0) + WEB_MERCATOR_EXTENT).abs() < 1e-3);
+        assert!(p.project_y(0.0).abs() < 1e-9);
+        assert!((p.unproject_y(p.project_y(48.8566)) - 48.8566).abs() < 1e-9);
+        assert!((p.unproject_x(p.project_x(-75.1234)) + 75.1234).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_equal_area() {
+        let p = EqualArea;
+        assert!((p.project_y(90.0) - EARTH_RADIUS).abs() < 1e-6);
+        assert!((p.unproject_y(p.project_y(-33.8688)) + 33.8688).abs() < 1e-9);
+        assert_eq!(p.unproject_y(2.0 * EARTH_RADIUS), 90.0);
+
+        // Equal bands of projected y are equal areas, narrower in latitude near the
+        // equator than near the poles.
+        let band = EARTH_RADIUS / 4.0;
+        assert!(p.unproject_y(band) < 90.0 - p.unproject_y(3.0 * band));
+    }
+
+    #[test]
+    fn test_identity() {
+        assert_eq!(Identity.project_x(-200.0), -200.0);
+        assert_eq!(Identity.unproject_y(95.0), 95.0);
+    }
+}
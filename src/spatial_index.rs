@@ -0,0 +1,719 @@
+//
+// Copyright 2020, Gobsmacked Labs, LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! In-memory point indexes that keep entries sorted by the index of a curve.
+//!
+//! Queries compute the curve's ranges, read the entries in each range and keep only
+//! those whose exact coordinates match, so the results do not depend on the
+//! resolution of the curve. Any `SpaceFillingCurve2D` or `SpaceTimeFillingCurve`
+//! can order the entries, distance queries need one with longitude and latitude
+//! bounds.
+//! ```
+//! use space_time::{spatial_index::PointIndex, zorder::z_curve_2d::ZCurve2D};
+//!
+//! let index = PointIndex::bulk_load(
+//!     ZCurve2D::new(1 << 16, -180.0, -90.0, 180.0, 90.0),
+//!     vec![
+//!         ("Paris", 2.3522, 48.8566, 2_161_000),
+//!         ("London", -0.1276, 51.5072, 8_982_000),
+//!         ("Berlin", 13.405, 52.52, 3_645_000),
+//!     ],
+//! );
+//!
+//! let near_paris = index.query_within_distance(2.3522, 48.8566, 500_000.0);
+//! let mut names = near_paris.iter().map(|e| e.key).collect::<Vec<_>>();
+//! names.sort_unstable();
+//! assert_eq!(names, vec!["London", "Paris"]);
+//!
+//! let in_box = index.query_box(10.0, 50.0, 15.0, 55.0);
+//! assert_eq!(in_box.len(), 1);
+//! assert_eq!(in_box[0].value, 3_645_000);
+//! ```
+
+use crate::{
+    curve::{SpaceFillingCurve2D, SpaceTimeFillingCurve},
+    error::{check_geographic, Error},
+    geometry::Circle,
+    index_range::{KeyRange, RangeSet},
+};
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+
+/// An entry of a `PointIndex`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry<K, V> {
+    /// The key of the entry.
+    pub key: K,
+    /// The x coordinate.
+    pub x: f64,
+    /// The y coordinate.
+    pub y: f64,
+    /// The value of the entry.
+    pub value: V,
+}
+
+/// An entry of a `PointTimeIndex`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeEntry<K, V> {
+    /// The key of the entry.
+    pub key: K,
+    /// The x coordinate.
+    pub x: f64,
+    /// The y coordinate.
+    pub y: f64,
+    /// The time.
+    pub t: f64,
+    /// The value of the entry.
+    pub value: V,
+}
+
+/// Points sorted by the index of a two dimensional curve.
+///
+/// An entry is found by its key and coordinates, so the same key may be stored at
+/// more than one point. NaN coordinates match each other, so an entry inserted at NaN
+/// can be removed, but no query returns it.
+pub struct PointIndex<C, K, V> {
+    curve: C,
+    entries: BTreeMap<u64, Vec<Entry<K, V>>>,
+    len: usize,
+}
+
+impl<C: SpaceFillingCurve2D, K: PartialEq, V> PointIndex<C, K, V> {
+    /// An empty index ordered by `curve`.
+    #[must_use]
+    pub fn new(curve: C) -> Self {
+        PointIndex {
+            curve,
+            entries: BTreeMap::new(),
+            len: 0,
+        }
+    }
+
+    /// An index of the `(key, x, y, value)` entries, in any order. A later entry
+    /// replaces an earlier one with the same key and coordinates.
+    #[must_use]
+    pub fn bulk_load<I>(curve: C, entries: I) -> Self
+    where
+        I: IntoIterator<Item = (K, f64, f64, V)>,
+    {
+        let mut keyed = entries
+            .into_iter()
+            .map(|(key, x, y, value)| (curve.index(x, y), Entry { key, x, y, value }))
+            .collect::<Vec<_>>();
+        keyed.sort_by_key(|&(index, _)| index);
+
+        let mut len = 0;
+        let entries = group_sorted(
+            keyed,
+            |a, b| a.key == b.key && same(a.x, b.x) && same(a.y, b.y),
+            &mut len,
+        );
+        PointIndex {
+            curve,
+            entries,
+            len,
+        }
+    }
+
+    /// The curve that orders the entries.
+    #[must_use]
+    pub fn curve(&self) -> &C {
+        &self.curve
+    }
+
+    /// The number of entries.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether there are no entries.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Every entry, in the order of the curve.
+    pub fn iter(&self) -> impl Iterator<Item = &Entry<K, V>> {
+        self.entries.values().flatten()
+    }
+
+    /// Insert an entry, returning the value it replaces if the key is already stored
+    /// at the same coordinates.
+    pub fn insert(&mut self, key: K, x: f64, y: f64, value: V) -> Option<V> {
+        let bucket = self.entries.entry(self.curve.index(x, y)).or_default();
+        match bucket
+            .iter_mut()
+            .find(|e| e.key == key && same(e.x, x) && same(e.y, y))
+        {
+            Some(entry) => Some(core::mem::replace(&mut entry.value, value)),
+            None => {
+                bucket.push(Entry { key, x, y, value });
+                self.len += 1;
+                None
+            }
+        }
+    }
+
+    /// Remove the entry with the key at the coordinates, returning its value.
+    pub fn remove(&mut self, key: &K, x: f64, y: f64) -> Option<V> {
+        let index = self.curve.index(x, y);
+        let bucket = self.entries.get_mut(&index)?;
+        let position = bucket
+            .iter()
+            .position(|e| &e.key == key && same(e.x, x) && same(e.y, y))?;
+        let entry = bucket.remove(position);
+        if bucket.is_empty() {
+            self.entries.remove(&index);
+        }
+        self.len -= 1;
+        Some(entry.value)
+    }
+
+    /// The entries in the bounding box, in the order of the curve. A box with
+    /// `x_min > x_max` wraps across the x bounds.
+    #[must_use]
+    pub fn query_box(&self, x_min: f64, y_min: f64, x_max: f64, y_max: f64) -> Vec<&Entry<K, V>> {
+        self.query_boxes(&[(x_min, y_min, x_max, y_max)])
+    }
+
+    /// The entries in any of the `(x_min, y_min, x_max, y_max)` bounding boxes, in the
+    /// order of the curve.
+    #[must_use]
+    pub fn query_boxes(&self, boxes: &[(f64, f64, f64, f64)]) -> Vec<&Entry<K, V>> {
+        let ranges = boxes
+            .iter()
+            .flat_map(|&(x_min, y_min, x_max, y_max)| {
+                self.curve.ranges(x_min, y_min, x_max, y_max, &[])
+            })
+            .collect::<RangeSet>();
+
+        scan(&self.entries, ranges.ranges(), |e| {
+            boxes.iter().any(|&(x_min, y_min, x_max, y_max)| {
+                box_contains(x_min, y_min, x_max, y_max, e.x, e.y)
+            })
+        })
+    }
+
+    /// The entries within `meters` of a point, in the order of the curve, with x as
+    /// longitude and y as latitude.
+    ///
+    /// # NOTE:
+    ///   panics if the curve bounds are not longitude and latitude, or if any
+    ///   argument is not finite or `meters` is negative.
+    #[must_use]
+    pub fn query_within_distance(&self, lon: f64, lat: f64, meters: f64) -> Vec<&Entry<K, V>> {
+        let (x_min, y_min, x_max, y_max) = self.curve.bounds();
+        assert!(
+            check_geographic(x_min, y_min, x_max, y_max).is_ok(),
+            "Distance queries need longitude and latitude bounds"
+        );
+        self.query_within_circle(&Circle::new(lon, lat, meters))
+    }
+
+    /// The entries within `meters` of a point, returning `Error::InvalidBounds` if
+    /// the curve bounds are not longitude and latitude, and the errors of
+    /// `Circle::try_new`.
+    pub fn try_query_within_distance(
+        &self,
+        lon: f64,
+        lat: f64,
+        meters: f64,
+    ) -> Result<Vec<&Entry<K, V>>, Error> {
+        let (x_min, y_min, x_max, y_max) = self.curve.bounds();
+        check_geographic(x_min, y_min, x_max, y_max)?;
+        let circle = Circle::try_new(lon, lat, meters)?;
+        Ok(self.query_within_circle(&circle))
+    }
+
+    fn query_within_circle(&self, circle: &Circle) -> Vec<&Entry<K, V>> {
+        let (x_min, y_min, x_max, y_max) = circle.bounding_box();
+        let ranges = self.curve.ranges(x_min, y_min, x_max, y_max, &[]);
+
+        scan(&self.entries, &ranges, |e| circle.contains(e.x, e.y))
+    }
+}
+
+/// Points in time sorted by the index of a space-time curve.
+///
+/// An entry is found by its key, coordinates and time, so the same key may be stored
+/// at more than one point or time. NaN coordinates and times match each other.
+pub struct PointTimeIndex<C, K, V> {
+    curve: C,
+    entries: BTreeMap<u64, Vec<TimeEntry<K, V>>>,
+    len: usize,
+}
+
+impl<C: SpaceTimeFillingCurve, K: PartialEq, V> PointTimeIndex<C, K, V> {
+    /// An empty index ordered by `curve`.
+    #[must_use]
+    pub fn new(curve: C) -> Self {
+        PointTimeIndex {
+            curve,
+            entries: BTreeMap::new(),
+            len: 0,
+        }
+    }
+
+    /// An index of the `(key, x, y, t, value)` entries, in any order. A later entry
+    /// replaces an earlier one with the same key, coordinates and time.
+    #[must_use]
+    pub fn bulk_load<I>(curve: C, entries: I) -> Self
+    where
+        I: IntoIterator<Item = (K, f64, f64, f64, V)>,
+    {
+        let mut keyed = entries
+            .into_iter()
+            .map(|(key, x, y, t, value)| {
+                (
+                    curve.index(x, y, t),
+                    TimeEntry {
+                        key,
+                        x,
+                        y,
+                        t,
+                        value,
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+        keyed.sort_by_key(|&(index, _)| index);
+
+        let mut len = 0;
+        let entries = group_sorted(
+            keyed,
+            |a, b| a.key == b.key && same(a.x, b.x) && same(a.y, b.y) && same(a.t, b.t),
+            &mut len,
+        );
+        PointTimeIndex {
+            curve,
+            entries,
+            len,
+        }
+    }
+
+    /// The curve that orders the entries.
+    #[must_use]
+    pub fn curve(&self) -> &C {
+        &self.curve
+    }
+
+    /// The number of entries.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether there are no entries.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Every entry, in the order of the curve.
+    pub fn iter(&self) -> impl Iterator<Item = &TimeEntry<K, V>> {
+        self.entries.values().flatten()
+    }
+
+    /// Insert an entry, returning the value it replaces if the key is already stored
+    /// at the same coordinates and time.
+    pub fn insert(&mut self, key: K, x: f64, y: f64, t: f64, value: V) -> Option<V> {
+        let bucket = self.entries.entry(self.curve.index(x, y, t)).or_default();
+        match bucket
+            .iter_mut()
+            .find(|e| e.key == key && same(e.x, x) && same(e.y, y) && same(e.t, t))
+        {
+            Some(entry) => Some(core::mem::replace(&mut entry.value, value)),
+            None => {
+                bucket.push(TimeEntry {
+                    key,
+                    x,
+                    y,
+                    t,
+                    value,
+                });
+                self.len += 1;
+                None
+            }
+        }
+    }
+
+    /// Remove the entry with the key at the coordinates and time, returning its value.
+    pub fn remove(&mut self, key: &K, x: f64, y: f64, t: f64) -> Option<V> {
+        let index = self.curve.index(x, y, t);
+        let bucket = self.entries.get_mut(&index)?;
+        let position = bucket
+            .iter()
+            .position(|e| &e.key == key && same(e.x, x) && same(e.y, y) && same(e.t, t))?;
+        let entry = bucket.remove(position);
+        if bucket.is_empty() {
+            self.entries.remove(&index);
+        }
+        self.len -= 1;
+        Some(entry.value)
+    }
+
+    /// The entries in the bounding box and time range, in the order of the curve. A
+    /// box with `x_min > x_max` wraps across the x bounds.
+    #[must_use]
+    #[allow(clippy::too_many_arguments)]
+    pub fn query_box(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        t_min: f64,
+        t_max: f64,
+    ) -> Vec<&TimeEntry<K, V>> {
+        self.query_boxes(&[(x_min, y_min, x_max, y_max, t_min, t_max)])
+    }
+
+    /// The entries in any of the `(x_min, y_min, x_max, y_max, t_min, t_max)` boxes,
+    /// in the order of the curve.
+    #[must_use]
+    pub fn query_boxes(&self, boxes: &[(f64, f64, f64, f64, f64, f64)]) -> Vec<&TimeEntry<K, V>> {
+        let ranges = boxes
+            .iter()
+            .flat_map(|&(x_min, y_min, x_max, y_max, t_min, t_max)| {
+                self.curve
+                    .ranges(x_min, y_min, x_max, y_max, t_min, t_max, &[])
+            })
+            .collect::<RangeSet>();
+
+        scan(&self.entries, ranges.ranges(), |e| {
+            boxes
+                .iter()
+                .any(|&(x_min, y_min, x_max, y_max, t_min, t_max)| {
+                    box_contains(x_min, y_min, x_max, y_max, e.x, e.y)
+                        && t_min <= e.t
+                        && e.t <= t_max
+                })
+        })
+    }
+
+    /// The entries within `meters` of a point during the time range, in the order of
+    /// the curve, with x as longitude and y as latitude.
+    ///
+    /// # NOTE:
+    ///   panics if the curve bounds are not longitude and latitude, or if any
+    ///   argument is not finite or `meters` is negative.
+    #[must_use]
+    pub fn query_within_distance(
+        &self,
+        lon: f64,
+        lat: f64,
+        meters: f64,
+        t_min: f64,
+        t_max: f64,
+    ) -> Vec<&TimeEntry<K, V>> {
+        let (x_min, y_min, x_max, y_max) = self.curve.bounds();
+        assert!(
+            check_geographic(x_min, y_min, x_max, y_max).is_ok(),
+            "Distance queries need longitude and latitude bounds"
+        );
+        self.query_within_circle(&Circle::new(lon, lat, meters), t_min, t_max)
+    }
+
+    /// The entries within `meters` of a point during the time range, returning
+    /// `Error::InvalidBounds` if the curve bounds are not longitude and latitude, and
+    /// the errors of `Circle::try_new`.
+    pub fn try_query_within_distance(
+        &self,
+        lon: f64,
+        lat: f64,
+        meters: f64,
+        t_min: f64,
+        t_max: f64,
+    ) -> Result<Vec<&TimeEntry<K, V>>, Error> {
+        let (x_min, y_min, x_max, y_max) = self.curve.bounds();
+        check_geographic(x_min, y_min, x_max, y_max)?;
+        let circle = Circle::try_new(lon, lat, meters)?;
+        Ok(self.query_within_circle(&circle, t_min, t_max))
+    }
+
+    fn query_within_circle(
+        &self,
+        circle: &Circle,
+        t_min: f64,
+        t_max: f64,
+    ) -> Vec<&TimeEntry<K, V>> {
+        let (x_min, y_min, x_max, y_max) = circle.bounding_box();
+        let ranges = self
+            .curve
+            .ranges(x_min, y_min, x_max, y_max, t_min, t_max, &[]);
+
+        scan(&self.entries, &ranges, |e| {
+            circle.contains(e.x, e.y) && t_min <= e.t && e.t <= t_max
+        })
+    }
+}
+
+/// Group entries sorted by index into buckets, dropping an entry that `same` finds
+/// replaced by a later one, and count the entries that are kept.
+fn group_sorted<E>(
+    keyed: Vec<(u64, E)>,
+    same: impl Fn(&E, &E) -> bool,
+    len: &mut usize,
+) -> BTreeMap<u64, Vec<E>> {
+    let mut buckets: Vec<(u64, Vec<E>)> = Vec::new();
+    for (index, entry) in keyed {
+        match buckets.last_mut() {
+            Some((last, bucket)) if *last == index => {
+                match bucket.iter_mut().find(|e| same(e, &entry)) {
+                    Some(existing) => *existing = entry,
+                    None => {
+                        bucket.push(entry);
+                        *len += 1;
+                    }
+                }
+            }
+            _ => {
+                buckets.push((index, vec![entry]));
+                *len += 1;
+            }
+        }
+    }
+    buckets.into_iter().collect()
+}
+
+/// The entries in sorted, disjoint ranges that pass `filter`.
+fn scan<'a, E>(
+    entries: &'a BTreeMap<u64, Vec<E>>,
+    ranges: &[KeyRange],
+    filter: impl Fn(&E) -> bool,
+) -> Vec<&'a E> {
+    ranges
+        .iter()
+        .flat_map(|r| entries.range(r.lower()..=r.upper()))
+        .flat_map(|(_, bucket)| bucket.iter())
+        .filter(|e| filter(e))
+        .collect()
+}
+
+/// Test whether two coordinates of entries are the same, with NaN the same as NaN.
+fn same(a: f64, b: f64) -> bool {
+    a == b || (a.is_nan() && b.is_nan())
+}
+
+/// Test whether a box, which wraps across the x bounds if `x_min > x_max`, contains
+/// a point.
+fn box_contains(x_min: f64, y_min: f64, x_max: f64, y_max: f64, x: f64, y: f64) -> bool {
+    let in_x = if x_min > x_max {
+        x >= x_min || x <= x_max
+    } else {
+        x_min <= x && x <= x_max
+    };
+    in_x && y_min <= y && y <= y_max
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hilbert::hilbert_2d::HilbertCurve2D,
+        zorder::{z_3::ZCurve3D, z_curve_2d::ZCurve2D},
+    };
+
+    fn sorted_keys<'a, I: IntoIterator<Item = &'a &'static str>>(keys: I) -> Vec<&'static str> {
+        let mut keys = keys.into_iter().copied().collect::<Vec<_>>();
+        keys.sort_unstable();
+        keys
+    }
+
+    /// Entries in the cell of `ZCurve2D::new(4, ..)` from (0, 0) to (90, 45), with a
+    /// repeated key and point.
+    fn one_cell() -> Vec<(&'static str, f64, f64, u32)> {
+        vec![
+            ("a", 10.0, 10.0, 1),
+            ("b", 10.0, 10.0, 2),
+            ("a", 10.0, 10.0, 3),
+            ("c", 80.0, 40.0, 4),
+            ("d", 45.0, 20.0, 5),
+        ]
+    }
+
+    /// Entries on the corners and edges of the curve bounds.
+    fn on_bounds() -> Vec<(&'static str, f64, f64, u32)> {
+        vec![
+            ("sw", -180.0, -90.0, 1),
+            ("ne", 180.0, 90.0, 2),
+            ("w", -180.0, 0.0, 3),
+            ("e", 180.0, 0.0, 4),
+            ("n", 0.0, 90.0, 5),
+            ("s", 0.0, -90.0, 6),
+        ]
+    }
+
+    #[test]
+    fn test_one_cell() {
+        let curve = ZCurve2D::new(4, -180.0, -90.0, 180.0, 90.0);
+        let cell = curve.index(10.0, 10.0);
+        assert!(one_cell()
+            .iter()
+            .all(|&(_, x, y, _)| curve.index(x, y) == cell));
+
+        let mut index = PointIndex::bulk_load(curve, one_cell());
+        assert_eq!(index.len(), 4);
+        assert_eq!(index.entries.len(), 1);
+
+        let found = index.query_box(0.0, 0.0, 20.0, 20.0);
+        assert_eq!(sorted_keys(found.iter().map(|e| &e.key)), vec!["a", "b"]);
+        assert_eq!(found.iter().map(|e| e.value).sum::<u32>(), 5);
+        let found = index.query_box(40.0, 15.0, 50.0, 25.0);
+        assert_eq!(sorted_keys(found.iter().map(|e| &e.key)), vec!["d"]);
+        assert!(index.query_box(20.0, 20.0, 30.0, 30.0).is_empty());
+
+        assert_eq!(index.remove(&"c", 10.0, 10.0), None);
+        assert_eq!(index.remove(&"c", 80.0, 40.0), Some(4));
+        assert_eq!(index.remove(&"a", 10.0, 10.0), Some(3));
+        assert_eq!(index.remove(&"b", 10.0, 10.0), Some(2));
+        assert_eq!(index.remove(&"d", 45.0, 20.0), Some(5));
+        assert!(index.is_empty());
+        assert!(index.entries.is_empty());
+    }
+
+    #[test]
+    fn test_on_bounds() {
+        let index = PointIndex::bulk_load(
+            ZCurve2D::new(1 << 12, -180.0, -90.0, 180.0, 90.0),
+            on_bounds(),
+        );
+        assert_eq!(index.len(), 6);
+
+        assert_eq!(index.query_box(-180.0, -90.0, 180.0, 90.0).len(), 6);
+        let found = index.query_box(179.0, -1.0, 180.0, 1.0);
+        assert_eq!(sorted_keys(found.iter().map(|e| &e.key)), vec!["e"]);
+        let found = index.query_box(179.0, -1.0, -179.0, 1.0);
+        assert_eq!(sorted_keys(found.iter().map(|e| &e.key)), vec!["e", "w"]);
+        let found = index.query_boxes(&[(-1.0, 89.0, 1.0, 90.0), (179.0, 89.0, 180.0, 90.0)]);
+        assert_eq!(sorted_keys(found.iter().map(|e| &e.key)), vec!["n", "ne"]);
+
+        let found = index.query_within_distance(180.0, 0.0, 1_000.0);
+        assert_eq!(sorted_keys(found.iter().map(|e| &e.key)), vec!["e", "w"]);
+        let found = index.query_within_distance(0.0, -89.0, 200_000.0);
+        assert_eq!(sorted_keys(found.iter().map(|e| &e.key)), vec!["s", "sw"]);
+    }
+
+    #[test]
+    fn test_distance_needs_geographic_bounds() {
+        let index = PointIndex::bulk_load(
+            ZCurve2D::new(1 << 12, -180.0, -90.0, 180.0, 90.0),
+            on_bounds(),
+        );
+        assert_eq!(
+            index.try_query_within_distance(180.0, 0.0, 1_000.0),
+            Ok(index.query_within_distance(180.0, 0.0, 1_000.0))
+        );
+        assert_eq!(
+            index.try_query_within_distance(180.0, 0.0, -1.0),
+            Err(Error::OutOfBounds)
+        );
+
+        let meters = PointIndex::bulk_load(ZCurve2D::web_mercator(12), one_cell());
+        assert_eq!(
+            meters.try_query_within_distance(10.0, 10.0, 1_000.0),
+            Err(Error::InvalidBounds)
+        );
+
+        let meters = PointTimeIndex::<_, &str, u32>::new(ZCurve3D::new(
+            4, 0.0, 0.0, 1_000.0, 1_000.0, 0.0, 100.0,
+        ));
+        assert_eq!(
+            meters.try_query_within_distance(10.0, 10.0, 1_000.0, 0.0, 100.0),
+            Err(Error::InvalidBounds)
+        );
+    }
+
+    #[test]
+    fn test_insert_and_remove() {
+        let mut index = PointIndex::new(HilbertCurve2D::default());
+        assert!(index.is_empty());
+        assert_eq!(index.insert("a", 10.0, 10.0, 1), None);
+        assert_eq!(index.insert("b", 10.0, 10.0, 2), None);
+        assert_eq!(index.insert("a", 10.0, 10.0, 3), Some(1));
+        assert_eq!(index.insert("a", 20.0, 20.0, 4), None);
+        assert_eq!(index.len(), 3);
+
+        assert_eq!(index.query_box(9.0, 9.0, 11.0, 11.0).len(), 2);
+        assert_eq!(index.remove(&"a", 10.0, 10.0), Some(3));
+        assert_eq!(index.remove(&"a", 10.0, 10.0), None);
+        assert_eq!(index.remove(&"c", 20.0, 20.0), None);
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.iter().count(), 2);
+
+        let reloaded = PointIndex::bulk_load(
+            HilbertCurve2D::default(),
+            vec![("a", 1.0, 1.0, 1), ("a", 1.0, 1.0, 2), ("b", 1.0, 1.0, 3)],
+        );
+        assert_eq!(reloaded.len(), 2);
+        assert_eq!(reloaded.query_box(0.0, 0.0, 2.0, 2.0).len(), 2);
+    }
+
+    #[test]
+    fn test_nan_coordinates() {
+        let mut index = PointIndex::new(ZCurve2D::default());
+        assert_eq!(index.insert("a", f64::NAN, 10.0, 1), None);
+        assert_eq!(index.insert("a", f64::NAN, 10.0, 2), Some(1));
+        assert_eq!(index.insert("b", 10.0, f64::NAN, 3), None);
+        assert_eq!(index.len(), 2);
+        assert!(index.query_box(-180.0, -90.0, 180.0, 90.0).is_empty());
+
+        assert_eq!(index.remove(&"a", f64::NAN, 10.0), Some(2));
+        assert_eq!(index.remove(&"b", 10.0, f64::NAN), Some(3));
+        assert!(index.is_empty());
+
+        let reloaded = PointIndex::bulk_load(
+            ZCurve2D::default(),
+            vec![("a", f64::NAN, 1.0, 1), ("a", f64::NAN, 1.0, 2)],
+        );
+        assert_eq!(reloaded.len(), 1);
+        assert_eq!(reloaded.iter().next().map(|e| e.value), Some(2));
+    }
+
+    #[test]
+    fn test_point_time_index() {
+        let curve = ZCurve3D::new(4, -180.0, -90.0, 180.0, 90.0, 0.0, 100.0);
+        let mut index = PointTimeIndex::bulk_load(
+            curve,
+            vec![
+                ("a", 10.0, 10.0, 30.0, 1),
+                ("a", 10.0, 10.0, 31.0, 2),
+                ("b", 10.0, 10.0, 30.0, 3),
+                ("b", 10.0, 10.0, 30.0, 4),
+                ("start", -180.0, -90.0, 0.0, 5),
+                ("end", 180.0, 90.0, 100.0, 6),
+            ],
+        );
+        assert_eq!(index.len(), 5);
+        let cell = index.curve().index(10.0, 10.0, 30.0);
+        assert_eq!(index.curve().index(10.0, 10.0, 31.0), cell);
+
+        let found = index.query_box(0.0, 0.0, 20.0, 20.0, 30.0, 30.0);
+        assert_eq!(sorted_keys(found.iter().map(|e| &e.key)), vec!["a", "b"]);
+        assert_eq!(found.iter().map(|e| e.value).sum::<u32>(), 5);
+        let found = index.query_box(-180.0, -90.0, 180.0, 90.0, 0.0, 0.0);
+        assert_eq!(sorted_keys(found.iter().map(|e| &e.key)), vec!["start"]);
+        let found = index.query_box(170.0, 80.0, 180.0, 90.0, 99.0, 100.0);
+        assert_eq!(sorted_keys(found.iter().map(|e| &e.key)), vec!["end"]);
+
+        let found = index.query_within_distance(10.0, 10.0, 1_000.0, 31.0, 40.0);
+        assert_eq!(found.iter().map(|e| e.value).collect::<Vec<_>>(), vec![2]);
+
+        assert_eq!(index.insert("a", 10.0, 10.0, f64::NAN, 7), None);
+        assert_eq!(index.remove(&"a", 10.0, 10.0, f64::NAN), Some(7));
+        assert_eq!(index.remove(&"a", 10.0, 10.0, 30.0), Some(1));
+        assert_eq!(index.len(), 4);
+    }
+}
@@ -0,0 +1,203 @@
+//
+// Copyright 2020, Gobsmacked Labs, LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Web Mercator `(zoom, x, y)` map tiles and Bing quadkeys.
+//!
+//! Tiles count x from the west and y from the north, like the rows of a `ZCurve2D`,
+//! so on a `ZCurve2D::web_mercator` curve every tile is a Z-index prefix and covers
+//! one range of indexes.
+//! ```
+//! use space_time::{tiles::Tile, zorder::z_curve_2d::ZCurve2D};
+//!
+//! let tile = Tile::from_quadkey("213").unwrap();
+//! assert_eq!(tile, Tile::new(3, 3, 5));
+//!
+//! let curve = ZCurve2D::web_mercator(16);
+//! let range = curve.tile_range(tile).unwrap();
+//! let index = curve.index(-2_500_000.0, -7_500_000.0);
+//! assert!(range.lower() <= index && index <= range.upper());
+//! assert_eq!(curve.tile(index, 3), Ok(tile));
+//! ```
+
+use crate::{
+    error::Error,
+    zorder::{z_2::Z2, z_n::ZN},
+};
+use alloc::{string::String, vec::Vec};
+
+/// Half the width of the Web Mercator (EPSG:3857) square, in meters.
+pub const WEB_MERCATOR_EXTENT: f64 = 20_037_508.342_789_244;
+
+/// A Web Mercator map tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Tile {
+    /// The zoom level, 0 for a single tile.
+    pub zoom: u32,
+    /// The column, from the west.
+    pub x: u32,
+    /// The row, from the north.
+    pub y: u32,
+}
+
+impl Tile {
+    /// Constructor.
+    ///
+    /// # NOTE:
+    ///   panics if `zoom` is more than `Z2::BITS_PER_DIMENSION` or `x` or `y` is not
+    ///   less than `2^zoom`.
+    #[must_use]
+    pub fn new(zoom: u32, x: u32, y: u32) -> Self {
+        assert!(zoom <= Z2::BITS_PER_DIMENSION);
+        assert!(u64::from(x) < 1 << zoom && u64::from(y) < 1 << zoom);
+        Tile { zoom, x, y }
+    }
+
+    /// Fallible constructor, returning `Error::InvalidResolution` if `zoom` is more
+    /// than `Z2::BITS_PER_DIMENSION` and `Error::OutOfBounds` if `x` or `y` is not
+    /// less than `2^zoom`.
+    pub fn try_new(zoom: u32, x: u32, y: u32) -> Result<Self, Error> {
+        if zoom > Z2::BITS_PER_DIMENSION {
+            return Err(Error::InvalidResolution);
+        }
+        if u64::from(x) >= 1 << zoom || u64::from(y) >= 1 << zoom {
+            return Err(Error::OutOfBounds);
+        }
+        Ok(Self::new(zoom, x, y))
+    }
+
+    /// Parse a quadkey, one digit from 0 to 3 per zoom level.
+    pub fn from_quadkey(quadkey: &str) -> Result<Self, Error> {
+        if quadkey.len() > Z2::BITS_PER_DIMENSION as usize {
+            return Err(Error::InvalidQuadkey);
+        }
+        let (mut x, mut y) = (0, 0);
+        for digit in quadkey.bytes() {
+            let digit = match digit {
+                b'0'..=b'3' => u32::from(digit - b'0'),
+                _ => return Err(Error::InvalidQuadkey),
+            };
+            x = (x << 1) | (digit & 1);
+            y = (y << 1) | (digit >> 1);
+        }
+        Ok(Self::new(quadkey.len() as u32, x, y))
+    }
+
+    /// The quadkey of the tile.
+    #[must_use]
+    pub fn quadkey(&self) -> String {
+        (1..=self.zoom)
+            .rev()
+            .map(|i| {
+                let digit = ((self.x >> (i - 1)) & 1) | (((self.y >> (i - 1)) & 1) << 1);
+                char::from(b'0' + digit as u8)
+            })
+            .collect()
+    }
+
+    /// The Z-index of the tile among the tiles of its zoom level. Quadkeys are this
+    /// index in base 4.
+    #[must_use]
+    pub fn z(&self) -> u64 {
+        Z2::new(self.x, self.y).z()
+    }
+
+    /// The tile one zoom level out. Returns `None` at zoom 0.
+    #[must_use]
+    pub fn parent(&self) -> Option<Self> {
+        match self.zoom {
+            0 => None,
+            zoom => Some(Tile::new(zoom - 1, self.x >> 1, self.y >> 1)),
+        }
+    }
+
+    /// The four tiles one zoom level in, in quadkey order.
+    ///
+    /// # NOTE:
+    ///   panics if the tile is at zoom `Z2::BITS_PER_DIMENSION`.
+    #[must_use]
+    pub fn children(&self) -> Vec<Self> {
+        (0..4)
+            .map(|digit| {
+                Tile::new(
+                    self.zoom + 1,
+                    (self.x << 1) | (digit & 1),
+                    (self.y << 1) | (digit >> 1),
+                )
+            })
+            .collect()
+    }
+
+    /// The `(x_min, y_min, x_max, y_max)` bounds of the tile in Web Mercator meters.
+    #[must_use]
+    pub fn bounds(&self) -> (f64, f64, f64, f64) {
+        let size = 2.0 * WEB_MERCATOR_EXTENT / (1_u64 << self.zoom) as f64;
+        let x_min = -WEB_MERCATOR_EXTENT + f64::from(self.x) * size;
+        let y_max = WEB_MERCATOR_EXTENT - f64::from(self.y) * size;
+        (x_min, y_max - size, x_min + size, y_max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quadkey() {
+        let tile = Tile::new(3, 3, 5);
+        assert_eq!(tile.quadkey(), "213");
+        assert_eq!(Tile::from_quadkey("213"), Ok(tile));
+        assert_eq!(tile.z(), 0b10_01_11);
+        assert_eq!(Tile::new(0, 0, 0).quadkey(), "");
+        assert_eq!(Tile::from_quadkey(""), Ok(Tile::new(0, 0, 0)));
+        assert_eq!(Tile::from_quadkey("214"), Err(Error::InvalidQuadkey));
+        assert_eq!(Tile::try_new(3, 8, 0), Err(Error::OutOfBounds));
+        assert_eq!(Tile::try_new(32, 0, 0), Err(Error::InvalidResolution));
+    }
+
+    #[test]
+    fn test_hierarchy() {
+        let tile = Tile::new(3, 3, 5);
+        assert_eq!(tile.parent(), Some(Tile::new(2, 1, 2)));
+        assert_eq!(Tile::new(0, 0, 0).parent(), None);
+
+        let children = tile.children();
+        assert_eq!(children.len(), 4);
+        assert!(children.iter().all(|c| c.parent() == Some(tile)));
+        assert!(children
+            .iter()
+            .map(Tile::quadkey)
+            .eq(["2130", "2131", "2132", "2133"].iter().copied()));
+
+        assert_eq!(
+            Tile::new(0, 0, 0).bounds(),
+            (
+                -WEB_MERCATOR_EXTENT,
+                -WEB_MERCATOR_EXTENT,
+                WEB_MERCATOR_EXTENT,
+                WEB_MERCATOR_EXTENT
+            )
+        );
+        assert_eq!(
+            Tile::new(1, 1, 0).bounds(),
+            (0.0, 0.0, WEB_MERCATOR_EXTENT, WEB_MERCATOR_EXTENT)
+        );
+    }
+
+    #[quickcheck]
+    fn test_quadkey_and_back(x: u32, y: u32) -> bool {
+        let tile = Tile::new(18, x % (1 << 18), y % (1 << 18));
+        Tile::from_quadkey(&tile.quadkey()) == Ok(tile)
+    }
+}
@@ -15,8 +15,17 @@
 
 //! SpaceFillingCurve for storing non-point features based on a bounding box.
 
-use crate::index_range::{CoveredRange, IndexRange, OverlappingRange};
-use alloc::{boxed::Box, collections::VecDeque, vec, vec::Vec};
+use crate::{
+    bounds::{cover_x_interval, split_x_interval, BoundsPolicy},
+    curve::SpaceFillingCurve2DExtent,
+    error::{check_bounds, check_geographic, Error},
+    geometry::{Circle, Polygon, Relation},
+    index_range::{merge_ranges, next_batch, next_range, Budget, KeyRange, RangeMerger, RangeWalk},
+    projection::{Identity, Projection},
+    RangeComputeHints, RangeHints,
+};
+use alloc::{collections::VecDeque, vec, vec::Vec};
+use core::convert::TryFrom;
 use num_integer::div_floor;
 #[allow(unused_imports)]
 use num_traits::Float;
@@ -26,23 +35,21 @@ use num_traits::Float;
 /// Based on [geomesa-z3 scala implementation](https://github.com/locationtech/geomesa/blob/771777d3a9716b04f7dcd27a6b7d1bb822a1b5a7/geomesa-z3/src/main/scala/org/locationtech/geomesa/curve/XZ2SFC.scala)
 /// which is based on 'XZ-Ordering: A Space Filling Curve for Objects
 /// with Spatial Extension' by Christian Bohm, Gerald Klump, and Hans-Peter Kriegel
-pub struct XZ2SFC {
+///
+/// Bounding boxes are normalized in the space of the `Projection`, by default in x
+/// and y.
+#[derive(Clone)]
+pub struct XZ2SFC<P = Identity> {
     g: u32,
     x_min: f64,
     x_max: f64,
     y_min: f64,
     y_max: f64,
+    policy: BoundsPolicy,
+    projection: P,
 }
 
 impl XZ2SFC {
-    fn x_size(&self) -> f64 {
-        self.x_max - self.x_min
-    }
-
-    fn y_size(&self) -> f64 {
-        self.y_max - self.y_min
-    }
-
     /// Return an `XZ2SFC`.
     #[must_use]
     pub fn new(g: u32, x_min: f64, y_min: f64, x_max: f64, y_max: f64) -> Self {
@@ -52,9 +59,26 @@ impl XZ2SFC {
             x_max,
             y_min,
             y_max,
+            policy: BoundsPolicy::default(),
+            projection: Identity,
         }
     }
 
+    /// The largest `g` that can be indexed in 64 bits.
+    pub const MAX_G: u32 = 31;
+
+    /// Fallible constructor, checking that `g` is between 1 and `XZ2SFC::MAX_G` and
+    /// that the bounds are finite and not empty.
+    pub fn try_new(g: u32, x_min: f64, y_min: f64, x_max: f64, y_max: f64) -> Result<Self, Error> {
+        if g == 0 || g > Self::MAX_G {
+            return Err(Error::InvalidResolution);
+        }
+        check_bounds(x_min, x_max)?;
+        check_bounds(y_min, y_max)?;
+
+        Ok(Self::new(g, x_min, y_min, x_max, y_max))
+    }
+
     /// An `XZ2SFC` for unprojected coordinates.
     #[must_use]
     pub fn wgs84(g: u32) -> Self {
@@ -64,12 +88,90 @@ impl XZ2SFC {
             x_max: 180.0,
             y_min: -90.0,
             y_max: 90.0,
+            policy: BoundsPolicy::default(),
+            projection: Identity,
+        }
+    }
+}
+
+impl<P: Projection> XZ2SFC<P> {
+    fn x_size(&self) -> f64 {
+        self.projection.project_x(self.x_max) - self.projection.project_x(self.x_min)
+    }
+
+    fn y_size(&self) -> f64 {
+        self.projection.project_y(self.y_max) - self.projection.project_y(self.y_min)
+    }
+
+    /// The x at normalized `x`, from 0 at `x_min` to 1 at `x_max` in projected space.
+    /// From 1 on the value is at least `x_max`, even where the projection clamps.
+    fn denormalize_x(&self, x: f64) -> f64 {
+        let p = &self.projection;
+        let value = p.unproject_x(p.project_x(self.x_min) + x * self.x_size());
+        if x >= 1.0 {
+            value.max(self.x_max)
+        } else {
+            value
         }
     }
 
-    /// Return the index for a bounding box.
+    /// The y at normalized `y`, as for `denormalize_x`.
+    fn denormalize_y(&self, y: f64) -> f64 {
+        let p = &self.projection;
+        let value = p.unproject_y(p.project_y(self.y_min) + y * self.y_size());
+        if y >= 1.0 {
+            value.max(self.y_max)
+        } else {
+            value
+        }
+    }
+
+    /// Normalize bounding boxes in the space of `projection`. The bounds of the curve
+    /// stay in unprojected coordinates.
+    #[must_use]
+    pub fn with_projection<Q: Projection>(self, projection: Q) -> XZ2SFC<Q> {
+        XZ2SFC {
+            g: self.g,
+            x_min: self.x_min,
+            x_max: self.x_max,
+            y_min: self.y_min,
+            y_max: self.y_max,
+            policy: self.policy,
+            projection,
+        }
+    }
+
+    /// The projection applied before normalizing bounding boxes.
+    #[must_use]
+    pub fn projection(&self) -> &P {
+        &self.projection
+    }
+
+    /// Set how `index` and `ranges` treat bounding boxes outside of the curve
+    /// bounds. A box that wraps across the x bounds is indexed across the full x
+    /// extent.
+    #[must_use]
+    pub fn with_bounds_policy(mut self, policy: BoundsPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// How coordinates outside of the curve bounds are treated.
+    #[must_use]
+    pub fn bounds_policy(&self) -> BoundsPolicy {
+        self.policy
+    }
+
+    /// Return the index for a bounding box. A box with `xmin > xmax` wraps across the
+    /// x bounds and is indexed across the full x extent.
+    ///
+    /// # NOTE:
+    ///   panics if the bounding box is inverted in y or outside of the curve bounds
+    ///   and not clamped or wrapped by the `BoundsPolicy`.
     #[must_use]
     pub fn index(&self, xmin: f64, ymin: f64, xmax: f64, ymax: f64) -> u64 {
+        let (xmin, ymin, xmax, ymax) = self.constrain_box(xmin, ymin, xmax, ymax);
+        let (xmin, xmax) = cover_x_interval(xmin, xmax, self.x_min, self.x_max);
         let (nxmin, nymin, nxmax, nymax) = self.normalize(xmin, ymin, xmax, ymax);
 
         let max_dim = (nxmax - nxmin).max(nymax - nymin);
@@ -92,36 +194,323 @@ impl XZ2SFC {
         self.sequence_code(nxmin, nymin, length)
     }
 
+    /// Return the index for a bounding box, returning an error if the box is inverted
+    /// in y, not finite or outside of the curve bounds and rejected by the
+    /// `BoundsPolicy`.
+    pub fn try_index(&self, xmin: f64, ymin: f64, xmax: f64, ymax: f64) -> Result<u64, Error> {
+        let (xmin, ymin, xmax, ymax) = self.check_box(xmin, ymin, xmax, ymax)?;
+
+        Ok(self.index(xmin, ymin, xmax, ymax))
+    }
+
+    /// Return the element of an index, inverting `index`. Returns `None` if the index
+    /// is past the last element of the curve.
+    #[must_use]
+    pub fn element(&self, index: u64) -> Option<XZ2Element> {
+        let mut xmin = 0.0;
+        let mut ymin = 0.0;
+        let mut size = 1.0;
+
+        let mut cs = index;
+        let mut level = 0_u32;
+
+        while cs > 0 {
+            if level == self.g {
+                return None;
+            }
+            let subtree = div_floor(4_u64.pow(self.g - level) - 1, 3);
+            cs -= 1;
+            let quadrant = cs / subtree;
+            if quadrant > 3 {
+                return None;
+            }
+            cs %= subtree;
+
+            size /= 2.0;
+            if quadrant & 1 == 1 {
+                xmin += size;
+            }
+            if quadrant & 2 == 2 {
+                ymin += size;
+            }
+            level += 1;
+        }
+
+        Some(XZ2Element {
+            level,
+            x_min: self.denormalize_x(xmin),
+            y_min: self.denormalize_y(ymin),
+            x_max: self.denormalize_x(xmin + 2.0 * size),
+            y_max: self.denormalize_y(ymin + 2.0 * size),
+        })
+    }
+
+    fn constrain_box(&self, xmin: f64, ymin: f64, xmax: f64, ymax: f64) -> (f64, f64, f64, f64) {
+        let (xmin, xmax) = self
+            .policy
+            .constrain_x_interval(xmin, xmax, self.x_min, self.x_max);
+        let (ymin, ymax) = self
+            .policy
+            .constrain_interval(ymin, ymax, self.y_min, self.y_max);
+        (xmin, ymin, xmax, ymax)
+    }
+
+    fn check_box(
+        &self,
+        xmin: f64,
+        ymin: f64,
+        xmax: f64,
+        ymax: f64,
+    ) -> Result<(f64, f64, f64, f64), Error> {
+        let (xmin, xmax) = self
+            .policy
+            .check_x_interval(xmin, xmax, self.x_min, self.x_max)?;
+        let (ymin, ymax) = self
+            .policy
+            .check_interval(ymin, ymax, self.y_min, self.y_max)?;
+        Ok((xmin, ymin, xmax, ymax))
+    }
+
     fn predicate(min: f64, max: f64, w2: f64) -> bool {
         max <= (min / w2).floor() * w2 + 2.0 * w2
     }
 
-    /// Compute that index ranges that are contained or overlap the bounding box.
+    /// Compute that index ranges that are contained or overlap the bounding box. A box
+    /// with `xmin > xmax` wraps across the x bounds, e.g. a window over the
+    /// antimeridian.
+    ///
+    /// # NOTE:
+    ///   panics if the bounding box is inverted in y or outside of the curve bounds and
+    ///   not clamped or wrapped by the `BoundsPolicy`.
     pub fn ranges(
         &self,
         xmin: f64,
         ymin: f64,
         xmax: f64,
         ymax: f64,
-        max_ranges: Option<u16>,
-    ) -> Vec<Box<dyn IndexRange>> {
-        let windows = {
-            let (nxmin, nymin, nxmax, nymax) = self.normalize(xmin, ymin, xmax, ymax);
-            &[QueryWindow {
-                xmin: nxmin,
-                ymin: nymin,
-                xmax: nxmax,
-                ymax: nymax,
-            }]
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        self.ranges_for_boxes(&[(xmin, ymin, xmax, ymax)], hints)
+    }
+
+    /// Compute the index ranges that are contained or overlap the bounding box,
+    /// returning an error if the box is inverted in y, not finite or outside of the curve
+    /// bounds and rejected by the `BoundsPolicy`.
+    pub fn try_ranges(
+        &self,
+        xmin: f64,
+        ymin: f64,
+        xmax: f64,
+        ymax: f64,
+        hints: &[RangeComputeHints],
+    ) -> Result<Vec<KeyRange>, Error> {
+        self.try_ranges_for_boxes(&[(xmin, ymin, xmax, ymax)], hints)
+    }
+
+    /// Compute one merged list of index ranges that are contained or overlap any of
+    /// the `(xmin, ymin, xmax, ymax)` bounding boxes.
+    ///
+    /// # NOTE:
+    ///   panics if a bounding box is inverted in y or outside of the curve bounds and
+    ///   not clamped or wrapped by the `BoundsPolicy`.
+    pub fn ranges_for_boxes(
+        &self,
+        boxes: &[(f64, f64, f64, f64)],
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        let windows = self.windows(boxes);
+
+        if windows.is_empty() {
+            return Vec::new();
+        }
+
+        self.ranges_impl(&windows, &RangeHints::new(hints))
+    }
+
+    /// Compute one merged list of index ranges that are contained or overlap any of
+    /// the bounding boxes, returning an error if a box is inverted in y, not finite or
+    /// outside of the curve bounds and rejected by the `BoundsPolicy`.
+    pub fn try_ranges_for_boxes(
+        &self,
+        boxes: &[(f64, f64, f64, f64)],
+        hints: &[RangeComputeHints],
+    ) -> Result<Vec<KeyRange>, Error> {
+        let boxes = boxes
+            .iter()
+            .map(|&(xmin, ymin, xmax, ymax)| self.check_box(xmin, ymin, xmax, ymax))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(self.ranges_for_boxes(&boxes, hints))
+    }
+
+    /// Get a resumable cursor over the index ranges that are contained or overlap any
+    /// of the `(xmin, ymin, xmax, ymax)` bounding boxes, in ascending order. Collecting
+    /// the cursor gives the same ranges as `ranges_for_boxes`. Only the `MaxRecurse`
+    /// and `Precision` hints apply, page with a `Budget` instead of `MaxRanges`.
+    ///
+    /// # NOTE:
+    ///   panics if a bounding box is inverted in y or outside of the curve bounds and
+    ///   not clamped or wrapped by the `BoundsPolicy`.
+    #[must_use]
+    pub fn range_cursor(
+        &self,
+        boxes: &[(f64, f64, f64, f64)],
+        hints: &[RangeComputeHints],
+    ) -> XZ2RangeCursor<P>
+    where
+        P: Clone,
+    {
+        let windows = self.windows(boxes);
+        let stack = if windows.is_empty() {
+            Vec::new()
+        } else {
+            XElement::level_one_elements()
+                .into_iter()
+                .rev()
+                .map(|el| (el, 1))
+                .collect()
+        };
+
+        XZ2RangeCursor {
+            walk: XZ2Walk {
+                curve: self.clone(),
+                max_level: self.max_level(&RangeHints::new(hints)),
+                windows,
+                stack,
+            },
+            merger: RangeMerger::new(true),
+        }
+    }
+
+    /// Compute the index ranges of the bounding boxes that are inside or overlap the
+    /// polygon. Only ranges of elements whose extent lies entirely inside the polygon
+    /// are `contained`. Parts of the polygon outside of the curve bounds are ignored.
+    pub fn ranges_for_polygon(
+        &self,
+        polygon: &Polygon,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        let (x_min, y_min, x_max, y_max) = polygon.bounding_box();
+        if x_max < self.x_min || x_min > self.x_max || y_max < self.y_min || y_min > self.y_max {
+            return Vec::new();
+        }
+
+        let classify = |quad: XElement| {
+            polygon.relate(
+                self.denormalize_x(quad.xmin),
+                self.denormalize_y(quad.ymin),
+                self.denormalize_x(quad.xext()),
+                self.denormalize_y(quad.yext()),
+            )
+        };
+
+        self.decompose(&classify, &RangeHints::new(hints), true)
+    }
+
+    /// Compute the index ranges of the bounding boxes that come within `meters` of a
+    /// point, with x as longitude and y as latitude. The circle may cross the
+    /// antimeridian or cover a pole. Only ranges of elements whose extent lies
+    /// entirely inside the circle are `contained`.
+    ///
+    /// # NOTE:
+    ///   panics if the curve bounds are not longitude and latitude, or if any
+    ///   argument is not finite or `meters` is negative.
+    pub fn ranges_within_distance(
+        &self,
+        lon: f64,
+        lat: f64,
+        meters: f64,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        assert!(
+            check_geographic(self.x_min, self.y_min, self.x_max, self.y_max).is_ok(),
+            "Distance queries need longitude and latitude bounds"
+        );
+        self.ranges_within_circle(&Circle::new(lon, lat, meters), hints)
+    }
+
+    /// Compute the index ranges of the bounding boxes that come within `meters` of a
+    /// point, returning `Error::InvalidBounds` if the curve bounds are not longitude
+    /// and latitude, and the errors of `Circle::try_new`.
+    pub fn try_ranges_within_distance(
+        &self,
+        lon: f64,
+        lat: f64,
+        meters: f64,
+        hints: &[RangeComputeHints],
+    ) -> Result<Vec<KeyRange>, Error> {
+        check_geographic(self.x_min, self.y_min, self.x_max, self.y_max)?;
+        let circle = Circle::try_new(lon, lat, meters)?;
+        Ok(self.ranges_within_circle(&circle, hints))
+    }
+
+    fn ranges_within_circle(&self, circle: &Circle, hints: &[RangeComputeHints]) -> Vec<KeyRange> {
+        let (x_min, y_min, x_max, y_max) = circle.bounding_box();
+        let (x_min, x_max) = cover_x_interval(x_min, x_max, self.x_min, self.x_max);
+        if x_max < self.x_min || x_min > self.x_max || y_max < self.y_min || y_min > self.y_max {
+            return Vec::new();
+        }
+
+        let classify = |quad: XElement| {
+            circle.relate(
+                self.denormalize_x(quad.xmin),
+                self.denormalize_y(quad.ymin),
+                self.denormalize_x(quad.xext()),
+                self.denormalize_y(quad.yext()),
+            )
         };
 
-        let range_stop = max_ranges.unwrap_or(u16::MAX);
+        self.decompose(&classify, &RangeHints::new(hints), true)
+    }
 
-        self.ranges_impl(windows, range_stop)
+    fn windows(&self, boxes: &[(f64, f64, f64, f64)]) -> Vec<QueryWindow> {
+        boxes
+            .iter()
+            .flat_map(|&(xmin, ymin, xmax, ymax)| {
+                let (xmin, ymin, xmax, ymax) = self.constrain_box(xmin, ymin, xmax, ymax);
+                split_x_interval(xmin, xmax, self.x_min, self.x_max).map(move |(xmin, xmax)| {
+                    let (nxmin, nymin, nxmax, nymax) = self.normalize(xmin, ymin, xmax, ymax);
+                    QueryWindow {
+                        xmin: nxmin,
+                        ymin: nymin,
+                        xmax: nxmax,
+                        ymax: nymax,
+                    }
+                })
+            })
+            .collect()
     }
 
-    fn ranges_impl(&self, query: &[QueryWindow], range_stop: u16) -> Vec<Box<dyn IndexRange>> {
-        let mut ranges: Vec<Box<dyn IndexRange>> = Vec::with_capacity(100);
+    fn ranges_impl(&self, query: &[QueryWindow], hints: &RangeHints) -> Vec<KeyRange> {
+        let classify = |quad| {
+            if Self::is_contained(quad, query) {
+                Relation::Inside
+            } else if Self::is_overlapped(quad, query) {
+                Relation::Crossing
+            } else {
+                Relation::Outside
+            }
+        };
+
+        self.decompose(&classify, hints, false)
+    }
+
+    /// Recurse the elements that `classify` finds inside or crossing the query.
+    ///
+    /// If `exact` is set the interval of a subtree ends at its last element and ranges
+    /// of different containment are not merged, so `contained` ranges hold only
+    /// elements inside the query. Otherwise intervals end one past the subtree, as in
+    /// geomesa.
+    fn decompose(
+        &self,
+        classify: &dyn Fn(XElement) -> Relation,
+        hints: &RangeHints,
+        exact: bool,
+    ) -> Vec<KeyRange> {
+        let max_level = self.max_level(hints);
+        let range_stop = hints.max_ranges.unwrap_or(usize::MAX);
+
+        let mut ranges: Vec<KeyRange> = Vec::with_capacity(100);
 
         let mut remaining: VecDeque<Option<XElement>> = VecDeque::with_capacity(100);
 
@@ -132,7 +521,8 @@ impl XZ2SFC {
 
         let mut level: u32 = 1;
 
-        while level < self.g && !remaining.is_empty() && ranges.len() < range_stop.into() {
+        while level < max_level && !remaining.is_empty() && ranges.len() < range_stop {
+            #[allow(clippy::collapsible_match)]
             match remaining.pop_front() {
                 Some(LEVEL_TERMINATOR) => {
                     if !remaining.is_empty() {
@@ -141,7 +531,7 @@ impl XZ2SFC {
                     }
                 }
                 Some(element) => {
-                    self.check_value(element, level, query, &mut ranges, &mut remaining)
+                    self.check_value(element, level, classify, exact, &mut ranges, &mut remaining)
                 }
                 _ => (),
             }
@@ -149,43 +539,24 @@ impl XZ2SFC {
 
         while let Some(quad) = remaining.pop_front() {
             if let Some(quad) = quad {
-                let (min, max) = self.sequence_interval(quad.xmin, quad.ymin, level, false);
-                ranges.push(Box::new(OverlappingRange::new(min, max)));
+                let (min, max) = self.subtree_interval(quad, level, exact);
+                ranges.push(KeyRange::overlapping(min, max));
             } else {
                 level += 1;
             }
         }
 
-        ranges.sort();
-
-        let mut current: Option<Box<dyn IndexRange>> = None;
-
-        let mut results = vec![];
-
-        for range in ranges {
-            if let Some(cur) = current {
-                if range.lower() <= cur.upper() + 1 {
-                    let max = cur.upper().max(range.upper());
-                    let min = cur.lower();
-                    if cur.contained() && range.contained() {
-                        current = Some(Box::new(CoveredRange::new(min, max)));
-                    } else {
-                        current = Some(Box::new(OverlappingRange::new(min, max)));
-                    }
-                } else {
-                    results.push(cur);
-                    current = Some(range);
-                }
-            } else {
-                current = Some(range);
-            }
-        }
+        hints.coalesce(merge_ranges(ranges, !exact))
+    }
 
-        if let Some(current) = current {
-            results.push(current);
-        }
+    /// The level at which refinement stops.
+    fn max_level(&self, hints: &RangeHints) -> u32 {
+        let precision_level = u32::try_from(hints.precision / 2).unwrap_or(u32::MAX);
+        let recurse_level = hints
+            .max_recurse
+            .map_or(u32::MAX, |max| u32::try_from(max).unwrap_or(u32::MAX));
 
-        results
+        self.g.min(precision_level).min(recurse_level).max(1)
     }
 
     fn sequence_code(&self, x: f64, y: f64, length: u32) -> u64 {
@@ -230,20 +601,25 @@ impl XZ2SFC {
         &self,
         quad: Option<XElement>,
         level: u32,
-        query: &[QueryWindow],
-        ranges: &mut Vec<Box<dyn IndexRange>>,
+        classify: &dyn Fn(XElement) -> Relation,
+        exact: bool,
+        ranges: &mut Vec<KeyRange>,
         remaining: &mut VecDeque<Option<XElement>>,
     ) {
         if let Some(quad) = quad {
-            if Self::is_contained(quad, query) {
-                let (min, max) = self.sequence_interval(quad.xmin, quad.ymin, level, false);
-                ranges.push(Box::new(CoveredRange::new(min, max)));
-            } else if Self::is_overlapped(quad, query) {
-                let (min, max) = self.sequence_interval(quad.xmin, quad.ymin, level, true);
-                ranges.push(Box::new(OverlappingRange::new(min, max)));
-                for el in quad.children() {
-                    remaining.push_back(Some(el));
+            match classify(quad) {
+                Relation::Inside => {
+                    let (min, max) = self.subtree_interval(quad, level, exact);
+                    ranges.push(KeyRange::covered(min, max));
+                }
+                Relation::Crossing => {
+                    let (min, max) = self.sequence_interval(quad.xmin, quad.ymin, level, true);
+                    ranges.push(KeyRange::overlapping(min, max));
+                    for el in quad.children() {
+                        remaining.push_back(Some(el));
+                    }
                 }
+                Relation::Outside => (),
             }
         }
     }
@@ -266,6 +642,11 @@ impl XZ2SFC {
         false
     }
 
+    fn subtree_interval(&self, quad: XElement, level: u32, exact: bool) -> (u64, u64) {
+        let (min, max) = self.sequence_interval(quad.xmin, quad.ymin, level, false);
+        (min, max - u64::from(exact))
+    }
+
     fn sequence_interval(&self, x: f64, y: f64, length: u32, partial: bool) -> (u64, u64) {
         let min = self.sequence_code(x, y, length);
 
@@ -287,15 +668,116 @@ impl XZ2SFC {
                 && y_max <= self.y_max
         );
 
+        let p = &self.projection;
+        let (x0, y0) = (p.project_x(self.x_min), p.project_y(self.y_min));
         (
-            (x_min - self.x_min) / self.x_size(),
-            (y_min - self.y_min) / self.y_size(),
-            (x_max - self.x_min) / self.x_size(),
-            (y_max - self.y_min) / self.y_size(),
+            (p.project_x(x_min) - x0) / self.x_size(),
+            (p.project_y(y_min) - y0) / self.y_size(),
+            (p.project_x(x_max) - x0) / self.x_size(),
+            (p.project_y(y_max) - y0) / self.y_size(),
         )
     }
 }
 
+impl<P: Projection> SpaceFillingCurve2DExtent for XZ2SFC<P> {
+    fn index(&self, x_min: f64, y_min: f64, x_max: f64, y_max: f64) -> u64 {
+        XZ2SFC::index(self, x_min, y_min, x_max, y_max)
+    }
+
+    fn ranges(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        XZ2SFC::ranges(self, x_min, y_min, x_max, y_max, hints)
+    }
+}
+
+/// An element of an `XZ2SFC`, a quad cell extended to twice its width and height
+/// from its minimum corner. Extended bounds may reach past the curve bounds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct XZ2Element {
+    /// The level of the quad tree, 0 for the whole curve.
+    pub level: u32,
+    /// The minimum x of the cell.
+    pub x_min: f64,
+    /// The minimum y of the cell.
+    pub y_min: f64,
+    /// The maximum x of the extended element.
+    pub x_max: f64,
+    /// The maximum y of the extended element.
+    pub y_max: f64,
+}
+
+/// A resumable cursor over the index ranges of bounding box queries, yielding
+/// ranges in ascending order as the elements are refined.
+#[derive(Clone)]
+pub struct XZ2RangeCursor<P = Identity> {
+    walk: XZ2Walk<P>,
+    merger: RangeMerger,
+}
+
+impl<P: Projection> XZ2RangeCursor<P> {
+    /// The ranges found within `budget`. Ranges in later batches follow the ranges in
+    /// earlier batches.
+    pub fn next_batch(&mut self, budget: Budget) -> Vec<KeyRange> {
+        next_batch(&mut self.walk, &mut self.merger, budget)
+    }
+
+    /// Whether every range has been returned.
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.walk.stack.is_empty() && self.merger.is_empty()
+    }
+}
+
+impl<P: Projection> Iterator for XZ2RangeCursor<P> {
+    type Item = KeyRange;
+
+    fn next(&mut self) -> Option<KeyRange> {
+        next_range(&mut self.walk, &mut self.merger)
+    }
+}
+
+/// Depth first walk of the elements in ascending sequence code order.
+#[derive(Clone)]
+struct XZ2Walk<P> {
+    curve: XZ2SFC<P>,
+    max_level: u32,
+    windows: Vec<QueryWindow>,
+    // Elements still to visit and their level, the next on top.
+    stack: Vec<(XElement, u32)>,
+}
+
+impl<P: Projection> RangeWalk for XZ2Walk<P> {
+    fn step(&mut self, emit: &mut dyn FnMut(KeyRange)) -> bool {
+        let (quad, level) = match self.stack.pop() {
+            Some(node) => node,
+            None => return false,
+        };
+
+        if level >= self.max_level {
+            let (min, max) = self.curve.subtree_interval(quad, level, false);
+            emit(KeyRange::overlapping(min, max));
+        } else if XZ2SFC::<P>::is_contained(quad, &self.windows) {
+            let (min, max) = self.curve.subtree_interval(quad, level, false);
+            emit(KeyRange::covered(min, max));
+        } else if XZ2SFC::<P>::is_overlapped(quad, &self.windows) {
+            let (min, max) = self
+                .curve
+                .sequence_interval(quad.xmin, quad.ymin, level, true);
+            emit(KeyRange::overlapping(min, max));
+            for el in quad.children().into_iter().rev() {
+                self.stack.push((el, level + 1));
+            }
+        }
+        true
+    }
+}
+
 const LEVEL_TERMINATOR: Option<XElement> = None;
 
 #[derive(Debug, Clone, Copy)]
@@ -396,14 +878,14 @@ mod tests {
         ];
 
         for bbox in &[containing, overlapping].concat() {
-            let ranges = sfc.ranges(bbox.0, bbox.1, bbox.2, bbox.3, None);
+            let ranges = sfc.ranges(bbox.0, bbox.1, bbox.2, bbox.3, &[]);
             assert!(ranges
                 .iter()
                 .any(|r| r.lower() <= polygon && polygon <= r.upper()));
         }
 
         for bbox in &disjoint {
-            let ranges = sfc.ranges(bbox.0, bbox.1, bbox.2, bbox.3, None);
+            let ranges = sfc.ranges(bbox.0, bbox.1, bbox.2, bbox.3, &[]);
             assert!(!ranges
                 .iter()
                 .any(|r| r.lower() <= polygon && polygon <= r.upper()));
@@ -420,19 +902,368 @@ mod tests {
         assert_eq!(sfc.index(79.9, 0.5, 79.9, 0.5), 17236267);
     }
 
+    #[test]
+    fn test_fallible() {
+        assert_eq!(
+            XZ2SFC::try_new(32, -180.0, -90.0, 180.0, 90.0).err(),
+            Some(Error::InvalidResolution)
+        );
+        assert_eq!(
+            XZ2SFC::try_new(12, -180.0, 90.0, 180.0, -90.0).err(),
+            Some(Error::InvalidBounds)
+        );
+
+        let sfc = XZ2SFC::try_new(12, -180.0, -90.0, 180.0, 90.0).unwrap();
+        assert_eq!(sfc.try_index(10.0, 10.0, 12.0, 12.0), Ok(16841390));
+        assert_eq!(
+            sfc.try_index(10.0, 12.0, 12.0, 10.0),
+            Err(Error::InvertedBox)
+        );
+        assert_eq!(
+            sfc.try_index(10.0, 10.0, 12.0, 90.5),
+            Err(Error::OutOfBounds)
+        );
+        assert_eq!(
+            sfc.try_index(f64::NAN, 10.0, 12.0, 12.0),
+            Err(Error::NotFinite)
+        );
+        assert!(sfc.try_ranges(9.0, 9.0, 13.0, 13.0, &[]).is_ok());
+        assert_eq!(
+            sfc.try_ranges(9.0, 13.0, 13.0, 9.0, &[]).err(),
+            Some(Error::InvertedBox)
+        );
+
+        // The largest g does not overflow.
+        let sfc = XZ2SFC::try_new(XZ2SFC::MAX_G, -180.0, -90.0, 180.0, 90.0).unwrap();
+        assert!(sfc.index(179.9, 89.9, 180.0, 90.0) > 0);
+    }
+
+    #[test]
+    fn test_bounds_policy() {
+        let sfc = XZ2SFC::wgs84(12).with_bounds_policy(BoundsPolicy::Clamp);
+        assert_eq!(
+            sfc.try_index(179.0, 89.0, 180.0000001, 90.5),
+            Ok(sfc.index(179.0, 89.0, 180.0, 90.0))
+        );
+        assert!(!sfc.ranges(170.0, 80.0, 190.0, 95.0, &[]).is_empty());
+
+        let sfc = sfc.with_bounds_policy(BoundsPolicy::WrapLongitude);
+        assert_eq!(
+            sfc.index(190.0, 10.0, 192.0, 12.0),
+            sfc.index(-170.0, 10.0, -168.0, 12.0)
+        );
+        // A box that ends on the antimeridian is not wrapped.
+        assert_eq!(
+            sfc.index(170.0, 10.0, 180.0, 12.0),
+            XZ2SFC::wgs84(12).index(170.0, 10.0, 180.0, 12.0)
+        );
+        assert_eq!(
+            sfc.index(180.0, 10.0, 185.0, 12.0),
+            sfc.index(-180.0, 10.0, -175.0, 12.0)
+        );
+
+        let polygon = sfc.index(-175.0, 10.0, -174.0, 11.0);
+        let ranges = sfc.try_ranges(170.0, 9.0, 190.0, 12.0, &[]).unwrap();
+        assert!(ranges
+            .iter()
+            .any(|r| r.lower() <= polygon && polygon <= r.upper()));
+    }
+
     #[test]
     fn test_ranges() {
         let sfc = XZ2SFC::wgs84(20);
 
-        assert_eq!(sfc.ranges(-0.5, -0.5, 0.5, 0.5, None).len(), 8077);
-        assert!(sfc.ranges(-0.5, -0.5, 0.5, 0.5, Some(1000)).len() < 1000);
+        assert_eq!(sfc.ranges(-0.5, -0.5, 0.5, 0.5, &[]).len(), 8077);
+        assert!(
+            sfc.ranges(-0.5, -0.5, 0.5, 0.5, &[RangeComputeHints::MaxRanges(1000)])
+                .len()
+                < 1000
+        );
 
-        assert_eq!(sfc.ranges(55.758, 20.5, 55.759, 21.5, None).len(), 5883);
-        assert_eq!(sfc.ranges(-55.758, 20.5, -55.755, 21.5, None).len(), 8070);
+        assert_eq!(sfc.ranges(55.758, 20.5, 55.759, 21.5, &[]).len(), 5883);
+        assert_eq!(sfc.ranges(-55.758, 20.5, -55.755, 21.5, &[]).len(), 8070);
 
-        let ranges = sfc.ranges(-55.758, 20.5, -55.755, 21.5, None);
+        let ranges = sfc.ranges(-55.758, 20.5, -55.755, 21.5, &[]);
 
         assert_eq!(ranges.first().map(|r| r.lower()), Some(1));
         assert_eq!(ranges.last().map(|r| r.upper()), Some(847016214083));
     }
+
+    #[test]
+    fn test_ranges_for_boxes() {
+        let sfc = XZ2SFC::wgs84(12);
+        let boxes = [(-80.0, 35.0, -75.0, 40.0), (10.0, 45.0, 12.0, 47.0)];
+        let ranges = sfc.ranges_for_boxes(&boxes, &[]);
+
+        assert!(ranges.windows(2).all(|w| w[0].upper() < w[1].lower()));
+        for &(xmin, ymin) in &[(-78.0, 37.0), (11.0, 46.0)] {
+            let index = sfc.index(xmin, ymin, xmin + 0.5, ymin + 0.5);
+            assert!(ranges
+                .iter()
+                .any(|r| r.lower() <= index && index <= r.upper()));
+        }
+
+        let first = sfc.ranges(-80.0, 35.0, -75.0, 40.0, &[]);
+        let same = sfc.ranges_for_boxes(&[boxes[0], boxes[0]], &[]);
+        assert_eq!(
+            same.iter().map(|r| r.tuple()).collect::<Vec<_>>(),
+            first.iter().map(|r| r.tuple()).collect::<Vec<_>>()
+        );
+
+        assert!(sfc.ranges_for_boxes(&[], &[]).is_empty());
+        assert_eq!(
+            sfc.try_ranges_for_boxes(&[boxes[0], (0.0, 0.0, 1.0, 91.0)], &[])
+                .err(),
+            Some(Error::OutOfBounds)
+        );
+    }
+
+    #[test]
+    fn test_ranges_for_polygon() {
+        let sfc = XZ2SFC::new(8, 0.0, 0.0, 64.0, 64.0);
+        let polygon = Polygon::new(
+            vec![(0.0, 0.0), (8.0, 0.0), (48.0, 40.0), (40.0, 40.0)],
+            vec![],
+        );
+        let ranges = sfc.ranges_for_polygon(&polygon, &[]);
+        let find = |index: u64| {
+            ranges
+                .iter()
+                .find(|r| r.lower() <= index && index <= r.upper())
+        };
+
+        let inside = sfc.index(20.0, 16.0, 20.5, 16.5);
+        assert!(find(inside).is_some());
+        let crossing = sfc.index(1.0, 1.0, 30.0, 2.0);
+        assert!(find(crossing).is_some());
+        let outside = sfc.index(40.0, 10.0, 41.0, 11.0);
+        assert!(find(outside).is_none());
+
+        let box_ranges = sfc.ranges(0.0, 0.0, 48.0, 40.0, &[]);
+        assert!(box_ranges
+            .iter()
+            .any(|r| r.lower() <= outside && outside <= r.upper()));
+
+        assert!(ranges.iter().any(|r| r.contained()));
+        for x in 0..64 {
+            for y in 0..64 {
+                let (x, y) = (f64::from(x), f64::from(y));
+                let index = sfc.index(x, y, x + 0.5, y + 0.5);
+                if find(index).is_some_and(|r| r.contained()) {
+                    let corners = [(x, y), (x + 0.5, y), (x, y + 0.5), (x + 0.5, y + 0.5)];
+                    assert!(corners.iter().all(|&(x, y)| polygon.contains(x, y)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_ranges_within_distance() {
+        let sfc = XZ2SFC::wgs84(12);
+        let ranges = sfc.ranges_within_distance(179.9, 45.0, 50_000.0, &[]);
+        let contains = |index| {
+            ranges
+                .iter()
+                .any(|r| r.lower() <= index && index <= r.upper())
+        };
+
+        assert!(contains(sfc.index(179.8, 45.0, 179.95, 45.1)));
+        assert!(contains(sfc.index(-179.9, 44.9, -179.8, 45.0)));
+        assert!(contains(sfc.index(170.0, 40.0, 179.8, 45.0)));
+        assert!(!contains(sfc.index(170.0, 40.0, 171.0, 41.0)));
+        assert!(!contains(sfc.index(-179.9, 40.0, -179.8, 40.1)));
+
+        let everything = sfc.ranges_within_distance(0.0, 0.0, 21_000_000.0, &[]);
+        assert!(everything.iter().all(|r| r.contained()));
+
+        assert_eq!(
+            sfc.try_ranges_within_distance(179.9, 45.0, 50_000.0, &[]),
+            Ok(ranges)
+        );
+        assert_eq!(
+            XZ2SFC::new(12, -1e6, -1e6, 1e6, 1e6).try_ranges_within_distance(
+                0.0,
+                0.0,
+                1_000.0,
+                &[]
+            ),
+            Err(Error::InvalidBounds)
+        );
+        assert_eq!(
+            sfc.try_ranges_within_distance(f64::NAN, 45.0, 50_000.0, &[]),
+            Err(Error::NotFinite)
+        );
+    }
+
+    #[test]
+    fn test_antimeridian_ranges() {
+        let sfc = XZ2SFC::wgs84(12);
+        let ranges = sfc.ranges(170.0, -10.0, -170.0, 10.0, &[]);
+
+        assert!(ranges.windows(2).all(|w| w[0].upper() < w[1].lower()));
+        for &(xmin, xmax) in &[(175.0, 176.0), (-176.0, -175.0)] {
+            let index = sfc.index(xmin, 0.0, xmax, 1.0);
+            assert!(ranges
+                .iter()
+                .any(|r| r.lower() <= index && index <= r.upper()));
+        }
+        assert_eq!(
+            sfc.try_ranges(170.0, -10.0, -170.0, 10.0, &[]).ok(),
+            Some(ranges)
+        );
+
+        assert_eq!(
+            sfc.index(179.0, 0.0, -179.0, 1.0),
+            sfc.index(-180.0, 0.0, 180.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn test_range_cursor() {
+        let sfc = XZ2SFC::wgs84(12);
+        let boxes = [(10.0, 10.0, 12.0, 12.0), (170.0, -5.0, -175.0, 5.0)];
+        let expected = sfc.ranges_for_boxes(&boxes, &[]);
+
+        assert_eq!(sfc.range_cursor(&boxes, &[]).collect::<Vec<_>>(), expected);
+
+        let hints = [RangeComputeHints::MaxRecurse(6)];
+        let expected = sfc.ranges_for_boxes(&boxes, &hints);
+        let mut cursor = sfc.range_cursor(&boxes, &hints);
+        let mut paged = Vec::new();
+        while !cursor.is_finished() {
+            let batch = cursor.next_batch(Budget::Ranges(10));
+            assert!(batch.len() <= 10);
+            paged.extend(batch);
+        }
+        assert_eq!(paged, expected);
+        assert_eq!(
+            sfc.range_cursor(&boxes, &[RangeComputeHints::Precision(12)])
+                .collect::<Vec<_>>(),
+            sfc.ranges_for_boxes(&boxes, &[RangeComputeHints::Precision(12)])
+        );
+    }
+
+    #[test]
+    fn test_range_hints() {
+        let sfc = XZ2SFC::wgs84(12);
+        let full = sfc.ranges(-80.0, 35.0, -75.0, 40.0, &[]);
+        let capped = sfc.ranges(
+            -80.0,
+            35.0,
+            -75.0,
+            40.0,
+            &[RangeComputeHints::MaxRanges(50)],
+        );
+        assert!(full.len() > 50);
+        assert!(capped.len() <= 50);
+
+        let shallow = sfc.ranges(
+            -80.0,
+            35.0,
+            -75.0,
+            40.0,
+            &[RangeComputeHints::MaxRecurse(6)],
+        );
+        assert!(shallow.len() < full.len());
+        assert_eq!(
+            shallow,
+            sfc.ranges(
+                -80.0,
+                35.0,
+                -75.0,
+                40.0,
+                &[RangeComputeHints::Precision(12)]
+            )
+        );
+
+        for &(x, y) in &[(-77.0, 37.0), (-75.5, 39.9)] {
+            let index = sfc.index(x, y, x + 0.01, y + 0.01);
+            for ranges in &[&capped, &shallow] {
+                assert!(ranges
+                    .iter()
+                    .any(|r| r.lower() <= index && index <= r.upper()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_element() {
+        let sfc = XZ2SFC::wgs84(12);
+        assert_eq!(
+            sfc.element(0),
+            Some(XZ2Element {
+                level: 0,
+                x_min: -180.0,
+                y_min: -90.0,
+                x_max: 540.0,
+                y_max: 270.0
+            })
+        );
+        assert_eq!(
+            sfc.element(2),
+            Some(XZ2Element {
+                level: 2,
+                x_min: -180.0,
+                y_min: -90.0,
+                x_max: 0.0,
+                y_max: 0.0
+            })
+        );
+        assert_eq!(sfc.element(12).map(|e| e.level), Some(12));
+
+        let last = div_floor(4_u64.pow(13) - 1, 3) - 1;
+        assert_eq!(sfc.element(last).map(|e| e.level), Some(12));
+        assert_eq!(sfc.element(last + 1), None);
+
+        for &(x_min, y_min, x_max, y_max) in &[
+            (10.0, 10.0, 12.0, 12.0),
+            (79.9, 0.5, 79.9, 0.5),
+            (-75.2, 38.9, -75.1, 39.0),
+            (100.0, -60.0, 150.0, -35.0),
+        ] {
+            let index = sfc.index(x_min, y_min, x_max, y_max);
+            let element = sfc.element(index).unwrap();
+            assert!(element.x_min <= x_min && element.y_min <= y_min);
+            assert!(x_max <= element.x_max && y_max <= element.y_max);
+
+            assert_eq!(
+                element.x_max - element.x_min,
+                720.0 * 0.5_f64.powi(element.level as i32)
+            );
+        }
+    }
+
+    #[test]
+    fn test_projection() {
+        use crate::projection::WebMercator;
+
+        let sfc = XZ2SFC::wgs84(12).with_projection(WebMercator);
+        for &(x_min, y_min, x_max, y_max) in &[
+            (2.3, 48.8, 2.4, 48.9),
+            (-10.0, 60.0, 10.0, 70.0),
+            (-1.0, 86.0, 1.0, 89.0),
+        ] {
+            let index = sfc.index(x_min, y_min, x_max, y_max);
+            let element = sfc.element(index).unwrap();
+            assert!(element.x_min <= x_min && x_max <= element.x_max);
+            assert!(element.y_min <= y_min);
+
+            let ranges = sfc.ranges(
+                x_min - 1.0,
+                y_min - 1.0,
+                x_max + 1.0,
+                (y_max + 1.0).min(90.0),
+                &[],
+            );
+            assert!(ranges
+                .iter()
+                .any(|r| r.lower() <= index && index <= r.upper()));
+        }
+
+        // Boxes above the last mercator latitude are indexed in the top row.
+        assert_eq!(
+            sfc.index(0.1, 86.0, 0.2, 89.0),
+            sfc.index(0.1, 86.0, 0.2, 90.0)
+        );
+    }
 }
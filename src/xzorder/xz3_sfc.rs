@@ -15,14 +15,26 @@
 
 //! Space-Time filling curve for non-points
 
-use crate::index_range::{CoveredRange, IndexRange, OverlappingRange};
-use alloc::{boxed::Box, collections::VecDeque, vec, vec::Vec};
+use crate::{
+    bounds::{cover_x_interval, split_x_interval, BoundsPolicy},
+    curve::SpaceTimeFillingCurveExtent,
+    error::{check_bounds, check_geographic, Error},
+    geometry::{Circle, Relation},
+    index_range::{merge_ranges, next_batch, next_range, Budget, KeyRange, RangeMerger, RangeWalk},
+    projection::{Identity, Projection},
+    RangeComputeHints, RangeHints,
+};
+use alloc::{collections::VecDeque, vec, vec::Vec};
+use core::convert::TryFrom;
 use num_integer::div_floor;
 #[allow(unused_imports)]
 use num_traits::Float;
 
-/// An extended z-order curve for space-time indexing with non-points.
-pub struct XZ3SFC {
+/// An extended z-order curve for space-time indexing with non-points. x and y are
+/// normalized in the space of the `Projection`, by default in x and y. Time is not
+/// projected.
+#[derive(Clone)]
+pub struct XZ3SFC<P = Identity> {
     g: u32,
     x_min: f64,
     x_max: f64,
@@ -30,6 +42,8 @@ pub struct XZ3SFC {
     y_max: f64,
     z_min: f64,
     z_max: f64,
+    policy: BoundsPolicy,
+    projection: P,
 }
 
 const LEVEL_TERMINATOR: Option<XElement> = None;
@@ -46,6 +60,8 @@ impl XZ3SFC {
             y_max: 90.0,
             z_min,
             z_max,
+            policy: BoundsPolicy::default(),
+            projection: Identity,
         }
     }
 
@@ -68,22 +84,113 @@ impl XZ3SFC {
             y_max,
             z_min,
             z_max,
+            policy: BoundsPolicy::default(),
+            projection: Identity,
         }
     }
 
+    /// The largest `g` that can be indexed in 64 bits.
+    pub const MAX_G: u32 = 20;
+
+    /// Fallible constructor, checking that `g` is between 1 and `XZ3SFC::MAX_G` and
+    /// that the bounds are finite and not empty.
+    pub fn try_new(
+        g: u32,
+        x_min: f64,
+        y_min: f64,
+        z_min: f64,
+        x_max: f64,
+        y_max: f64,
+        z_max: f64,
+    ) -> Result<Self, Error> {
+        if g == 0 || g > Self::MAX_G {
+            return Err(Error::InvalidResolution);
+        }
+        check_bounds(x_min, x_max)?;
+        check_bounds(y_min, y_max)?;
+        check_bounds(z_min, z_max)?;
+
+        Ok(Self::new(g, x_min, y_min, z_min, x_max, y_max, z_max))
+    }
+}
+
+impl<P: Projection> XZ3SFC<P> {
     fn x_size(&self) -> f64 {
-        self.x_max - self.x_min
+        self.projection.project_x(self.x_max) - self.projection.project_x(self.x_min)
     }
 
     fn y_size(&self) -> f64 {
-        self.y_max - self.y_min
+        self.projection.project_y(self.y_max) - self.projection.project_y(self.y_min)
     }
 
     fn z_size(&self) -> f64 {
         self.z_max - self.z_min
     }
 
-    /// Compute the index for a bounding box with a time (z) component
+    /// The x at normalized `x`, from 0 at `x_min` to 1 at `x_max` in projected space.
+    /// From 1 on the value is at least `x_max`, even where the projection clamps.
+    fn denormalize_x(&self, x: f64) -> f64 {
+        let p = &self.projection;
+        let value = p.unproject_x(p.project_x(self.x_min) + x * self.x_size());
+        if x >= 1.0 {
+            value.max(self.x_max)
+        } else {
+            value
+        }
+    }
+
+    /// The y at normalized `y`, as for `denormalize_x`.
+    fn denormalize_y(&self, y: f64) -> f64 {
+        let p = &self.projection;
+        let value = p.unproject_y(p.project_y(self.y_min) + y * self.y_size());
+        if y >= 1.0 {
+            value.max(self.y_max)
+        } else {
+            value
+        }
+    }
+
+    /// Normalize x and y in the space of `projection`. The bounds of the curve stay
+    /// in unprojected coordinates.
+    #[must_use]
+    pub fn with_projection<Q: Projection>(self, projection: Q) -> XZ3SFC<Q> {
+        XZ3SFC {
+            g: self.g,
+            x_min: self.x_min,
+            x_max: self.x_max,
+            y_min: self.y_min,
+            y_max: self.y_max,
+            z_min: self.z_min,
+            z_max: self.z_max,
+            policy: self.policy,
+            projection,
+        }
+    }
+
+    /// The projection applied to x and y before normalizing them.
+    #[must_use]
+    pub fn projection(&self) -> &P {
+        &self.projection
+    }
+
+    /// Set how `index` and `ranges` treat bounding boxes outside of the curve
+    /// bounds. A box that wraps across the x bounds is indexed across the full x
+    /// extent.
+    #[must_use]
+    pub fn with_bounds_policy(mut self, policy: BoundsPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// How coordinates outside of the curve bounds are treated.
+    #[must_use]
+    pub fn bounds_policy(&self) -> BoundsPolicy {
+        self.policy
+    }
+
+    /// Compute the index for a bounding box with a time (z) component. A box with
+    /// `x_min > x_max` wraps across the x bounds and is indexed across the full x
+    /// extent.
     pub fn index(
         &self,
         x_min: f64,
@@ -93,6 +200,9 @@ impl XZ3SFC {
         y_max: f64,
         z_max: f64,
     ) -> u64 {
+        let (x_min, y_min, z_min, x_max, y_max, z_max) =
+            self.constrain_box(x_min, y_min, z_min, x_max, y_max, z_max);
+        let (x_min, x_max) = cover_x_interval(x_min, x_max, self.x_min, self.x_max);
         let (nxmin, nymin, nzmin, nxmax, nymax, nzmax) =
             self.normalize(x_min, y_min, z_min, x_max, y_max, z_max);
 
@@ -117,11 +227,122 @@ impl XZ3SFC {
         self.sequence_code(nxmin, nymin, nzmin, length)
     }
 
+    /// Compute the index for a bounding box with a time (z) component, returning an
+    /// error if the box is inverted in y or time, not finite or outside of the curve
+    /// bounds and rejected by the `BoundsPolicy`.
+    pub fn try_index(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        z_min: f64,
+        x_max: f64,
+        y_max: f64,
+        z_max: f64,
+    ) -> Result<u64, Error> {
+        let (x_min, y_min, z_min, x_max, y_max, z_max) =
+            self.check_box(x_min, y_min, z_min, x_max, y_max, z_max)?;
+
+        Ok(self.index(x_min, y_min, z_min, x_max, y_max, z_max))
+    }
+
+    /// Return the element of an index, inverting `index`. Returns `None` if the index
+    /// is past the last element of the curve.
+    #[must_use]
+    pub fn element(&self, index: u64) -> Option<XZ3Element> {
+        let mut x = 0.0;
+        let mut y = 0.0;
+        let mut z = 0.0;
+        let mut size = 1.0;
+
+        let mut cs = index;
+        let mut level = 0_u32;
+
+        while cs > 0 {
+            if level == self.g {
+                return None;
+            }
+            let subtree = div_floor(8_u64.pow(self.g - level) - 1, 7);
+            cs -= 1;
+            let octant = cs / subtree;
+            if octant > 7 {
+                return None;
+            }
+            cs %= subtree;
+
+            size /= 2.0;
+            if octant & 1 == 1 {
+                x += size;
+            }
+            if octant & 2 == 2 {
+                y += size;
+            }
+            if octant & 4 == 4 {
+                z += size;
+            }
+            level += 1;
+        }
+
+        let z_min = self.z_min + z * self.z_size();
+        Some(XZ3Element {
+            level,
+            x_min: self.denormalize_x(x),
+            y_min: self.denormalize_y(y),
+            z_min,
+            x_max: self.denormalize_x(x + 2.0 * size),
+            y_max: self.denormalize_y(y + 2.0 * size),
+            z_max: z_min + 2.0 * size * self.z_size(),
+        })
+    }
+
+    fn constrain_box(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        z_min: f64,
+        x_max: f64,
+        y_max: f64,
+        z_max: f64,
+    ) -> (f64, f64, f64, f64, f64, f64) {
+        let (x_min, x_max) = self
+            .policy
+            .constrain_x_interval(x_min, x_max, self.x_min, self.x_max);
+        let (y_min, y_max) = self
+            .policy
+            .constrain_interval(y_min, y_max, self.y_min, self.y_max);
+        let (z_min, z_max) = self
+            .policy
+            .constrain_interval(z_min, z_max, self.z_min, self.z_max);
+        (x_min, y_min, z_min, x_max, y_max, z_max)
+    }
+
+    fn check_box(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        z_min: f64,
+        x_max: f64,
+        y_max: f64,
+        z_max: f64,
+    ) -> Result<(f64, f64, f64, f64, f64, f64), Error> {
+        let (x_min, x_max) = self
+            .policy
+            .check_x_interval(x_min, x_max, self.x_min, self.x_max)?;
+        let (y_min, y_max) = self
+            .policy
+            .check_interval(y_min, y_max, self.y_min, self.y_max)?;
+        let (z_min, z_max) = self
+            .policy
+            .check_interval(z_min, z_max, self.z_min, self.z_max)?;
+        Ok((x_min, y_min, z_min, x_max, y_max, z_max))
+    }
+
     fn predicate(min: f64, max: f64, w2: f64) -> bool {
         max <= (min / w2).floor() * w2 + (2.0 * w2)
     }
 
-    /// Compute the index range that are contained or overlap the bounding box.
+    /// Compute the index range that are contained or overlap the bounding box. A box
+    /// with `xmin > xmax` wraps across the x bounds, e.g. a window over the
+    /// antimeridian.
     #[allow(clippy::too_many_arguments)]
     pub fn ranges(
         &self,
@@ -131,26 +352,241 @@ impl XZ3SFC {
         xmax: f64,
         ymax: f64,
         zmax: f64,
-        max_ranges: Option<u16>,
-    ) -> Vec<Box<dyn IndexRange>> {
-        let windows = {
-            let (nxmin, nymin, nzmin, nxmax, nymax, nzmax) =
-                self.normalize(xmin, ymin, zmin, xmax, ymax, zmax);
-            &[QueryWindow {
-                x_min: nxmin,
-                y_min: nymin,
-                z_min: nzmin,
-                x_max: nxmax,
-                y_max: nymax,
-                z_max: nzmax,
-            }]
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        self.ranges_for_boxes(&[(xmin, ymin, zmin, xmax, ymax, zmax)], hints)
+    }
+
+    /// Compute the index range that are contained or overlap the bounding box,
+    /// returning an error if the box is inverted in y or time, not finite or outside of
+    /// the curve bounds and rejected by the `BoundsPolicy`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn try_ranges(
+        &self,
+        xmin: f64,
+        ymin: f64,
+        zmin: f64,
+        xmax: f64,
+        ymax: f64,
+        zmax: f64,
+        hints: &[RangeComputeHints],
+    ) -> Result<Vec<KeyRange>, Error> {
+        self.try_ranges_for_boxes(&[(xmin, ymin, zmin, xmax, ymax, zmax)], hints)
+    }
+
+    /// Compute one merged list of index ranges that are contained or overlap any of
+    /// the `(xmin, ymin, zmin, xmax, ymax, zmax)` boxes.
+    pub fn ranges_for_boxes(
+        &self,
+        boxes: &[(f64, f64, f64, f64, f64, f64)],
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        let windows = self.windows(boxes);
+
+        if windows.is_empty() {
+            return Vec::new();
+        }
+
+        self.ranges_impl(&windows, &RangeHints::new(hints))
+    }
+
+    /// Compute one merged list of index ranges that are contained or overlap any of
+    /// the boxes, returning an error if a box is inverted in y or time, not finite or
+    /// outside of the curve bounds and rejected by the `BoundsPolicy`.
+    pub fn try_ranges_for_boxes(
+        &self,
+        boxes: &[(f64, f64, f64, f64, f64, f64)],
+        hints: &[RangeComputeHints],
+    ) -> Result<Vec<KeyRange>, Error> {
+        let boxes = boxes
+            .iter()
+            .map(|&(xmin, ymin, zmin, xmax, ymax, zmax)| {
+                self.check_box(xmin, ymin, zmin, xmax, ymax, zmax)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(self.ranges_for_boxes(&boxes, hints))
+    }
+
+    /// Get a resumable cursor over the index ranges that are contained or overlap any
+    /// of the `(xmin, ymin, zmin, xmax, ymax, zmax)` boxes, in ascending order.
+    /// Collecting the cursor gives the same ranges as `ranges_for_boxes`. Only the
+    /// `MaxRecurse` and `Precision` hints apply, page with a `Budget` instead of
+    /// `MaxRanges`.
+    #[must_use]
+    pub fn range_cursor(
+        &self,
+        boxes: &[(f64, f64, f64, f64, f64, f64)],
+        hints: &[RangeComputeHints],
+    ) -> XZ3RangeCursor<P>
+    where
+        P: Clone,
+    {
+        let windows = self.windows(boxes);
+        let stack = if windows.is_empty() {
+            Vec::new()
+        } else {
+            XElement::level_one_elements()
+                .into_iter()
+                .rev()
+                .map(|el| (el, 1))
+                .collect()
+        };
+
+        XZ3RangeCursor {
+            walk: XZ3Walk {
+                curve: self.clone(),
+                max_level: self.max_level(&RangeHints::new(hints)),
+                windows,
+                stack,
+            },
+            merger: RangeMerger::new(true),
+        }
+    }
+
+    /// Compute the index ranges of the bounding boxes that come within `meters` of a
+    /// point during the time range, with x as longitude and y as latitude. The circle
+    /// may cross the antimeridian or cover a pole. Only ranges of elements whose
+    /// extent lies entirely inside the circle and time range are `contained`.
+    ///
+    /// # NOTE:
+    ///   panics if the curve bounds are not longitude and latitude, or if any
+    ///   argument is not finite or `meters` is negative.
+    #[allow(clippy::too_many_arguments)]
+    pub fn ranges_within_distance(
+        &self,
+        lon: f64,
+        lat: f64,
+        meters: f64,
+        t_min: f64,
+        t_max: f64,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        assert!(
+            check_geographic(self.x_min, self.y_min, self.x_max, self.y_max).is_ok(),
+            "Distance queries need longitude and latitude bounds"
+        );
+        self.ranges_within_circle(&Circle::new(lon, lat, meters), t_min, t_max, hints)
+    }
+
+    /// Compute the index ranges of the bounding boxes that come within `meters` of a
+    /// point during the time range, returning `Error::InvalidBounds` if the curve
+    /// bounds are not longitude and latitude, the errors of `Circle::try_new`, and an
+    /// error if the time range is inverted, not finite or outside of the curve and
+    /// rejected by the `BoundsPolicy`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn try_ranges_within_distance(
+        &self,
+        lon: f64,
+        lat: f64,
+        meters: f64,
+        t_min: f64,
+        t_max: f64,
+        hints: &[RangeComputeHints],
+    ) -> Result<Vec<KeyRange>, Error> {
+        check_geographic(self.x_min, self.y_min, self.x_max, self.y_max)?;
+        let circle = Circle::try_new(lon, lat, meters)?;
+        let (t_min, t_max) = self
+            .policy
+            .check_interval(t_min, t_max, self.z_min, self.z_max)?;
+        Ok(self.ranges_within_circle(&circle, t_min, t_max, hints))
+    }
+
+    fn ranges_within_circle(
+        &self,
+        circle: &Circle,
+        t_min: f64,
+        t_max: f64,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        let (x_min, y_min, x_max, y_max) = circle.bounding_box();
+        let (x_min, x_max) = cover_x_interval(x_min, x_max, self.x_min, self.x_max);
+        if x_max < self.x_min
+            || x_min > self.x_max
+            || y_max < self.y_min
+            || y_min > self.y_max
+            || t_max < self.z_min
+            || t_min > self.z_max
+        {
+            return Vec::new();
+        }
+        let nt_min = (t_min - self.z_min) / self.z_size();
+        let nt_max = (t_max - self.z_min) / self.z_size();
+
+        let classify = |oct: &XElement| {
+            let time = if nt_min <= oct.z_min && oct.zext() <= nt_max {
+                Relation::Inside
+            } else if nt_min <= oct.zext() && oct.z_min <= nt_max {
+                Relation::Crossing
+            } else {
+                Relation::Outside
+            };
+            if time == Relation::Outside {
+                return time;
+            }
+
+            time.and(circle.relate(
+                self.denormalize_x(oct.x_min),
+                self.denormalize_y(oct.y_min),
+                self.denormalize_x(oct.xext()),
+                self.denormalize_y(oct.yext()),
+            ))
+        };
+
+        self.decompose(&classify, &RangeHints::new(hints), true)
+    }
+
+    fn windows(&self, boxes: &[(f64, f64, f64, f64, f64, f64)]) -> Vec<QueryWindow> {
+        boxes
+            .iter()
+            .flat_map(|&(xmin, ymin, zmin, xmax, ymax, zmax)| {
+                let (xmin, ymin, zmin, xmax, ymax, zmax) =
+                    self.constrain_box(xmin, ymin, zmin, xmax, ymax, zmax);
+                split_x_interval(xmin, xmax, self.x_min, self.x_max).map(move |(xmin, xmax)| {
+                    let (nxmin, nymin, nzmin, nxmax, nymax, nzmax) =
+                        self.normalize(xmin, ymin, zmin, xmax, ymax, zmax);
+                    QueryWindow {
+                        x_min: nxmin,
+                        y_min: nymin,
+                        z_min: nzmin,
+                        x_max: nxmax,
+                        y_max: nymax,
+                        z_max: nzmax,
+                    }
+                })
+            })
+            .collect()
+    }
+
+    fn ranges_impl(&self, query: &[QueryWindow], hints: &RangeHints) -> Vec<KeyRange> {
+        let classify = |oct: &XElement| {
+            if Self::is_contained(oct, query) {
+                Relation::Inside
+            } else if Self::is_overlapped(oct, query) {
+                Relation::Crossing
+            } else {
+                Relation::Outside
+            }
         };
 
-        let range_stop = max_ranges.unwrap_or(u16::MAX);
-        self.ranges_impl(windows, range_stop)
+        self.decompose(&classify, hints, false)
     }
 
-    fn ranges_impl(&self, query: &[QueryWindow], range_stop: u16) -> Vec<Box<dyn IndexRange>> {
+    /// Recurse the elements that `classify` finds inside or crossing the query.
+    ///
+    /// If `exact` is set the interval of a subtree ends at its last element and ranges
+    /// of different containment are not merged, so `contained` ranges hold only
+    /// elements inside the query. Otherwise intervals end one past the subtree, as in
+    /// geomesa.
+    fn decompose(
+        &self,
+        classify: &dyn Fn(&XElement) -> Relation,
+        hints: &RangeHints,
+        exact: bool,
+    ) -> Vec<KeyRange> {
+        let max_level = self.max_level(hints);
+        let range_stop = hints.max_ranges.unwrap_or(usize::MAX);
+
         let mut ranges = Vec::with_capacity(100);
 
         let mut remaining = VecDeque::with_capacity(100);
@@ -162,7 +598,8 @@ impl XZ3SFC {
 
         let mut level = 1;
 
-        while level < self.g && !remaining.is_empty() && ranges.len() < range_stop.into() {
+        while level < max_level && !remaining.is_empty() && ranges.len() < range_stop {
+            #[allow(clippy::collapsible_match)]
             match remaining.pop_front() {
                 Some(LEVEL_TERMINATOR) => {
                     if !remaining.is_empty() {
@@ -171,7 +608,7 @@ impl XZ3SFC {
                     }
                 }
                 Some(Some(oct)) => {
-                    self.check_value(&oct, level, query, &mut ranges, &mut remaining);
+                    self.check_value(&oct, level, classify, exact, &mut ranges, &mut remaining);
                 }
                 _ => (),
             }
@@ -179,41 +616,24 @@ impl XZ3SFC {
 
         while let Some(el) = remaining.pop_front() {
             if let Some(oct) = el {
-                let (min, max) =
-                    self.sequence_interval(oct.x_min, oct.y_min, oct.z_min, level, false);
-                ranges.push(Box::new(OverlappingRange::new(min, max)));
+                let (min, max) = self.subtree_interval(&oct, level, exact);
+                ranges.push(KeyRange::overlapping(min, max));
             } else {
                 level += 1;
             }
         }
 
-        ranges.sort();
-
-        let mut current: Option<Box<dyn IndexRange>> = None;
-        let mut results = vec![];
-        for range in ranges {
-            if let Some(cur) = current {
-                if range.lower() <= cur.upper() + 1 {
-                    let max = cur.upper().max(range.upper());
-                    let min = cur.lower();
-                    if cur.contained() && range.contained() {
-                        current = Some(Box::new(CoveredRange::new(min, max)));
-                    } else {
-                        current = Some(Box::new(OverlappingRange::new(min, max)));
-                    }
-                } else {
-                    results.push(cur);
-                    current = Some(range);
-                }
-            } else {
-                current = Some(range);
-            }
-        }
+        hints.coalesce(merge_ranges(ranges, !exact))
+    }
 
-        if let Some(current) = current {
-            results.push(current);
-        }
-        results
+    /// The level at which refinement stops.
+    fn max_level(&self, hints: &RangeHints) -> u32 {
+        let precision_level = u32::try_from(hints.precision / 3).unwrap_or(u32::MAX);
+        let recurse_level = hints
+            .max_recurse
+            .map_or(u32::MAX, |max| u32::try_from(max).unwrap_or(u32::MAX));
+
+        self.g.min(precision_level).min(recurse_level).max(1)
     }
 
     fn is_contained(oct: &XElement, query: &[QueryWindow]) -> bool {
@@ -238,22 +658,33 @@ impl XZ3SFC {
         &self,
         oct: &XElement,
         level: u32,
-        query: &[QueryWindow],
-        ranges: &mut Vec<Box<dyn IndexRange>>,
+        classify: &dyn Fn(&XElement) -> Relation,
+        exact: bool,
+        ranges: &mut Vec<KeyRange>,
         remaining: &mut VecDeque<Option<XElement>>,
     ) {
-        if Self::is_contained(oct, query) {
-            let (min, max) = self.sequence_interval(oct.x_min, oct.y_min, oct.z_min, level, false);
-            ranges.push(Box::new(CoveredRange::new(min, max)));
-        } else if Self::is_overlapped(oct, query) {
-            let (min, max) = self.sequence_interval(oct.x_min, oct.y_min, oct.z_min, level, true);
-            ranges.push(Box::new(OverlappingRange::new(min, max)));
-            for el in oct.children() {
-                remaining.push_back(Some(el));
+        match classify(oct) {
+            Relation::Inside => {
+                let (min, max) = self.subtree_interval(oct, level, exact);
+                ranges.push(KeyRange::covered(min, max));
+            }
+            Relation::Crossing => {
+                let (min, max) =
+                    self.sequence_interval(oct.x_min, oct.y_min, oct.z_min, level, true);
+                ranges.push(KeyRange::overlapping(min, max));
+                for el in oct.children() {
+                    remaining.push_back(Some(el));
+                }
             }
+            Relation::Outside => (),
         }
     }
 
+    fn subtree_interval(&self, oct: &XElement, level: u32, exact: bool) -> (u64, u64) {
+        let (min, max) = self.sequence_interval(oct.x_min, oct.y_min, oct.z_min, level, false);
+        (min, max - u64::from(exact))
+    }
+
     fn normalize(
         &self,
         x_min: f64,
@@ -263,12 +694,14 @@ impl XZ3SFC {
         y_max: f64,
         z_max: f64,
     ) -> (f64, f64, f64, f64, f64, f64) {
+        let p = &self.projection;
+        let (x0, y0) = (p.project_x(self.x_min), p.project_y(self.y_min));
         (
-            (x_min - self.x_min) / self.x_size(),
-            (y_min - self.y_min) / self.y_size(),
+            (p.project_x(x_min) - x0) / self.x_size(),
+            (p.project_y(y_min) - y0) / self.y_size(),
             (z_min - self.z_min) / self.z_size(),
-            (x_max - self.x_min) / self.x_size(),
-            (y_max - self.y_min) / self.y_size(),
+            (p.project_x(x_max) - x0) / self.x_size(),
+            (p.project_y(y_max) - y0) / self.y_size(),
             (z_max - self.z_min) / self.z_size(),
         )
     }
@@ -355,6 +788,116 @@ impl XZ3SFC {
     }
 }
 
+impl<P: Projection> SpaceTimeFillingCurveExtent for XZ3SFC<P> {
+    fn index(&self, x_min: f64, y_min: f64, x_max: f64, y_max: f64, t_min: f64, t_max: f64) -> u64 {
+        XZ3SFC::index(self, x_min, y_min, t_min, x_max, y_max, t_max)
+    }
+
+    fn ranges(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        t_min: f64,
+        t_max: f64,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        XZ3SFC::ranges(self, x_min, y_min, t_min, x_max, y_max, t_max, hints)
+    }
+}
+
+/// An element of an `XZ3SFC`, an oct cell extended to twice its size in each
+/// dimension from its minimum corner. Extended bounds may reach past the curve bounds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct XZ3Element {
+    /// The level of the oct tree, 0 for the whole curve.
+    pub level: u32,
+    /// The minimum x of the cell.
+    pub x_min: f64,
+    /// The minimum y of the cell.
+    pub y_min: f64,
+    /// The minimum z of the cell.
+    pub z_min: f64,
+    /// The maximum x of the extended element.
+    pub x_max: f64,
+    /// The maximum y of the extended element.
+    pub y_max: f64,
+    /// The maximum z of the extended element.
+    pub z_max: f64,
+}
+
+/// A resumable cursor over the index ranges of box queries, yielding ranges in
+/// ascending order as the elements are refined.
+#[derive(Clone)]
+pub struct XZ3RangeCursor<P = Identity> {
+    walk: XZ3Walk<P>,
+    merger: RangeMerger,
+}
+
+impl<P: Projection> XZ3RangeCursor<P> {
+    /// The ranges found within `budget`. Ranges in later batches follow the ranges in
+    /// earlier batches.
+    pub fn next_batch(&mut self, budget: Budget) -> Vec<KeyRange> {
+        next_batch(&mut self.walk, &mut self.merger, budget)
+    }
+
+    /// Whether every range has been returned.
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.walk.stack.is_empty() && self.merger.is_empty()
+    }
+}
+
+impl<P: Projection> Iterator for XZ3RangeCursor<P> {
+    type Item = KeyRange;
+
+    fn next(&mut self) -> Option<KeyRange> {
+        next_range(&mut self.walk, &mut self.merger)
+    }
+}
+
+/// Depth first walk of the elements in ascending sequence code order.
+#[derive(Clone)]
+struct XZ3Walk<P> {
+    curve: XZ3SFC<P>,
+    max_level: u32,
+    windows: Vec<QueryWindow>,
+    // Elements still to visit and their level, the next on top.
+    stack: Vec<(XElement, u32)>,
+}
+
+impl<P: Projection> RangeWalk for XZ3Walk<P> {
+    fn step(&mut self, emit: &mut dyn FnMut(KeyRange)) -> bool {
+        let (oct, level) = match self.stack.pop() {
+            Some(node) => node,
+            None => return false,
+        };
+
+        if level >= self.max_level {
+            let (min, max) = self
+                .curve
+                .sequence_interval(oct.x_min, oct.y_min, oct.z_min, level, false);
+            emit(KeyRange::overlapping(min, max));
+        } else if XZ3SFC::<P>::is_contained(&oct, &self.windows) {
+            let (min, max) = self
+                .curve
+                .sequence_interval(oct.x_min, oct.y_min, oct.z_min, level, false);
+            emit(KeyRange::covered(min, max));
+        } else if XZ3SFC::<P>::is_overlapped(&oct, &self.windows) {
+            let (min, max) = self
+                .curve
+                .sequence_interval(oct.x_min, oct.y_min, oct.z_min, level, true);
+            emit(KeyRange::overlapping(min, max));
+            for el in oct.children().into_iter().rev() {
+                self.stack.push((el, level + 1));
+            }
+        }
+        true
+    }
+}
+
+#[derive(Clone)]
 struct QueryWindow {
     x_min: f64,
     y_min: f64,
@@ -364,7 +907,7 @@ struct QueryWindow {
     z_max: f64,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 struct XElement {
     x_min: f64,
     y_min: f64,
@@ -546,7 +1089,15 @@ mod tests {
         ];
 
         for bbox in &[containing, overlapping].concat() {
-            let ranges = sfc.ranges(bbox.0, bbox.1, bbox.2, bbox.3, bbox.4, bbox.5, Some(10000));
+            let ranges = sfc.ranges(
+                bbox.0,
+                bbox.1,
+                bbox.2,
+                bbox.3,
+                bbox.4,
+                bbox.5,
+                &[RangeComputeHints::MaxRanges(10000)],
+            );
 
             assert!(ranges
                 .iter()
@@ -554,7 +1105,15 @@ mod tests {
         }
 
         for bbox in &disjoint {
-            let ranges = sfc.ranges(bbox.0, bbox.1, bbox.2, bbox.3, bbox.4, bbox.5, Some(10000));
+            let ranges = sfc.ranges(
+                bbox.0,
+                bbox.1,
+                bbox.2,
+                bbox.3,
+                bbox.4,
+                bbox.5,
+                &[RangeComputeHints::MaxRanges(10000)],
+            );
 
             assert!(!ranges
                 .iter()
@@ -582,11 +1141,108 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_fallible() {
+        assert_eq!(
+            XZ3SFC::try_new(21, -180.0, -90.0, 0.0, 180.0, 90.0, 1.0).err(),
+            Some(Error::InvalidResolution)
+        );
+        assert_eq!(
+            XZ3SFC::try_new(12, -180.0, -90.0, 1.0, 180.0, 90.0, 1.0).err(),
+            Some(Error::InvalidBounds)
+        );
+
+        let sfc = XZ3SFC::try_new(12, -180.0, -90.0, 0.0, 180.0, 90.0, 100000.0).unwrap();
+        assert_eq!(
+            sfc.try_index(-80.0, -45.0, 1000.0, -78.8, -40.0, 1000.0),
+            Ok(3_681_700_138)
+        );
+        assert_eq!(
+            sfc.try_index(-80.0, -45.0, 1000.0, -78.8, -40.0, 100001.0),
+            Err(Error::OutOfBounds)
+        );
+        assert_eq!(
+            sfc.try_index(-80.0, -45.0, 1000.0, -78.8, -40.0, 900.0),
+            Err(Error::InvertedBox)
+        );
+        assert_eq!(
+            sfc.try_ranges(-80.0, -45.0, 900.0, -78.8, -40.0, f64::NAN, &[])
+                .err(),
+            Some(Error::NotFinite)
+        );
+
+        // The largest g does not overflow.
+        let sfc = XZ3SFC::try_new(XZ3SFC::MAX_G, -180.0, -90.0, 0.0, 180.0, 90.0, 1.0).unwrap();
+        assert!(sfc.index(179.9, 89.9, 0.9, 180.0, 90.0, 1.0) > 0);
+    }
+
+    #[test]
+    fn test_ranges_for_boxes() {
+        let sfc = XZ3SFC::wgs84(12, 0.0, 100_000.0);
+        let boxes = [
+            (-80.0, 35.0, 900.0, -75.0, 40.0, 1100.0),
+            (10.0, 45.0, 5000.0, 12.0, 47.0, 5100.0),
+        ];
+        let ranges = sfc.ranges_for_boxes(&boxes, &[]);
+
+        assert!(ranges.windows(2).all(|w| w[0].upper() < w[1].lower()));
+        for &(xmin, ymin, zmin) in &[(-78.0, 37.0, 1000.0), (11.0, 46.0, 5050.0)] {
+            let index = sfc.index(xmin, ymin, zmin, xmin + 0.5, ymin + 0.5, zmin + 10.0);
+            assert!(ranges
+                .iter()
+                .any(|r| r.lower() <= index && index <= r.upper()));
+        }
+        assert!(sfc.ranges_for_boxes(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn test_antimeridian_ranges() {
+        let sfc = XZ3SFC::wgs84(12, 0.0, 100_000.0);
+        let ranges = sfc.ranges(170.0, -10.0, 900.0, -170.0, 10.0, 1100.0, &[]);
+
+        assert!(ranges.windows(2).all(|w| w[0].upper() < w[1].lower()));
+        for &(xmin, xmax) in &[(175.0, 176.0), (-176.0, -175.0)] {
+            let index = sfc.index(xmin, 0.0, 1000.0, xmax, 1.0, 1000.0);
+            assert!(ranges
+                .iter()
+                .any(|r| r.lower() <= index && index <= r.upper()));
+        }
+    }
+
+    #[test]
+    fn test_bounds_policy() {
+        let sfc = XZ3SFC::wgs84(12, 0.0, 100_000.0).with_bounds_policy(BoundsPolicy::Clamp);
+        assert_eq!(
+            sfc.try_index(-80.0, -45.0, -10.0, -78.8, -40.0, 1000.0),
+            Ok(sfc.index(-80.0, -45.0, 0.0, -78.8, -40.0, 1000.0))
+        );
+
+        let sfc = sfc.with_bounds_policy(BoundsPolicy::WrapLongitude);
+        assert_eq!(
+            sfc.index(190.0, 10.0, 1000.0, 192.0, 12.0, 1000.0),
+            sfc.index(-170.0, 10.0, 1000.0, -168.0, 12.0, 1000.0)
+        );
+
+        let polygon = sfc.index(-175.0, 10.0, 1000.0, -174.0, 11.0, 1000.0);
+        let ranges = sfc
+            .try_ranges(170.0, 9.0, 900.0, 190.0, 12.0, 1100.0, &[])
+            .unwrap();
+        assert!(ranges
+            .iter()
+            .any(|r| r.lower() <= polygon && polygon <= r.upper()));
+
+        let sfc = sfc.with_bounds_policy(BoundsPolicy::Reject);
+        assert_eq!(
+            sfc.try_index(190.0, 10.0, 1000.0, 192.0, 12.0, 1000.0),
+            Err(Error::OutOfBounds)
+        );
+    }
+
     #[test]
     fn test_queries() {
         let sfc = XZ3SFC::wgs84(12, 0.0, 100_000.0);
 
-        let ranges = sfc.ranges(-80.0, -45.0, 900.0, -78.8, -40.0, 1100.0, None);
+        let ranges = sfc.ranges(-80.0, -45.0, 900.0, -78.8, -40.0, 1100.0, &[]);
 
         assert_eq!(ranges.len(), 912);
 
@@ -594,4 +1250,97 @@ mod tests {
 
         assert_eq!(ranges.last().map(|r| r.upper()), Some(3_682_578_823));
     }
+
+    #[test]
+    fn test_ranges_within_distance() {
+        let sfc = XZ3SFC::wgs84(12, 0.0, 1_000.0);
+        let ranges = sfc.ranges_within_distance(0.0, 90.0, 100_000.0, 100.0, 200.0, &[]);
+        let contains = |index| {
+            ranges
+                .iter()
+                .any(|r| r.lower() <= index && index <= r.upper())
+        };
+
+        assert!(contains(sfc.index(10.0, 89.5, 150.0, 11.0, 89.6, 160.0)));
+        assert!(contains(
+            sfc.index(-170.0, 89.5, 150.0, -169.0, 89.6, 160.0)
+        ));
+        assert!(!contains(sfc.index(10.0, 89.5, 500.0, 11.0, 89.6, 510.0)));
+        assert!(!contains(sfc.index(10.0, 80.0, 150.0, 11.0, 81.0, 160.0)));
+
+        assert_eq!(
+            sfc.try_ranges_within_distance(0.0, 90.0, 100_000.0, 100.0, 200.0, &[]),
+            Ok(ranges)
+        );
+        assert_eq!(
+            sfc.try_ranges_within_distance(0.0, 90.0, 100_000.0, 100.0, 2_000.0, &[]),
+            Err(Error::OutOfBounds)
+        );
+        let meters = XZ3SFC::new(12, -1e6, -1e6, 0.0, 1e6, 1e6, 1_000.0);
+        assert_eq!(
+            meters.try_ranges_within_distance(0.0, 0.0, 1_000.0, 100.0, 200.0, &[]),
+            Err(Error::InvalidBounds)
+        );
+    }
+
+    #[test]
+    fn test_range_cursor() {
+        let sfc = XZ3SFC::wgs84(8, 0.0, 100_000.0);
+        let boxes = [(-80.0, 35.0, 900.0, -75.0, 40.0, 1100.0)];
+
+        assert_eq!(
+            sfc.range_cursor(&boxes, &[]).collect::<Vec<_>>(),
+            sfc.ranges_for_boxes(&boxes, &[])
+        );
+
+        let mut cursor = sfc.range_cursor(&boxes, &[]);
+        let first = cursor.next_batch(Budget::Nodes(1));
+        assert!(first.len() <= 1);
+        assert!(!cursor.is_finished());
+
+        let hints = [RangeComputeHints::MaxRecurse(4)];
+        let mut cursor = sfc.range_cursor(&boxes, &hints);
+        let mut paged = Vec::new();
+        while !cursor.is_finished() {
+            paged.extend(cursor.next_batch(Budget::Ranges(5)));
+        }
+        assert_eq!(paged, sfc.ranges_for_boxes(&boxes, &hints));
+        assert_ne!(paged, sfc.ranges_for_boxes(&boxes, &[]));
+    }
+
+    #[test]
+    fn test_element() {
+        let sfc = XZ3SFC::wgs84(12, 0.0, 100_000.0);
+        assert_eq!(
+            sfc.element(0),
+            Some(XZ3Element {
+                level: 0,
+                x_min: -180.0,
+                y_min: -90.0,
+                z_min: 0.0,
+                x_max: 540.0,
+                y_max: 270.0,
+                z_max: 200_000.0
+            })
+        );
+
+        let last = div_floor(8_u64.pow(13) - 1, 7) - 1;
+        assert_eq!(sfc.element(last).map(|e| e.level), Some(12));
+        assert_eq!(sfc.element(last + 1), None);
+
+        for &(x_min, y_min, z_min, x_max, y_max, z_max) in &[
+            (-80.0, -45.0, 1000.0, -78.8, -40.0, 1000.0),
+            (80.0, 25.0, 2000.0, 87.8, 40.0, 2000.0),
+            (10.0, 10.0, 50_000.0, 10.0, 10.0, 50_000.0),
+        ] {
+            let index = sfc.index(x_min, y_min, z_min, x_max, y_max, z_max);
+            let element = sfc.element(index).unwrap();
+            assert!(element.x_min <= x_min && element.y_min <= y_min && element.z_min <= z_min);
+            assert!(x_max <= element.x_max && y_max <= element.y_max && z_max <= element.z_max);
+            assert_eq!(
+                element.x_max - element.x_min,
+                720.0 * 0.5_f64.powi(element.level as i32)
+            );
+        }
+    }
 }
@@ -0,0 +1,69 @@
+//
+// Copyright 2020, Gobsmacked Labs, LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Which cells of a grid count as the neighbors of a cell.
+
+/// The neighbors of a cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Adjacency {
+    /// Cells sharing an edge in 2D or a face in 3D, 4 or 6 neighbors.
+    Face,
+    /// Cells sharing an edge or a corner, 8 neighbors in 2D or 26 in 3D.
+    All,
+}
+
+impl Adjacency {
+    /// Whether the cell at `offsets` cells along each dimension is a neighbor.
+    pub(crate) fn includes(self, offsets: &[i64]) -> bool {
+        let moved = offsets.iter().filter(|&&d| d != 0).count();
+        match self {
+            Adjacency::Face => moved == 1,
+            Adjacency::All => moved > 0,
+        }
+    }
+}
+
+/// Move `delta` cells from `cell` in a dimension of `cells` cells, wrapping around
+/// if `wrap`. Returns `None` when moving off the grid.
+pub(crate) fn step(cell: u32, delta: i64, cells: u32, wrap: bool) -> Option<u32> {
+    let cell = i64::from(cell) + delta;
+    let cells = i64::from(cells);
+    if (0..cells).contains(&cell) {
+        Some(cell as u32)
+    } else if wrap {
+        Some(cell.rem_euclid(cells) as u32)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adjacency() {
+        assert!(Adjacency::Face.includes(&[0, -1]));
+        assert!(!Adjacency::Face.includes(&[1, -1]));
+        assert!(Adjacency::All.includes(&[1, -1, 1]));
+        assert!(!Adjacency::All.includes(&[0, 0]));
+
+        assert_eq!(step(0, -1, 8, false), None);
+        assert_eq!(step(0, -1, 8, true), Some(7));
+        assert_eq!(step(7, 1, 8, true), Some(0));
+        assert_eq!(step(7, 1, 8, false), None);
+        assert_eq!(step(3, 1, 8, false), Some(4));
+    }
+}
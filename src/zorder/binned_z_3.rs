@@ -0,0 +1,375 @@
+//
+// Copyright 2020, Gobsmacked Labs, LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A three dimensional Z-Order curve over binned time.
+
+use crate::{
+    binned_time::{BinnedTime, TimePeriod},
+    bounds::BoundsPolicy,
+    error::Error,
+    index_range::KeyRange,
+    zorder::z_3::ZCurve3D,
+    RangeComputeHints,
+};
+use alloc::vec::Vec;
+use core::{convert::Infallible, ops::RangeInclusive};
+
+/// A point and time curve where time is split into bins of a `TimePeriod`, like
+/// geomesa's Z3 index.
+///
+/// The z-value only covers the offset within a bin, so each bin uses the full time
+/// precision of the curve.
+pub struct BinnedZCurve3D {
+    period: TimePeriod,
+    curve: ZCurve3D,
+}
+
+/// The index ranges for a span of time bins. Only bins that the query covers whole
+/// in time share a span, any other span is a single bin.
+#[derive(Debug)]
+pub struct BinnedRanges {
+    /// The time bins.
+    pub bins: RangeInclusive<u16>,
+    /// Whether the query covers the bins whole in time.
+    pub whole_bins: bool,
+    /// The index ranges within each of the bins.
+    pub ranges: Vec<KeyRange>,
+}
+
+impl Default for BinnedZCurve3D {
+    fn default() -> BinnedZCurve3D {
+        BinnedZCurve3D::new(10_000, -180.0, -90.0, 180.0, 90.0, TimePeriod::Week)
+    }
+}
+
+impl BinnedZCurve3D {
+    /// Constructor with bounds on the space and the period of each time bin.
+    #[must_use]
+    pub fn new(g: u32, x_min: f64, y_min: f64, x_max: f64, y_max: f64, period: TimePeriod) -> Self {
+        BinnedZCurve3D {
+            period,
+            curve: ZCurve3D::new(
+                g,
+                x_min,
+                y_min,
+                x_max,
+                y_max,
+                0.0,
+                period.max_offset() as f64,
+            ),
+        }
+    }
+
+    /// Set how `index` and `ranges` treat coordinates outside of the spatial bounds.
+    #[must_use]
+    pub fn with_bounds_policy(mut self, policy: BoundsPolicy) -> Self {
+        self.curve = self.curve.with_bounds_policy(policy);
+        self
+    }
+
+    /// The period of each time bin.
+    #[must_use]
+    pub fn period(&self) -> TimePeriod {
+        self.period
+    }
+
+    /// Index a `x` longitude, `y` latitude, and `t` milliseconds since the Unix
+    /// Epoch, returning the time bin and the index within that bin.
+    ///
+    /// # NOTE:
+    ///   panics if `t` is before the Unix Epoch.
+    #[must_use]
+    pub fn index(&self, x: f64, y: f64, t: i64) -> (u16, u64) {
+        let binned = self.period.bin_time(t);
+        (binned.bin, self.curve.index(x, y, self.offset(binned)))
+    }
+
+    /// Return the x, y and milliseconds since the Unix Epoch from a bin and index.
+    #[must_use]
+    pub fn invert(&self, bin: u16, i: u64) -> (f64, f64, i64) {
+        let (x, y, offset) = self.curve.invert(i);
+        (
+            x,
+            y,
+            self.period.bin_start(bin) + offset as i64 * self.period.offset_unit(),
+        )
+    }
+
+    /// Index a `x` longitude, `y` latitude, and `t` milliseconds since the Unix
+    /// Epoch, returning an error if `t` is before the Unix Epoch or after the last
+    /// bin, or if `x` or `y` is not finite or is outside of the spatial bounds and
+    /// rejected by the `BoundsPolicy`.
+    pub fn try_index(&self, x: f64, y: f64, t: i64) -> Result<(u16, u64), Error> {
+        let binned = self.period.try_bin_time(t)?;
+        Ok((binned.bin, self.curve.try_index(x, y, self.offset(binned))?))
+    }
+
+    /// Return the `KeyRange`s for the time bins that the bounding box and time
+    /// range cover, in bin order. Bins between the first and last bin are whole bins
+    /// in one span with one list of ranges. A box with `x_min > x_max` wraps across
+    /// the x bounds.
+    ///
+    /// The time range is clamped to the bins, so it may start before the Unix
+    /// Epoch. An inverted time range covers no bins.
+    #[must_use]
+    #[allow(clippy::too_many_arguments)]
+    pub fn ranges(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        t_min: i64,
+        t_max: i64,
+        hints: &[RangeComputeHints],
+    ) -> Vec<BinnedRanges> {
+        let t_min = t_min.max(0);
+        let t_max = t_max.min(self.period.max_millis());
+        if t_min > t_max {
+            return Vec::new();
+        }
+
+        let binned = self.binned_ranges(
+            self.period.bin_time(t_min),
+            self.period.bin_time(t_max),
+            |t_min, t_max| -> Result<_, Infallible> {
+                Ok(self
+                    .curve
+                    .ranges(x_min, y_min, x_max, y_max, t_min, t_max, hints))
+            },
+        );
+        match binned {
+            Ok(binned) => binned,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Return the `KeyRange`s for each time bin that the bounding box and time
+    /// range cover, returning an error if the time range is inverted, before the
+    /// Unix Epoch or after the last bin, or if the box is rejected by the
+    /// `ZCurve3D::try_ranges` of the bins.
+    #[allow(clippy::too_many_arguments)]
+    pub fn try_ranges(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        t_min: i64,
+        t_max: i64,
+        hints: &[RangeComputeHints],
+    ) -> Result<Vec<BinnedRanges>, Error> {
+        if t_min > t_max {
+            return Err(Error::InvertedBox);
+        }
+
+        self.binned_ranges(
+            self.period.try_bin_time(t_min)?,
+            self.period.try_bin_time(t_max)?,
+            |t_min, t_max| {
+                self.curve
+                    .try_ranges(x_min, y_min, x_max, y_max, t_min, t_max, hints)
+            },
+        )
+    }
+
+    /// Split the time range from `lower` to `upper` into bins, computing the ranges
+    /// of each bin from its offsets with `bin_ranges`.
+    fn binned_ranges<E>(
+        &self,
+        lower: BinnedTime,
+        upper: BinnedTime,
+        bin_ranges: impl Fn(f64, f64) -> Result<Vec<KeyRange>, E>,
+    ) -> Result<Vec<BinnedRanges>, E> {
+        let max_offset = self.period.max_offset() as f64;
+
+        if lower.bin == upper.bin {
+            return Ok(alloc::vec![BinnedRanges {
+                bins: lower.bin..=lower.bin,
+                whole_bins: false,
+                ranges: bin_ranges(self.offset(lower), self.offset(upper))?,
+            }]);
+        }
+
+        let mut results = Vec::with_capacity(3);
+        results.push(BinnedRanges {
+            bins: lower.bin..=lower.bin,
+            whole_bins: false,
+            ranges: bin_ranges(self.offset(lower), max_offset)?,
+        });
+
+        if upper.bin - lower.bin > 1 {
+            results.push(BinnedRanges {
+                bins: lower.bin + 1..=upper.bin - 1,
+                whole_bins: true,
+                ranges: bin_ranges(0.0, max_offset)?,
+            });
+        }
+
+        results.push(BinnedRanges {
+            bins: upper.bin..=upper.bin,
+            whole_bins: false,
+            ranges: bin_ranges(0.0, self.offset(upper))?,
+        });
+        Ok(results)
+    }
+
+    /// The offset of a binned time on the curve. Offsets past the max offset of the
+    /// period, at the end of a year, are at the max offset.
+    fn offset(&self, binned: BinnedTime) -> f64 {
+        binned.offset.min(self.period.max_offset()) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2020-04-22T19:33:17.829Z
+    const APRIL_22_2020: i64 = 1_587_583_997_829;
+    const DAY: i64 = 86_400_000;
+
+    #[test]
+    fn test_index_and_invert() {
+        let curve = BinnedZCurve3D::new(1024, -180.0, -90.0, 180.0, 90.0, TimePeriod::Day);
+        let (bin, z) = curve.index(2.3522, 48.8566, APRIL_22_2020);
+        assert_eq!(bin, 18_374);
+
+        let (x, y, t) = curve.invert(bin, z);
+        assert!((x - 2.3522).abs() < 0.5);
+        assert!((y - 48.8566).abs() < 0.5);
+        assert!((t - APRIL_22_2020).abs() < DAY / 1024);
+    }
+
+    #[test]
+    fn test_ranges_single_bin() {
+        let curve = BinnedZCurve3D::default();
+        let (bin, z) = curve.index(2.3522, 48.8566, APRIL_22_2020);
+
+        let ranges = curve.ranges(
+            2.35,
+            48.85,
+            2.354,
+            48.857,
+            APRIL_22_2020 - 1000,
+            APRIL_22_2020 + 1000,
+            &[],
+        );
+
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0].bins, bin..=bin);
+        assert!(!ranges[0].whole_bins);
+        assert!(ranges[0]
+            .ranges
+            .iter()
+            .any(|r| r.lower() <= z && z <= r.upper()));
+    }
+
+    #[test]
+    fn test_ranges_many_bins() {
+        let curve = BinnedZCurve3D::new(1024, -180.0, -90.0, 180.0, 90.0, TimePeriod::Day);
+
+        let ranges = curve.ranges(
+            -93.266,
+            44.9777,
+            -93.264,
+            44.9779,
+            APRIL_22_2020,
+            APRIL_22_2020 + 10 * DAY,
+            &[],
+        );
+
+        assert_eq!(ranges.len(), 3);
+        assert_eq!(ranges[0].bins, 18_374..=18_374);
+        assert_eq!(ranges[1].bins, 18_375..=18_383);
+        assert_eq!(ranges[2].bins, 18_384..=18_384);
+        assert!(!ranges[0].whole_bins && ranges[1].whole_bins && !ranges[2].whole_bins);
+
+        for day in 0..=10 {
+            let t = APRIL_22_2020 + day * DAY;
+            let (bin, z) = curve.index(-93.2650, 44.9778, t);
+            let bin_ranges = ranges.iter().find(|r| r.bins.contains(&bin)).unwrap();
+            assert!(bin_ranges
+                .ranges
+                .iter()
+                .any(|r| r.lower() <= z && z <= r.upper()));
+        }
+
+        // Earlier in the day than the query starts.
+        let (bin, z) = curve.index(-93.2650, 44.9778, APRIL_22_2020 - DAY / 2);
+        let first = ranges.iter().find(|r| r.bins.contains(&bin)).unwrap();
+        assert!(!first
+            .ranges
+            .iter()
+            .any(|r| r.lower() <= z && z <= r.upper()));
+    }
+
+    #[test]
+    fn test_ranges_inverted_and_before_epoch() {
+        let curve = BinnedZCurve3D::new(1024, -180.0, -90.0, 180.0, 90.0, TimePeriod::Day);
+
+        assert!(curve
+            .ranges(-1.0, -1.0, 1.0, 1.0, 30 * DAY, DAY, &[])
+            .is_empty());
+        assert!(curve.ranges(-1.0, -1.0, 1.0, 1.0, -DAY, -5, &[]).is_empty());
+        assert_eq!(
+            curve
+                .try_ranges(-1.0, -1.0, 1.0, 1.0, 30 * DAY, DAY, &[])
+                .err(),
+            Some(Error::InvertedBox)
+        );
+
+        let ranges = curve.ranges(-1.0, -1.0, 1.0, 1.0, -5, DAY / 2, &[]);
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0].bins, 0..=0);
+        let (bin, z) = curve.index(0.0, 0.0, 0);
+        assert_eq!(bin, 0);
+        assert!(ranges[0]
+            .ranges
+            .iter()
+            .any(|r| r.lower() <= z && z <= r.upper()));
+
+        assert_eq!(
+            curve
+                .try_ranges(-1.0, -1.0, 1.0, 1.0, -5, DAY / 2, &[])
+                .err(),
+            Some(Error::OutOfBounds)
+        );
+        assert_eq!(curve.try_index(0.0, 0.0, -5), Err(Error::OutOfBounds));
+        assert_eq!(
+            curve.try_index(0.0, 0.0, DAY),
+            Ok(curve.index(0.0, 0.0, DAY))
+        );
+        assert_eq!(curve.try_index(0.0, 91.0, DAY), Err(Error::OutOfBounds));
+    }
+
+    #[test]
+    fn test_end_of_year() {
+        let curve = BinnedZCurve3D::new(1024, -180.0, -90.0, 180.0, 90.0, TimePeriod::Year);
+        // 2020-12-31T12:00:00Z, past 52 weeks into the year.
+        let t = 1_609_416_000_000;
+
+        let (bin, z) = curve.index(0.0, 0.0, t);
+        assert_eq!(bin, 50);
+        assert_eq!(curve.try_index(0.0, 0.0, t), Ok((bin, z)));
+
+        let ranges = curve.ranges(-1.0, -1.0, 1.0, 1.0, t - DAY, t, &[]);
+        assert_eq!(ranges.len(), 1);
+        assert!(ranges[0]
+            .ranges
+            .iter()
+            .any(|r| r.lower() <= z && z <= r.upper()));
+    }
+}
@@ -0,0 +1,272 @@
+//
+// Copyright 2020, Gobsmacked Labs, LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Hierarchical cell ids over Z-order curves, with a sentinel bit marking the level
+//! like S2 cell ids.
+//!
+//! A cell at `level` is a Z-index prefix of `level * DIMENSIONS` bits. Its id is the
+//! prefix, a single 1 bit and then zeros, so every level of the quad or oct tree has
+//! its own id and a cell's leaf Z-indexes are a contiguous span.
+//! ```
+//! use space_time::zorder::{cell_id::CellId, z_2::Z2, z_curve_2d::ZCurve2D};
+//!
+//! let curve = ZCurve2D::new(1 << 20, -180.0, -90.0, 180.0, 90.0);
+//! let leaf = CellId::<Z2>::new(curve.index(2.3522, 48.8566), 31);
+//!
+//! let coarse = leaf.parent_at(20);
+//! assert_eq!(coarse.level(), 20);
+//! assert!(coarse.contains(leaf));
+//! assert_eq!(coarse.children().len(), 4);
+//! assert!(coarse.range_min() <= leaf.range_min() && leaf.range_max() <= coarse.range_max());
+//! ```
+
+use crate::zorder::z_n::ZN;
+use alloc::vec::Vec;
+use core::{
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+};
+
+/// A cell of the quad or oct tree of a Z-order curve.
+pub struct CellId<Z> {
+    id: u64,
+    curve: PhantomData<fn() -> Z>,
+}
+
+impl<Z: ZN> CellId<Z> {
+    /// The cell at `level` that contains the leaf Z-index `z`. Level 0 is the whole
+    /// curve and level `Z::BITS_PER_DIMENSION` a single leaf.
+    ///
+    /// # NOTE:
+    ///   panics if `level` is more than `Z::BITS_PER_DIMENSION`.
+    #[must_use]
+    pub fn new(z: u64, level: u32) -> Self {
+        assert!(level <= Z::BITS_PER_DIMENSION);
+
+        let lsb = Self::lsb(level);
+        let leaves = u64::MAX >> (64 - Z::TOTAL_BITS);
+        let prefix = z & leaves & !(lsb - 1);
+        Self::from_raw((prefix << 1) | lsb)
+    }
+
+    /// The cell with id `id`. Returns `None` if `id` is not a valid cell id.
+    #[must_use]
+    pub fn from_id(id: u64) -> Option<Self> {
+        let shift = u64::from(id.trailing_zeros());
+        let valid = id != 0
+            && shift % Z::DIMENSIONS == 0
+            && u64::from(64 - id.leading_zeros()) <= Z::TOTAL_BITS + 1;
+        if valid {
+            Some(Self::from_raw(id))
+        } else {
+            None
+        }
+    }
+
+    fn from_raw(id: u64) -> Self {
+        CellId {
+            id,
+            curve: PhantomData,
+        }
+    }
+
+    /// The lowest set bit of the ids of cells at `level`.
+    fn lsb(level: u32) -> u64 {
+        1 << (Z::DIMENSIONS * u64::from(Z::BITS_PER_DIMENSION - level))
+    }
+
+    /// The id, for storing as a key.
+    #[must_use]
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The level of the cell, 0 for the whole curve.
+    #[must_use]
+    pub fn level(&self) -> u32 {
+        Z::BITS_PER_DIMENSION - (u64::from(self.id.trailing_zeros()) / Z::DIMENSIONS) as u32
+    }
+
+    /// Whether the cell is a single leaf Z-index.
+    #[must_use]
+    pub fn is_leaf(&self) -> bool {
+        self.id & 1 == 1
+    }
+
+    /// The cell one level up. Returns `None` for the cell of the whole curve.
+    #[must_use]
+    pub fn parent(&self) -> Option<Self> {
+        match self.level() {
+            0 => None,
+            level => Some(self.parent_at(level - 1)),
+        }
+    }
+
+    /// The cell at `level` that contains this cell.
+    ///
+    /// # NOTE:
+    ///   panics if `level` is more than the level of the cell.
+    #[must_use]
+    pub fn parent_at(&self, level: u32) -> Self {
+        assert!(level <= self.level());
+        Self::new(self.range_min(), level)
+    }
+
+    /// The cells one level down, in Z-order. Empty for a leaf.
+    #[must_use]
+    pub fn children(&self) -> Vec<Self> {
+        if self.is_leaf() {
+            return Vec::new();
+        }
+        let level = self.level() + 1;
+        let step = Self::lsb(level);
+        (0..u64::from(Z::QUADRANTS))
+            .map(|quadrant| Self::new(self.range_min() + quadrant * step, level))
+            .collect()
+    }
+
+    /// The first leaf Z-index in the cell.
+    #[must_use]
+    pub fn range_min(&self) -> u64 {
+        (self.id - self.lsb_of_id()) >> 1
+    }
+
+    /// The last leaf Z-index in the cell.
+    #[must_use]
+    pub fn range_max(&self) -> u64 {
+        self.range_min() + (self.lsb_of_id() - 1)
+    }
+
+    fn lsb_of_id(&self) -> u64 {
+        self.id & self.id.wrapping_neg()
+    }
+
+    /// Whether `other` is this cell or one of its descendants.
+    #[must_use]
+    pub fn contains(&self, other: Self) -> bool {
+        self.range_min() <= other.range_min() && other.range_max() <= self.range_max()
+    }
+
+    /// Whether this cell and `other` share any leaf, that is one contains the other.
+    #[must_use]
+    pub fn intersects(&self, other: Self) -> bool {
+        self.range_min() <= other.range_max() && other.range_min() <= self.range_max()
+    }
+}
+
+impl<Z> Clone for CellId<Z> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Z> Copy for CellId<Z> {}
+
+impl<Z> PartialEq for CellId<Z> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<Z> Eq for CellId<Z> {}
+
+impl<Z> PartialOrd for CellId<Z> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<Z> Ord for CellId<Z> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.id.cmp(&other.id)
+    }
+}
+
+impl<Z> Hash for CellId<Z> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl<Z> fmt::Debug for CellId<Z> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CellId")
+            .field(&format_args!("{:#018x}", self.id))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zorder::{z_2::Z2, z_3::Z3};
+
+    #[test]
+    fn test_levels() {
+        let root = CellId::<Z2>::new(0x2000_0000_0000_0001, 0);
+        assert_eq!(root.id(), 1 << 62);
+        assert_eq!(root.level(), 0);
+        assert_eq!((root.range_min(), root.range_max()), (0, (1 << 62) - 1));
+        assert_eq!(root.parent(), None);
+
+        let leaf = CellId::<Z2>::new(0x2000_0000_0000_0001, 31);
+        assert!(leaf.is_leaf());
+        assert_eq!(leaf.level(), 31);
+        assert_eq!(
+            (leaf.range_min(), leaf.range_max()),
+            (0x2000_0000_0000_0001, 0x2000_0000_0000_0001)
+        );
+        assert!(leaf.children().is_empty());
+        assert_eq!(leaf.parent_at(1).range_min(), 0x2000_0000_0000_0000);
+        assert_eq!(leaf.parent_at(1).range_max(), 0x2fff_ffff_ffff_ffff);
+
+        let children = root.children();
+        assert_eq!(children.len(), 4);
+        assert!(children
+            .windows(2)
+            .all(|w| w[0].range_max() + 1 == w[1].range_min()));
+        assert!(children.iter().all(|c| c.parent() == Some(root)));
+        assert!(children[2].contains(leaf) && !children[1].intersects(leaf));
+
+        let top = CellId::<Z3>::new(u64::MAX, 0);
+        assert_eq!(top.id(), 1 << 63);
+        assert_eq!(top.children().len(), 8);
+        assert_eq!(CellId::<Z3>::new(u64::MAX, 21).range_max(), (1 << 63) - 1);
+
+        assert_eq!(CellId::<Z2>::from_id(leaf.id()), Some(leaf));
+        assert_eq!(CellId::<Z2>::from_id(0), None);
+        assert_eq!(CellId::<Z2>::from_id(0b10), None);
+        assert_eq!(CellId::<Z2>::from_id(1 << 63), None);
+        assert_eq!(CellId::<Z3>::from_id(1 << 63), Some(top));
+    }
+
+    #[quickcheck]
+    fn test_parent_contains_child(z: u64, level: u32) -> bool {
+        let level = level % 21 + 1;
+        let cell = CellId::<Z3>::new(z, level);
+        let parent = cell.parent().unwrap();
+        let leaf = CellId::<Z3>::new(z, 21);
+
+        cell.level() == level
+            && parent.contains(cell)
+            && !cell.contains(parent)
+            && cell.intersects(parent)
+            && cell.contains(leaf)
+            && CellId::from_id(cell.id()) == Some(cell)
+            && parent.children().contains(&cell)
+    }
+}
@@ -0,0 +1,348 @@
+//
+// Copyright 2020, Gobsmacked Labs, LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Best-first k-nearest-neighbor search over data keyed by a Z-order curve.
+//!
+//! `KnnSearch` visits the cells of the curve in order of their distance from the
+//! query point and asks a scan callback for the entries in the index range of each
+//! cell. It stops once the next cell is farther away than the kth nearest entry found
+//! so far. Curves describe their cells for one query with `KnnQuery`.
+//! ```
+//! use space_time::{
+//!     geometry::haversine_distance,
+//!     zorder::{knn::KnnSearch, z_curve_2d::ZCurve2D},
+//! };
+//!
+//! let curve = ZCurve2D::new(1 << 16, -180.0, -90.0, 180.0, 90.0);
+//! let cities = [
+//!     ("Paris", 2.3522, 48.8566),
+//!     ("London", -0.1276, 51.5072),
+//!     ("Berlin", 13.405, 52.52),
+//!     ("New York", -74.006, 40.7128),
+//! ];
+//! let mut keyed = cities
+//!     .iter()
+//!     .map(|&(name, x, y)| (curve.index(x, y), name, x, y))
+//!     .collect::<Vec<_>>();
+//! keyed.sort_by_key(|e| e.0);
+//!
+//! // Amsterdam.
+//! let (lon, lat) = (4.9041, 52.3676);
+//! let nearest = KnnSearch::new(2)
+//!     .with_scan_level(20)
+//!     .search(&curve.knn_query(lon, lat), |range| {
+//!         let start = keyed.partition_point(|e| e.0 < range.lower());
+//!         keyed[start..]
+//!             .iter()
+//!             .take_while(|e| e.0 <= range.upper())
+//!             .map(|&(_, name, x, y)| (name, haversine_distance(lon, lat, x, y)))
+//!             .collect::<Vec<_>>()
+//!     });
+//!
+//! assert_eq!(
+//!     nearest.iter().map(|n| n.item).collect::<Vec<_>>(),
+//!     vec!["London", "Paris"]
+//! );
+//! ```
+
+use crate::{
+    index_range::KeyRange,
+    zorder::{cell_id::CellId, z_n::ZN},
+};
+use alloc::{collections::BinaryHeap, vec::Vec};
+use core::cmp::Ordering;
+
+/// The cells of a curve as seen from one query.
+pub trait KnnQuery {
+    /// The Z-order of the curve.
+    type Z: ZN;
+
+    /// The cell that contains every index of the curve.
+    fn root(&self) -> CellId<Self::Z>;
+
+    /// A lower bound on the distance from the query to any point in `cell` that can
+    /// match, or `None` if no point in the cell can match.
+    fn min_distance(&self, cell: CellId<Self::Z>) -> Option<f64>;
+}
+
+/// An entry found by a search and its distance from the query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Neighbor<T> {
+    /// The entry returned by the scan.
+    pub item: T,
+    /// The distance returned by the scan.
+    pub distance: f64,
+}
+
+/// A `Neighbor` in the heap of the nearest entries, ordered by distance.
+struct Candidate<T>(Neighbor<T>);
+
+impl<T> PartialEq for Candidate<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for Candidate<T> {}
+
+impl<T> PartialOrd for Candidate<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Candidate<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.distance.total_cmp(&other.0.distance)
+    }
+}
+
+/// A cell waiting to be visited, ordered so the nearest cell is popped first.
+struct Pending<Z> {
+    distance: f64,
+    cell: CellId<Z>,
+}
+
+impl<Z> PartialEq for Pending<Z> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<Z> Eq for Pending<Z> {}
+
+impl<Z> PartialOrd for Pending<Z> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<Z> Ord for Pending<Z> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .distance
+            .total_cmp(&self.distance)
+            .then_with(|| other.cell.cmp(&self.cell))
+    }
+}
+
+/// Options for a k-nearest-neighbor search.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KnnSearch {
+    k: usize,
+    scan_level: u32,
+    max_distance: f64,
+}
+
+impl KnnSearch {
+    /// A search for the `k` nearest entries at any distance, scanning single cells.
+    #[must_use]
+    pub fn new(k: usize) -> Self {
+        KnnSearch {
+            k,
+            scan_level: u32::MAX,
+            max_distance: f64::INFINITY,
+        }
+    }
+
+    /// Scan whole cells at `level` instead of refining them, for fewer and longer
+    /// scans. Levels past the leaves of the curve are the leaves.
+    #[must_use]
+    pub fn with_scan_level(mut self, level: u32) -> Self {
+        self.scan_level = level;
+        self
+    }
+
+    /// Only return entries at most `max_distance` away. Without it a search for more
+    /// entries than exist visits every cell.
+    #[must_use]
+    pub fn with_max_distance(mut self, max_distance: f64) -> Self {
+        self.max_distance = max_distance;
+        self
+    }
+
+    /// The number of entries to find.
+    #[must_use]
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// The level of the cells that are scanned.
+    #[must_use]
+    pub fn scan_level(&self) -> u32 {
+        self.scan_level
+    }
+
+    /// The largest distance of an entry.
+    #[must_use]
+    pub fn max_distance(&self) -> f64 {
+        self.max_distance
+    }
+
+    /// Find the `k` nearest entries, nearest first.
+    ///
+    /// `scan` is called with the index range of each visited cell and returns the
+    /// entries stored in that range with their exact distance from the query, in
+    /// the units of `KnnQuery::min_distance`. Entries that do not match the query,
+    /// e.g. outside of its time range, should be left out.
+    pub fn search<Q, T, S, I>(&self, query: &Q, mut scan: S) -> Vec<Neighbor<T>>
+    where
+        Q: KnnQuery,
+        S: FnMut(KeyRange) -> I,
+        I: IntoIterator<Item = (T, f64)>,
+    {
+        if self.k == 0 {
+            return Vec::new();
+        }
+        let scan_level = self.scan_level.min(Q::Z::BITS_PER_DIMENSION);
+        let mut nearest: BinaryHeap<Candidate<T>> = BinaryHeap::with_capacity(self.k + 1);
+        let mut cells = BinaryHeap::new();
+
+        let root = query.root();
+        if let Some(distance) = query.min_distance(root) {
+            cells.push(Pending {
+                distance,
+                cell: root,
+            });
+        }
+
+        while let Some(Pending { distance, cell }) = cells.pop() {
+            let bound = match nearest.peek() {
+                Some(kth) if nearest.len() == self.k => kth.0.distance.min(self.max_distance),
+                _ => self.max_distance,
+            };
+            if distance > bound {
+                break;
+            }
+
+            if cell.level() >= scan_level {
+                let entries = scan(KeyRange::covered(cell.range_min(), cell.range_max()));
+                for (item, distance) in entries {
+                    if distance <= self.max_distance {
+                        nearest.push(Candidate(Neighbor { item, distance }));
+                        if nearest.len() > self.k {
+                            nearest.pop();
+                        }
+                    }
+                }
+            } else {
+                for child in cell.children() {
+                    if let Some(distance) = query.min_distance(child) {
+                        if distance <= bound {
+                            cells.push(Pending {
+                                distance,
+                                cell: child,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        nearest.into_sorted_vec().into_iter().map(|c| c.0).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        geometry::haversine_distance,
+        zorder::{z_3::ZCurve3D, z_curve_2d::ZCurve2D},
+    };
+
+    fn points() -> Vec<(f64, f64, f64)> {
+        (0..400)
+            .map(|i| {
+                let i = f64::from(i);
+                (
+                    (i * 37.0) % 360.0 - 180.0,
+                    (i * 23.0) % 170.0 - 85.0,
+                    (i * 11.0) % 100.0,
+                )
+            })
+            .collect()
+    }
+
+    fn brute_force(lon: f64, lat: f64, k: usize, time: Option<(f64, f64)>) -> Vec<f64> {
+        let mut distances = points()
+            .into_iter()
+            .filter(|&(_, _, t)| time.is_none_or(|(t_min, t_max)| t_min <= t && t <= t_max))
+            .map(|(x, y, _)| haversine_distance(lon, lat, x, y))
+            .collect::<Vec<_>>();
+        distances.sort_by(f64::total_cmp);
+        distances.truncate(k);
+        distances
+    }
+
+    #[test]
+    fn test_knn_z2() {
+        let curve = ZCurve2D::new(1 << 10, -180.0, -90.0, 180.0, 90.0);
+        let mut keyed = points()
+            .into_iter()
+            .map(|(x, y, _)| (curve.index(x, y), x, y))
+            .collect::<Vec<_>>();
+        keyed.sort_by_key(|e| e.0);
+
+        for &(lon, lat) in &[(0.0, 0.0), (179.9, 10.0), (-30.0, 89.0)] {
+            let mut scans = 0;
+            let nearest =
+                KnnSearch::new(5)
+                    .with_scan_level(27)
+                    .search(&curve.knn_query(lon, lat), |range| {
+                        scans += 1;
+                        keyed
+                            .iter()
+                            .filter(|e| range.lower() <= e.0 && e.0 <= range.upper())
+                            .map(|&(_, x, y)| ((x, y), haversine_distance(lon, lat, x, y)))
+                            .collect::<Vec<_>>()
+                    });
+
+            let distances = nearest.iter().map(|n| n.distance).collect::<Vec<_>>();
+            assert_eq!(distances, brute_force(lon, lat, 5, None));
+            assert!(scans < 1 << 8);
+        }
+
+        let bounded = KnnSearch::new(5)
+            .with_max_distance(1.0)
+            .search(&curve.knn_query(0.5, 0.5), |_| Vec::<((), f64)>::new());
+        assert!(bounded.is_empty());
+    }
+
+    #[test]
+    fn test_knn_z3() {
+        let curve = ZCurve3D::new(1 << 8, -180.0, -90.0, 180.0, 90.0, 0.0, 100.0);
+        let keyed = points()
+            .into_iter()
+            .map(|(x, y, t)| (curve.index(x, y, t), x, y, t))
+            .collect::<Vec<_>>();
+
+        let (lon, lat, t_min, t_max) = (10.0, 10.0, 20.0, 40.0);
+        let nearest = KnnSearch::new(3).with_scan_level(18).search(
+            &curve.knn_query(lon, lat, t_min, t_max),
+            |range| {
+                keyed
+                    .iter()
+                    .filter(|e| range.lower() <= e.0 && e.0 <= range.upper())
+                    .filter(|e| t_min <= e.3 && e.3 <= t_max)
+                    .map(|&(_, x, y, _)| ((x, y), haversine_distance(lon, lat, x, y)))
+                    .collect::<Vec<_>>()
+            },
+        );
+
+        let distances = nearest.iter().map(|n| n.distance).collect::<Vec<_>>();
+        assert_eq!(distances, brute_force(lon, lat, 3, Some((t_min, t_max))));
+    }
+}
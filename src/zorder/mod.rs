@@ -13,8 +13,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-//! Provides a Z-Order curve implementation of `SpaceFillingCurve2D`.
+//! Provides a Z-Order curve implementation of `SpaceFillingCurve2D`, and the
+//! geomesa-compatible `Z2SFC` and `Z3SFC`.
 
+pub mod adjacency;
+pub mod binned_z_3;
+pub mod cell_id;
+pub mod knn;
+pub mod normalized_dimension;
+pub mod region_coverer;
+pub mod z2_sfc;
+pub mod z3_sfc;
 pub mod z_2;
 pub mod z_3;
 pub mod z_curve_2d;
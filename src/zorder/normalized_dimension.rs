@@ -0,0 +1,140 @@
+//
+// Copyright 2020, Gobsmacked Labs, LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Maps a dimension onto `2^precision` bins.
+//!
+//! Based on [geomesa-z3 NormalizedDimension](https://github.com/locationtech/geomesa/blob/771777d3a9716b04f7dcd27a6b7d1bb822a1b5a7/geomesa-z3/src/main/scala/org/locationtech/geomesa/curve/NormalizedDimension.scala).
+
+#[allow(unused_imports)]
+use num_traits::Float;
+
+/// A dimension from `min` to `max` split into `2^precision` bins, like geomesa's
+/// `BitNormalizedDimension`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NormalizedDimension {
+    min: f64,
+    max: f64,
+    max_index: u32,
+    normalizer: f64,
+    denormalizer: f64,
+}
+
+impl NormalizedDimension {
+    /// Constructor.
+    ///
+    /// # NOTE:
+    ///   panics if `precision` is zero or more than 31.
+    #[must_use]
+    pub fn new(min: f64, max: f64, precision: u32) -> Self {
+        assert!(precision > 0 && precision < 32);
+
+        let bins = 1_u64 << precision;
+        NormalizedDimension {
+            min,
+            max,
+            max_index: (bins - 1) as u32,
+            normalizer: bins as f64 / (max - min),
+            denormalizer: (max - min) / bins as f64,
+        }
+    }
+
+    /// Longitude, from -180 to 180, like geomesa's `NormalizedLon`.
+    #[must_use]
+    pub fn lon(precision: u32) -> Self {
+        Self::new(-180.0, 180.0, precision)
+    }
+
+    /// Latitude, from -90 to 90, like geomesa's `NormalizedLat`.
+    #[must_use]
+    pub fn lat(precision: u32) -> Self {
+        Self::new(-90.0, 90.0, precision)
+    }
+
+    /// Time, from 0 to `max`, like geomesa's `NormalizedTime`.
+    #[must_use]
+    pub fn time(precision: u32, max: f64) -> Self {
+        Self::new(0.0, max, precision)
+    }
+
+    /// The lower bound.
+    #[must_use]
+    pub fn min(&self) -> f64 {
+        self.min
+    }
+
+    /// The upper bound.
+    #[must_use]
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    /// The largest bin.
+    #[must_use]
+    pub fn max_index(&self) -> u32 {
+        self.max_index
+    }
+
+    /// The bin of `x`. Values below `min` are in bin 0 and values above `max` in the
+    /// last bin.
+    #[must_use]
+    pub fn normalize(&self, x: f64) -> u32 {
+        if x >= self.max {
+            self.max_index
+        } else {
+            ((x - self.min) * self.normalizer).floor() as u32
+        }
+    }
+
+    /// The center of bin `i`.
+    #[must_use]
+    pub fn denormalize(&self, i: u32) -> f64 {
+        let i = i.min(self.max_index);
+        self.min + (f64::from(i) + 0.5) * self.denormalizer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let lat = NormalizedDimension::lat(31);
+        assert_eq!(lat.normalize(-90.0), 0);
+        assert_eq!(lat.normalize(0.0), 1 << 30);
+        assert_eq!(lat.normalize(90.0), i32::MAX as u32);
+        assert_eq!(lat.normalize(38.9876), 1_538_882_009);
+        assert_eq!(lat.denormalize(0), -89.999_999_958_090_48);
+
+        let lon = NormalizedDimension::lon(31);
+        assert_eq!(lon.normalize(-75.1234), 625_613_287);
+        assert_eq!(lon.denormalize(625_613_287), -75.123_400_026_932_36);
+        assert_eq!(lon.denormalize(u32::MAX), lon.denormalize(lon.max_index()));
+
+        let time = NormalizedDimension::time(21, 604_800.0);
+        assert_eq!(time.max_index(), 0x1f_ffff);
+        assert_eq!(
+            time.denormalize(time.normalize(300_000.0)),
+            299_999.864_959_716_8
+        );
+    }
+
+    #[quickcheck]
+    fn test_normalize_and_back(x: u32) -> bool {
+        let lon = NormalizedDimension::lon(21);
+        let x = x & lon.max_index();
+        lon.normalize(lon.denormalize(x)) == x
+    }
+}
@@ -0,0 +1,350 @@
+//
+// Copyright 2020, Gobsmacked Labs, LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Covers a region with a bounded number of `CellId`s, like S2's `RegionCoverer`.
+//!
+//! The coverer refines the quad or oct tree of a Z-order curve breadth first, the
+//! same way as `ZN::zranges`, but returns cells instead of key ranges. Levels are
+//! levels of the tree of the `ZN`, so a `ZCurve2D` with a resolution of `2^k` has
+//! its single cells at level `Z2::BITS_PER_DIMENSION` and the whole curve at level
+//! `Z2::BITS_PER_DIMENSION - k`.
+//! ```
+//! use space_time::zorder::{region_coverer::RegionCoverer, z_curve_2d::ZCurve2D};
+//!
+//! let curve = ZCurve2D::new(1 << 16, -180.0, -90.0, 180.0, 90.0);
+//! let coverer = RegionCoverer::new().with_max_cells(8);
+//! let cells = curve.covering(&[(2.25, 48.8, 2.42, 48.9)], &coverer);
+//! assert!(cells.len() <= 8);
+//!
+//! let index = curve.index(2.3522, 48.8566);
+//! assert!(cells
+//!     .iter()
+//!     .any(|c| c.range_min() <= index && index <= c.range_max()));
+//! ```
+
+use crate::{
+    geometry::Relation,
+    index_range::{merge_ranges, KeyRange},
+    zorder::{
+        cell_id::CellId,
+        z_n::{is_contained, is_overlapped, ZN},
+        z_range::ZRange,
+    },
+};
+use alloc::{collections::VecDeque, vec::Vec};
+
+/// Options for covering a region with cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegionCoverer {
+    min_level: u32,
+    max_level: u32,
+    max_cells: usize,
+}
+
+impl Default for RegionCoverer {
+    /// No level limits and at most 8 cells, like S2.
+    fn default() -> Self {
+        RegionCoverer {
+            min_level: 0,
+            max_level: u32::MAX,
+            max_cells: 8,
+        }
+    }
+}
+
+impl RegionCoverer {
+    /// A coverer with no level limits and at most 8 cells.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only use cells at `level` or below. Takes priority over `max_cells`, so a
+    /// covering may have more cells.
+    #[must_use]
+    pub fn with_min_level(mut self, level: u32) -> Self {
+        self.min_level = level;
+        self
+    }
+
+    /// Only use cells at `level` or above. Levels past the leaves of the curve are
+    /// the leaves.
+    #[must_use]
+    pub fn with_max_level(mut self, level: u32) -> Self {
+        self.max_level = level;
+        self
+    }
+
+    /// Stop refining once a covering would have more than `max_cells` cells.
+    #[must_use]
+    pub fn with_max_cells(mut self, max_cells: usize) -> Self {
+        self.max_cells = max_cells;
+        self
+    }
+
+    /// The smallest level of cells.
+    #[must_use]
+    pub fn min_level(&self) -> u32 {
+        self.min_level
+    }
+
+    /// The largest level of cells.
+    #[must_use]
+    pub fn max_level(&self) -> u32 {
+        self.max_level
+    }
+
+    /// The number of cells to stop refining at.
+    #[must_use]
+    pub fn max_cells(&self) -> usize {
+        self.max_cells
+    }
+
+    /// Normalized cells that together cover every Z-index in zbounds.
+    #[must_use]
+    pub fn covering<Z: ZN>(&self, zbounds: &[ZRange]) -> Vec<CellId<Z>> {
+        self.covering_with(&|range| classify::<Z>(range, zbounds))
+    }
+
+    /// Normalized cells that are entirely inside zbounds. May be empty if zbounds
+    /// has no cell between the min and max level.
+    #[must_use]
+    pub fn interior_covering<Z: ZN>(&self, zbounds: &[ZRange]) -> Vec<CellId<Z>> {
+        self.interior_covering_with(&|range| classify::<Z>(range, zbounds))
+    }
+
+    /// Normalized cells that together cover the region of `classify`, which finds
+    /// the relation of the Z-indexes of a cell to the region.
+    #[must_use]
+    pub fn covering_with<Z: ZN>(&self, classify: &dyn Fn(ZRange) -> Relation) -> Vec<CellId<Z>> {
+        self.cover(classify, false)
+    }
+
+    /// Normalized cells that are entirely inside the region of `classify`.
+    #[must_use]
+    pub fn interior_covering_with<Z: ZN>(
+        &self,
+        classify: &dyn Fn(ZRange) -> Relation,
+    ) -> Vec<CellId<Z>> {
+        self.cover(classify, true)
+    }
+
+    fn cover<Z: ZN>(
+        &self,
+        classify: &dyn Fn(ZRange) -> Relation,
+        interior: bool,
+    ) -> Vec<CellId<Z>> {
+        let max_level = self.max_level.min(Z::BITS_PER_DIMENSION);
+        let min_level = self.min_level.min(max_level);
+
+        let mut cells = Vec::new();
+        let mut candidates = VecDeque::new();
+
+        let add = |cell: CellId<Z>,
+                   relation: Relation,
+                   cells: &mut Vec<CellId<Z>>,
+                   candidates: &mut VecDeque<CellId<Z>>| match relation {
+            Relation::Outside => (),
+            Relation::Inside if cell.level() >= min_level => cells.push(cell),
+            Relation::Crossing if cell.level() >= max_level => {
+                if !interior {
+                    cells.push(cell);
+                }
+            }
+            _ => candidates.push_back(cell),
+        };
+
+        let root = CellId::new(0, 0);
+        add(root, classify(zrange(root)), &mut cells, &mut candidates);
+
+        while let Some(cell) = candidates.pop_front() {
+            let children = cell
+                .children()
+                .into_iter()
+                .map(|child| (child, classify(zrange(child))))
+                .filter(|&(_, relation)| relation != Relation::Outside)
+                .collect::<Vec<_>>();
+
+            if cell.level() >= min_level
+                && cells.len() + candidates.len() + children.len() > self.max_cells
+            {
+                if !interior {
+                    cells.push(cell);
+                }
+                continue;
+            }
+            for (child, relation) in children {
+                add(child, relation, &mut cells, &mut candidates);
+            }
+        }
+
+        normalize(cells, min_level)
+    }
+}
+
+/// The key ranges of cells, merging adjacent cells. Every range is `contained` if
+/// `contained` is set, e.g. for an interior covering.
+#[must_use]
+pub fn cell_ranges<Z: ZN>(cells: &[CellId<Z>], contained: bool) -> Vec<KeyRange> {
+    merge_ranges(
+        cells
+            .iter()
+            .map(|cell| KeyRange::new(cell.range_min(), cell.range_max(), contained))
+            .collect(),
+        true,
+    )
+}
+
+/// Sort cells, drop cells inside other cells and replace every complete set of
+/// siblings with their parent, down to `min_level`.
+fn normalize<Z: ZN>(mut cells: Vec<CellId<Z>>, min_level: u32) -> Vec<CellId<Z>> {
+    cells.sort_unstable_by_key(|cell| (cell.range_min(), cell.level()));
+
+    let quadrants = Z::QUADRANTS as usize;
+    let mut normalized: Vec<CellId<Z>> = Vec::with_capacity(cells.len());
+    for cell in cells {
+        if normalized.last().is_some_and(|last| last.contains(cell)) {
+            continue;
+        }
+        normalized.push(cell);
+
+        while normalized.len() >= quadrants {
+            let siblings = &normalized[normalized.len() - quadrants..];
+            let parent = match siblings[0].parent() {
+                Some(parent) if parent.level() >= min_level => parent,
+                _ => break,
+            };
+            if !siblings.iter().all(|s| s.parent() == Some(parent)) {
+                break;
+            }
+            normalized.truncate(normalized.len() - quadrants);
+            normalized.push(parent);
+        }
+    }
+    normalized
+}
+
+fn zrange<Z: ZN>(cell: CellId<Z>) -> ZRange {
+    ZRange {
+        min: cell.range_min(),
+        max: cell.range_max(),
+    }
+}
+
+fn classify<Z: ZN>(range: ZRange, zbounds: &[ZRange]) -> Relation {
+    if is_contained::<Z>(range, zbounds) {
+        Relation::Inside
+    } else if is_overlapped::<Z>(range, zbounds) {
+        Relation::Crossing
+    } else {
+        Relation::Outside
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zorder::z_2::Z2;
+    use alloc::vec;
+
+    fn query() -> [ZRange; 1] {
+        [ZRange {
+            min: Z2::new(3, 5).z(),
+            max: Z2::new(20, 12).z(),
+        }]
+    }
+
+    fn covers(cells: &[CellId<Z2>], x: u32, y: u32) -> bool {
+        let z = Z2::new(x, y).z();
+        cells
+            .iter()
+            .any(|c| c.range_min() <= z && z <= c.range_max())
+    }
+
+    #[test]
+    fn test_covering() {
+        for max_cells in &[1, 4, 8, 20, 100] {
+            let coverer = RegionCoverer::new().with_max_cells(*max_cells);
+            let cells = coverer.covering::<Z2>(&query());
+
+            assert!(cells.len() <= *max_cells);
+            assert!(cells
+                .windows(2)
+                .all(|w| w[0].range_max() < w[1].range_min()));
+            for &(x, y) in &[(3, 5), (20, 12), (10, 8), (3, 12)] {
+                assert!(covers(&cells, x, y));
+            }
+        }
+
+        let exact = RegionCoverer::new()
+            .with_max_cells(usize::MAX)
+            .covering::<Z2>(&query());
+        assert!(!covers(&exact, 2, 5) && !covers(&exact, 21, 12) && !covers(&exact, 10, 13));
+        assert_eq!(
+            cell_ranges(&exact, false),
+            Z2::zranges::<Z2>(&query(), 64, None, Some(64))
+                .into_iter()
+                .map(|r| KeyRange::overlapping(r.lower(), r.upper()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_levels() {
+        let coverer = RegionCoverer::new()
+            .with_min_level(28)
+            .with_max_level(29)
+            .with_max_cells(1);
+        let cells = coverer.covering::<Z2>(&query());
+        assert!(cells.iter().all(|c| c.level() == 28 || c.level() == 29));
+        assert!(cells.len() > 1);
+        for &(x, y) in &[(3, 5), (20, 12), (10, 8)] {
+            assert!(covers(&cells, x, y));
+        }
+
+        // Four siblings inside the region are normalized to their parent.
+        let whole = [ZRange {
+            min: Z2::new(0, 0).z(),
+            max: Z2::new(7, 7).z(),
+        }];
+        assert_eq!(
+            RegionCoverer::new().covering::<Z2>(&whole),
+            vec![CellId::new(0, 28)]
+        );
+        assert_eq!(
+            RegionCoverer::new()
+                .with_min_level(30)
+                .covering::<Z2>(&whole)
+                .len(),
+            16
+        );
+    }
+
+    #[test]
+    fn test_interior_covering() {
+        let coverer = RegionCoverer::new().with_max_cells(usize::MAX);
+        let cells = coverer.interior_covering::<Z2>(&query());
+        assert!(!cells.is_empty());
+        for cell in &cells {
+            assert_eq!(classify::<Z2>(zrange(*cell), &query()), Relation::Inside);
+        }
+        assert!(covers(&cells, 3, 5) && covers(&cells, 20, 12));
+        assert!(cell_ranges(&cells, true).iter().all(|r| r.contained()));
+
+        let coarse = coverer.with_max_level(28).interior_covering::<Z2>(&query());
+        assert!(coarse.iter().all(|c| c.level() <= 28));
+        assert!(coarse.len() < cells.len());
+    }
+}
@@ -0,0 +1,282 @@
+//
+// Copyright 2020, Gobsmacked Labs, LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A point curve whose keys match geomesa's `Z2SFC`.
+//!
+//! Based on [geomesa-z3 Z2SFC](https://github.com/locationtech/geomesa/blob/771777d3a9716b04f7dcd27a6b7d1bb822a1b5a7/geomesa-z3/src/main/scala/org/locationtech/geomesa/curve/Z2SFC.scala).
+//! Unlike `ZCurve2D`, each dimension is split into `2^precision` bins and latitude
+//! increases with the row.
+
+use crate::{
+    bounds::{split_x_interval, BoundsPolicy},
+    curve::SpaceFillingCurve2D,
+    error::Error,
+    index_range::KeyRange,
+    zorder::{
+        normalized_dimension::NormalizedDimension,
+        z_2::Z2,
+        z_n::{hinted_zranges, DEFAULT_RECURSE, ZN},
+        z_range::ZRange,
+    },
+    RangeComputeHints, RangeHints,
+};
+use alloc::vec::Vec;
+
+/// Longitude and latitude curve with the same keys as geomesa's `Z2SFC` for the same
+/// precision.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Z2SFC {
+    lon: NormalizedDimension,
+    lat: NormalizedDimension,
+    policy: BoundsPolicy,
+}
+
+impl Default for Z2SFC {
+    /// Geomesa's default precision of 31 bits.
+    fn default() -> Self {
+        Z2SFC::new(31)
+    }
+}
+
+impl Z2SFC {
+    /// Constructor with `precision` bits per dimension.
+    ///
+    /// # NOTE:
+    ///   panics if `precision` is zero or more than 31.
+    #[must_use]
+    pub fn new(precision: u32) -> Self {
+        Z2SFC {
+            lon: NormalizedDimension::lon(precision),
+            lat: NormalizedDimension::lat(precision),
+            policy: BoundsPolicy::default(),
+        }
+    }
+
+    /// Fallible constructor, returning `Error::InvalidResolution` if `precision` is
+    /// zero or more than 31.
+    pub fn try_new(precision: u32) -> Result<Self, Error> {
+        if precision == 0 || precision > Z2::BITS_PER_DIMENSION {
+            return Err(Error::InvalidResolution);
+        }
+        Ok(Self::new(precision))
+    }
+
+    /// Set how `index` and `ranges` treat coordinates outside of the curve bounds.
+    /// `BoundsPolicy::Clamp` matches geomesa's lenient indexing.
+    #[must_use]
+    pub fn with_bounds_policy(mut self, policy: BoundsPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// How coordinates outside of the curve bounds are treated.
+    #[must_use]
+    pub fn bounds_policy(&self) -> BoundsPolicy {
+        self.policy
+    }
+
+    /// Index a `x` longitude and `y` latitude.
+    #[must_use]
+    pub fn index(&self, x: f64, y: f64) -> u64 {
+        let x = self.policy.constrain_x(x, self.lon.min(), self.lon.max());
+        let y = self.policy.constrain(y, self.lat.min(), self.lat.max());
+        Z2::new(self.lon.normalize(x), self.lat.normalize(y)).z()
+    }
+
+    /// Index a `x` longitude and `y` latitude, returning an error if either is not
+    /// finite or is outside of the curve bounds and rejected by the `BoundsPolicy`.
+    pub fn try_index(&self, x: f64, y: f64) -> Result<u64, Error> {
+        let x = self.policy.check_x(x, self.lon.min(), self.lon.max())?;
+        let y = self.policy.check(y, self.lat.min(), self.lat.max())?;
+        Ok(self.index(x, y))
+    }
+
+    /// Return the center of the cell of an index.
+    #[must_use]
+    pub fn invert(&self, z: u64) -> (f64, f64) {
+        let (x, y) = Z2::new_from_zorder(z).decode();
+        (self.lon.denormalize(x), self.lat.denormalize(y))
+    }
+
+    /// Return the index ranges that cover the bounding box. Without hints these are
+    /// the ranges of geomesa's `Z2SFC.ranges`, which recurses `DEFAULT_RECURSE`
+    /// levels. A box with `x_min > x_max` wraps across the antimeridian.
+    #[must_use]
+    pub fn ranges(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        self.ranges_for_boxes(&[(x_min, y_min, x_max, y_max)], hints)
+    }
+
+    /// Return one merged list of index ranges covering all of the `(x_min, y_min,
+    /// x_max, y_max)` bounding boxes.
+    #[must_use]
+    pub fn ranges_for_boxes(
+        &self,
+        boxes: &[(f64, f64, f64, f64)],
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        let (lon, lat) = (self.lon, self.lat);
+        let zbounds = boxes
+            .iter()
+            .flat_map(|&(x_min, y_min, x_max, y_max)| {
+                let (x_min, x_max) =
+                    self.policy
+                        .constrain_x_point_interval(x_min, x_max, lon.min(), lon.max());
+                let (y_min, y_max) =
+                    self.policy
+                        .constrain_interval(y_min, y_max, lat.min(), lat.max());
+
+                split_x_interval(x_min, x_max, lon.min(), lon.max()).map(move |(x_min, x_max)| {
+                    ZRange {
+                        min: Z2::new(lon.normalize(x_min), lat.normalize(y_min)).z(),
+                        max: Z2::new(lon.normalize(x_max), lat.normalize(y_max)).z(),
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if zbounds.is_empty() {
+            return Vec::new();
+        }
+
+        let hints = RangeHints::new(hints);
+        hints.coalesce(hinted_zranges::<Z2>(
+            &zbounds,
+            hints.precision,
+            hints.max_ranges,
+            Some(hints.max_recurse.unwrap_or(DEFAULT_RECURSE)),
+        ))
+    }
+}
+
+impl SpaceFillingCurve2D for Z2SFC {
+    fn index(&self, x: f64, y: f64) -> u64 {
+        Z2SFC::index(self, x, y)
+    }
+
+    fn bounds(&self) -> (f64, f64, f64, f64) {
+        (
+            self.lon.min(),
+            self.lat.min(),
+            self.lon.max(),
+            self.lat.max(),
+        )
+    }
+
+    fn ranges(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        Z2SFC::ranges(self, x_min, y_min, x_max, y_max, hints)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Keys of geomesa's Z2SFC.scala and NormalizedDimension.scala at commit 771777d3,
+    // evaluated outside of this crate. They are not copied from geomesa's test suite.
+    #[test]
+    fn test_geomesa_keys() {
+        let sfc = Z2SFC::default();
+        assert_eq!(sfc.index(-180.0, -90.0), 0);
+        assert_eq!(sfc.index(180.0, 90.0), 0x3fff_ffff_ffff_ffff);
+        assert_eq!(sfc.index(0.0, 0.0), 0x3000_0000_0000_0000);
+        assert_eq!(sfc.index(-75.1234, 38.9876), 0x269b_9ac6_2bc3_f697);
+        assert_eq!(sfc.index(2.3522, 48.8566), 0x3808_dbbc_034c_876d);
+        assert_eq!(sfc.index(139.6917, 35.6895), 0x3783_6c47_025a_6040);
+        assert_eq!(Z2SFC::new(12).index(-75.1234, 38.9876), 10_120_811);
+
+        let (x, y) = sfc.invert(sfc.index(-75.1234, 38.9876));
+        assert!((x + 75.1234).abs() < 1e-6 && (y - 38.9876).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_bounds() {
+        let sfc = Z2SFC::default();
+        assert_eq!(sfc.try_index(180.1, 0.0), Err(Error::OutOfBounds));
+        assert_eq!(sfc.try_index(0.0, f64::NAN), Err(Error::NotFinite));
+        assert_eq!(
+            sfc.with_bounds_policy(BoundsPolicy::Clamp)
+                .try_index(180.1, 90.5),
+            Ok(sfc.index(180.0, 90.0))
+        );
+        assert_eq!(Z2SFC::try_new(32), Err(Error::InvalidResolution));
+    }
+
+    #[test]
+    fn test_ranges() {
+        let sfc = Z2SFC::default();
+        let ranges = sfc.ranges(-80.0, 35.0, -75.0, 40.0, &[]);
+
+        assert!(ranges.windows(2).all(|w| w[0].upper() + 1 < w[1].lower()));
+        for &(x, y) in &[(-77.5, 37.5), (-80.0, 35.0), (-75.0, 40.0)] {
+            let index = sfc.index(x, y);
+            assert!(ranges
+                .iter()
+                .any(|r| r.lower() <= index && index <= r.upper()));
+        }
+        let outside = sfc.index(-70.0, 37.5);
+        assert!(!ranges
+            .iter()
+            .any(|r| r.lower() <= outside && outside <= r.upper()));
+
+        // One whole cell of a coarse curve is a single contained range.
+        let coarse = Z2SFC::new(2);
+        assert_eq!(
+            coarse.ranges(0.0, 0.0, 89.9, 44.9, &[]),
+            alloc::vec![KeyRange::covered(12, 12)]
+        );
+    }
+
+    // Ranges of geomesa's `ZN.zranges` at commit 771777d3 with its defaults (precision
+    // 64, no range limit, `ZN.DefaultRecurse` levels), evaluated outside of this crate.
+    #[test]
+    fn test_geomesa_ranges() {
+        let sfc = Z2SFC::default();
+        let expected = alloc::vec![
+            KeyRange::overlapping(0x0fd0_0000_0000_0000, 0x0fdf_ffff_ffff_ffff),
+            KeyRange::overlapping(0x0ff0_0000_0000_0000, 0x0fff_ffff_ffff_ffff),
+            KeyRange::overlapping(0x1a80_0000_0000_0000, 0x1a8f_ffff_ffff_ffff),
+            KeyRange::overlapping(0x1aa0_0000_0000_0000, 0x1aaf_ffff_ffff_ffff),
+            KeyRange::overlapping(0x2550_0000_0000_0000, 0x255f_ffff_ffff_ffff),
+            KeyRange::overlapping(0x2570_0000_0000_0000, 0x257f_ffff_ffff_ffff),
+            KeyRange::overlapping(0x3000_0000_0000_0000, 0x300f_ffff_ffff_ffff),
+            KeyRange::overlapping(0x3020_0000_0000_0000, 0x302f_ffff_ffff_ffff),
+        ];
+        assert_eq!(sfc.ranges(-10.0, -10.0, 10.0, 10.0, &[]), expected);
+        assert_eq!(
+            sfc.ranges(
+                -10.0,
+                -10.0,
+                10.0,
+                10.0,
+                &[RangeComputeHints::MaxRecurse(DEFAULT_RECURSE)]
+            ),
+            expected
+        );
+    }
+}
@@ -0,0 +1,355 @@
+//
+// Copyright 2020, Gobsmacked Labs, LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A point and time curve whose keys match geomesa's `Z3SFC`.
+//!
+//! Based on [geomesa-z3 Z3SFC](https://github.com/locationtech/geomesa/blob/771777d3a9716b04f7dcd27a6b7d1bb822a1b5a7/geomesa-z3/src/main/scala/org/locationtech/geomesa/curve/Z3SFC.scala).
+//! Time is the offset into a time bin, in the units of the `TimePeriod`, as given by
+//! `TimePeriod::bin_time`.
+
+use crate::{
+    binned_time::TimePeriod,
+    bounds::{split_x_interval, BoundsPolicy},
+    curve::SpaceTimeFillingCurve,
+    error::Error,
+    index_range::KeyRange,
+    zorder::{
+        normalized_dimension::NormalizedDimension,
+        z_3::Z3,
+        z_n::{hinted_zranges, DEFAULT_RECURSE, ZN},
+        z_range::ZRange,
+    },
+    RangeComputeHints, RangeHints,
+};
+use alloc::vec::Vec;
+
+/// Longitude, latitude and time curve with the same keys as geomesa's `Z3SFC` for the
+/// same period and precision.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Z3SFC {
+    period: TimePeriod,
+    lon: NormalizedDimension,
+    lat: NormalizedDimension,
+    time: NormalizedDimension,
+    policy: BoundsPolicy,
+}
+
+impl Z3SFC {
+    /// Constructor with `precision` bits per dimension. Geomesa's default precision is
+    /// 21 bits.
+    ///
+    /// # NOTE:
+    ///   panics if `precision` is zero or more than 21.
+    #[must_use]
+    pub fn new(period: TimePeriod, precision: u32) -> Self {
+        assert!(precision > 0 && precision <= Z3::BITS_PER_DIMENSION);
+
+        Z3SFC {
+            period,
+            lon: NormalizedDimension::lon(precision),
+            lat: NormalizedDimension::lat(precision),
+            time: NormalizedDimension::time(precision, period.max_offset() as f64),
+            policy: BoundsPolicy::default(),
+        }
+    }
+
+    /// Fallible constructor, returning `Error::InvalidResolution` if `precision` is
+    /// zero or more than 21.
+    pub fn try_new(period: TimePeriod, precision: u32) -> Result<Self, Error> {
+        if precision == 0 || precision > Z3::BITS_PER_DIMENSION {
+            return Err(Error::InvalidResolution);
+        }
+        Ok(Self::new(period, precision))
+    }
+
+    /// Set how `index` and `ranges` treat coordinates outside of the curve bounds.
+    /// `BoundsPolicy::Clamp` matches geomesa's lenient indexing.
+    #[must_use]
+    pub fn with_bounds_policy(mut self, policy: BoundsPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// How coordinates outside of the curve bounds are treated.
+    #[must_use]
+    pub fn bounds_policy(&self) -> BoundsPolicy {
+        self.policy
+    }
+
+    /// The period of each time bin.
+    #[must_use]
+    pub fn period(&self) -> TimePeriod {
+        self.period
+    }
+
+    /// Index a `x` longitude, `y` latitude and `t` offset into a time bin.
+    #[must_use]
+    pub fn index(&self, x: f64, y: f64, t: i64) -> u64 {
+        let x = self.policy.constrain_x(x, self.lon.min(), self.lon.max());
+        let y = self.policy.constrain(y, self.lat.min(), self.lat.max());
+        let t = self
+            .policy
+            .constrain(t as f64, self.time.min(), self.time.max());
+        Z3::new(
+            self.lon.normalize(x),
+            self.lat.normalize(y),
+            self.time.normalize(t),
+        )
+        .z()
+    }
+
+    /// Index a `x` longitude, `y` latitude and `t` offset into a time bin, returning
+    /// an error if any value is not finite or is outside of the curve bounds and
+    /// rejected by the `BoundsPolicy`.
+    pub fn try_index(&self, x: f64, y: f64, t: i64) -> Result<u64, Error> {
+        let x = self.policy.check_x(x, self.lon.min(), self.lon.max())?;
+        let y = self.policy.check(y, self.lat.min(), self.lat.max())?;
+        let t = self
+            .policy
+            .check(t as f64, self.time.min(), self.time.max())?;
+        Ok(self.index(x, y, t as i64))
+    }
+
+    /// Return the center of the cell of an index, with the time offset truncated.
+    #[must_use]
+    pub fn invert(&self, z: u64) -> (f64, f64, i64) {
+        let (x, y, t) = Z3::new_from_raw(z).decode();
+        (
+            self.lon.denormalize(x),
+            self.lat.denormalize(y),
+            self.time.denormalize(t) as i64,
+        )
+    }
+
+    /// Return the index ranges that cover the bounding box and time offsets. Without
+    /// hints these are the ranges of geomesa's `Z3SFC.ranges`, which recurses
+    /// `DEFAULT_RECURSE` levels. A box with `x_min > x_max` wraps across the
+    /// antimeridian.
+    #[must_use]
+    #[allow(clippy::too_many_arguments)]
+    pub fn ranges(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        t_min: i64,
+        t_max: i64,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        self.ranges_for_boxes(&[(x_min, y_min, x_max, y_max)], &[(t_min, t_max)], hints)
+    }
+
+    /// Return one merged list of index ranges covering every combination of the
+    /// `(x_min, y_min, x_max, y_max)` bounding boxes and `(t_min, t_max)` offsets.
+    #[must_use]
+    pub fn ranges_for_boxes(
+        &self,
+        boxes: &[(f64, f64, f64, f64)],
+        times: &[(i64, i64)],
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        let (lon, lat, time) = (self.lon, self.lat, self.time);
+        let mut zbounds = Vec::with_capacity(boxes.len() * times.len());
+        for &(x_min, y_min, x_max, y_max) in boxes {
+            let (x_min, x_max) =
+                self.policy
+                    .constrain_x_point_interval(x_min, x_max, lon.min(), lon.max());
+            let (y_min, y_max) = self
+                .policy
+                .constrain_interval(y_min, y_max, lat.min(), lat.max());
+
+            for &(t_min, t_max) in times {
+                let (t_min, t_max) = self.policy.constrain_interval(
+                    t_min as f64,
+                    t_max as f64,
+                    time.min(),
+                    time.max(),
+                );
+
+                for (x_min, x_max) in split_x_interval(x_min, x_max, lon.min(), lon.max()) {
+                    zbounds.push(ZRange {
+                        min: Z3::new(
+                            lon.normalize(x_min),
+                            lat.normalize(y_min),
+                            time.normalize(t_min),
+                        )
+                        .z(),
+                        max: Z3::new(
+                            lon.normalize(x_max),
+                            lat.normalize(y_max),
+                            time.normalize(t_max),
+                        )
+                        .z(),
+                    });
+                }
+            }
+        }
+
+        if zbounds.is_empty() {
+            return Vec::new();
+        }
+
+        let hints = RangeHints::new(hints);
+        hints.coalesce(hinted_zranges::<Z3>(
+            &zbounds,
+            hints.precision,
+            hints.max_ranges,
+            Some(hints.max_recurse.unwrap_or(DEFAULT_RECURSE)),
+        ))
+    }
+}
+
+impl SpaceTimeFillingCurve for Z3SFC {
+    fn index(&self, x: f64, y: f64, t: f64) -> u64 {
+        Z3SFC::index(self, x, y, t as i64)
+    }
+
+    fn bounds(&self) -> (f64, f64, f64, f64) {
+        (
+            self.lon.min(),
+            self.lat.min(),
+            self.lon.max(),
+            self.lat.max(),
+        )
+    }
+
+    fn ranges(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        t_min: f64,
+        t_max: f64,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        Z3SFC::ranges(
+            self,
+            x_min,
+            y_min,
+            x_max,
+            y_max,
+            t_min as i64,
+            t_max as i64,
+            hints,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Keys of geomesa's Z3SFC.scala, NormalizedDimension.scala and BinnedTime.scala at
+    // commit 771777d3, evaluated outside of this crate. They are not copied from
+    // geomesa's test suite.
+    #[test]
+    fn test_geomesa_keys() {
+        let sfc = Z3SFC::new(TimePeriod::Week, 21);
+        assert_eq!(sfc.index(-180.0, -90.0, 0), 0);
+        assert_eq!(sfc.index(180.0, 90.0, 604_800), 0x7fff_ffff_ffff_ffff);
+        assert_eq!(sfc.index(-75.1234, 38.9876, 300_000), 0x2bb5_dd5d_bc3a_6ddc);
+        assert_eq!(sfc.index(2.3522, 48.8566, 0), 0x3400_4194_d360_00c8);
+        assert_eq!(sfc.index(139.6917, 35.6895, 604_800), 0x7bf4_9eef_2cbe_49ad);
+        assert_eq!(
+            Z3SFC::new(TimePeriod::Day, 21).index(-75.1234, 38.9876, 43_200_000),
+            7_102_542_838_187_435_224
+        );
+
+        // 2020-04-22T19:33:17.829Z, as offsets from `TimePeriod::bin_time`.
+        assert_eq!(
+            Z3SFC::new(TimePeriod::Month, 21).index(2.3522, 48.8566, 1_884_797),
+            0x7520_c195_d3e2_48ec
+        );
+        let year = Z3SFC::new(TimePeriod::Year, 21);
+        assert_eq!(year.index(2.3522, 48.8566, 162_453), 0x3c04_d39c_f3f2_40e8);
+        assert_eq!(
+            year.index(-75.1234, 38.9876, 524_160),
+            0x6bb5_df5d_bcba_6dfc
+        );
+        assert_eq!(
+            year.with_bounds_policy(BoundsPolicy::Clamp)
+                .index(-75.1234, 38.9876, 527_000),
+            0x6bb5_df5d_bcba_6dfc
+        );
+
+        let (x, y, t) = sfc.invert(sfc.index(-75.1234, 38.9876, 300_000));
+        assert!((x + 75.1234).abs() < 1e-3 && (y - 38.9876).abs() < 1e-3);
+        assert_eq!(t, 299_999);
+    }
+
+    #[test]
+    fn test_bounds() {
+        let sfc = Z3SFC::new(TimePeriod::Week, 21);
+        assert_eq!(sfc.try_index(0.0, 0.0, 604_801), Err(Error::OutOfBounds));
+        assert_eq!(sfc.try_index(0.0, 0.0, -1), Err(Error::OutOfBounds));
+        assert_eq!(
+            sfc.with_bounds_policy(BoundsPolicy::Clamp)
+                .try_index(0.0, 0.0, 700_000),
+            Ok(sfc.index(0.0, 0.0, 604_800))
+        );
+        assert_eq!(
+            Z3SFC::try_new(TimePeriod::Week, 22),
+            Err(Error::InvalidResolution)
+        );
+    }
+
+    #[test]
+    fn test_ranges() {
+        let sfc = Z3SFC::new(TimePeriod::Week, 21);
+        let ranges = sfc.ranges_for_boxes(
+            &[(-80.0, 35.0, -75.0, 40.0)],
+            &[(1_000, 2_000), (300_000, 310_000)],
+            &[],
+        );
+
+        assert!(ranges.windows(2).all(|w| w[0].upper() + 1 < w[1].lower()));
+        for &(x, y, t) in &[(-77.5, 37.5, 1_500), (-75.0, 40.0, 305_000)] {
+            let index = sfc.index(x, y, t);
+            assert!(ranges
+                .iter()
+                .any(|r| r.lower() <= index && index <= r.upper()));
+        }
+        assert!(sfc.ranges_for_boxes(&[], &[(0, 1)], &[]).is_empty());
+    }
+
+    // Ranges of geomesa's `ZN.zranges` at commit 771777d3 with its defaults (precision
+    // 64, no range limit, `ZN.DefaultRecurse` levels), evaluated outside of this crate.
+    #[test]
+    fn test_geomesa_ranges() {
+        let sfc = Z3SFC::new(TimePeriod::Week, 21);
+        let expected = alloc::vec![
+            KeyRange::overlapping(0x2bb5_dc00_0000_0000, 0x2bb5_dfff_ffff_ffff),
+            KeyRange::overlapping(0x6291_4c00_0000_0000, 0x6291_4fff_ffff_ffff),
+            KeyRange::overlapping(0x6291_5c00_0000_0000, 0x6291_5fff_ffff_ffff),
+            KeyRange::overlapping(0x6291_cc00_0000_0000, 0x6291_cfff_ffff_ffff),
+            KeyRange::overlapping(0x6291_dc00_0000_0000, 0x6291_dfff_ffff_ffff),
+            KeyRange::overlapping(0x6295_4c00_0000_0000, 0x6295_4fff_ffff_ffff),
+            KeyRange::overlapping(0x6295_5c00_0000_0000, 0x6295_5fff_ffff_ffff),
+            KeyRange::overlapping(0x6295_cc00_0000_0000, 0x6295_cfff_ffff_ffff),
+            KeyRange::overlapping(0x6295_dc00_0000_0000, 0x6295_dfff_ffff_ffff),
+            KeyRange::overlapping(0x62b1_4c00_0000_0000, 0x62b1_4fff_ffff_ffff),
+            KeyRange::overlapping(0x62b1_5c00_0000_0000, 0x62b1_5fff_ffff_ffff),
+            KeyRange::overlapping(0x62b1_cc00_0000_0000, 0x62b1_cfff_ffff_ffff),
+            KeyRange::overlapping(0x62b1_dc00_0000_0000, 0x62b1_dfff_ffff_ffff),
+            KeyRange::overlapping(0x62b5_4c00_0000_0000, 0x62b5_4fff_ffff_ffff),
+        ];
+        assert_eq!(
+            sfc.ranges(-75.2, 38.9, -75.0, 39.1, 300_000, 360_000, &[]),
+            expected
+        );
+    }
+}
@@ -15,7 +15,10 @@
 
 //! A two dimensional Z-Order curve.
 
-use crate::zorder::{z_n::ZN, z_range::ZRange};
+use crate::{
+    error::Error,
+    zorder::{z_n::ZN, z_range::ZRange},
+};
 use core::convert::TryInto;
 
 /// A two dimensional Z-Order curve.
@@ -34,6 +37,15 @@ impl Z2 {
         Self::new_from_zorder(Self::split(x) | Self::split(y) << 1)
     }
 
+    /// Fallible constructor for `Z2`, returning `Error::OutOfBounds` if either value
+    /// is larger than `MAX_MASK`.
+    pub fn try_new(x: u32, y: u32) -> Result<Self, Error> {
+        if u64::from(x) > Self::MAX_MASK || u64::from(y) > Self::MAX_MASK {
+            return Err(Error::OutOfBounds);
+        }
+        Ok(Self::new(x, y))
+    }
+
     /// Create a Z2 directly from the z value.
     #[must_use]
     pub fn new_from_zorder(zorder: u64) -> Self {
@@ -157,6 +169,16 @@ mod tests {
         assert_eq!(Z2::new(0, 3).z, 10);
     }
 
+    #[test]
+    fn test_try_new() {
+        assert_eq!(Z2::try_new(23, 13), Ok(Z2::new(23, 13)));
+        assert_eq!(
+            Z2::try_new(Z2::MAX_MASK as u32 + 1, 0),
+            Err(Error::OutOfBounds)
+        );
+        assert_eq!(Z2::try_new(0, u32::MAX), Err(Error::OutOfBounds));
+    }
+
     #[test]
     fn test_z2_decoding() {
         assert_eq!(Z2::new(23, 13).decode(), (23, 13));
@@ -210,6 +232,37 @@ mod tests {
         assert_eq!(ranges[1].upper(), 27);
     }
 
+    #[test]
+    fn test_zrange_precision() {
+        use crate::{index_range::KeyRange, zorder::z_n::hinted_zranges};
+        use alloc::vec;
+
+        // zbounds is x 0 to 5 and y 0 to 3. Past precision `zranges` covers every
+        // cell, `hinted_zranges` drops the cells outside and keeps crossing cells
+        // as overlapping.
+        let zbounds = [ZRange { min: 0, max: 27 }];
+        assert_eq!(
+            Z2::zranges::<Z2>(&zbounds, 60, None, None),
+            vec![KeyRange::covered(0, 31)]
+        );
+        assert_eq!(
+            hinted_zranges::<Z2>(&zbounds, 60, None, None),
+            vec![KeyRange::covered(0, 19), KeyRange::covered(24, 27)]
+        );
+        assert_eq!(
+            Z2::zranges::<Z2>(&zbounds, 58, None, None),
+            vec![KeyRange::covered(0, 63)]
+        );
+        assert_eq!(
+            hinted_zranges::<Z2>(&zbounds, 58, None, None),
+            vec![KeyRange::overlapping(0, 31)]
+        );
+        assert_eq!(
+            hinted_zranges::<Z2>(&zbounds, 64, None, None),
+            Z2::zranges::<Z2>(&zbounds, 64, None, None)
+        );
+    }
+
     #[test]
     fn test_contains() {
         let z_range_1 = ZRange { min: 0, max: 3 };
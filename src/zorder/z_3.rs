@@ -16,14 +16,27 @@
 //! A three dimensional space filling curve.
 
 use crate::{
-    index_range::IndexRange,
-    zorder::{z_n::ZN, z_range::ZRange},
-    RangeComputeHints,
+    bounds::{split_x_interval, BoundsPolicy},
+    curve::SpaceTimeFillingCurve,
+    error::{check_bounds, check_geographic, Error},
+    geometry::{box_distance, Circle, Relation},
+    index_range::KeyRange,
+    projection::{Identity, Projection},
+    zorder::{
+        adjacency::{step, Adjacency},
+        cell_id::CellId,
+        knn::KnnQuery,
+        region_coverer::RegionCoverer,
+        z_n::{hinted_zranges, is_contained, is_overlapped, ZRangeCursor, ZN},
+        z_range::ZRange,
+    },
+    RangeComputeHints, RangeHints,
 };
-use alloc::{boxed::Box, vec::Vec};
+use alloc::vec::Vec;
 use core::convert::TryInto;
 
 /// Three dimensional space filling curve.
+#[derive(Debug, PartialEq, Eq)]
 pub struct Z3 {
     z: u64,
 }
@@ -47,7 +60,15 @@ impl Z3 {
         Self::combine(self.z >> 2)
     }
 
-    fn decode(&self) -> (u32, u32, u32) {
+    /// Index value.
+    #[must_use]
+    pub fn z(&self) -> u64 {
+        self.z
+    }
+
+    /// Return the user space (un-z-order indexed) values.
+    #[must_use]
+    pub fn decode(&self) -> (u32, u32, u32) {
         (self.d0(), self.d1(), self.d2())
     }
 
@@ -63,6 +84,15 @@ impl Z3 {
         }
     }
 
+    /// Fallible constructor, returning `Error::OutOfBounds` if any value is larger
+    /// than `MAX_MASK`.
+    pub fn try_new(x: u32, y: u32, z: u32) -> Result<Self, Error> {
+        if [x, y, z].iter().any(|v| u64::from(*v) > Self::MAX_MASK) {
+            return Err(Error::OutOfBounds);
+        }
+        Ok(Self::new(x, y, z))
+    }
+
     fn partial_overlaps(a1: u32, a2: u32, b1: u32, b2: u32) -> bool {
         a1.max(b1) <= a2.min(b2)
     }
@@ -91,7 +121,7 @@ impl ZN for Z3 {
         x = (x ^ (x >> 4)) & 0x100f_00f0_0f00_f00f;
         x = (x ^ (x >> 8)) & 0x1f_0000_ff00_00ff;
         x = (x ^ (x >> 16)) & 0x1f_0000_0000_ffff;
-        x = x ^ (x >> 32);
+        x = (x ^ (x >> 32)) & Self::MAX_MASK;
         x.try_into()
             .expect("values were chosen so x fits into a u32")
     }
@@ -131,83 +161,188 @@ impl ZN for Z3 {
     }
 }
 
-/// A nice interface into a curve to index a point and time.
-pub struct ZCurve3D {
+/// A nice interface into a curve to index a point and time. Cells are equal in the
+/// space of the `Projection` of x and y, by default in x and y. Time is not projected.
+pub struct ZCurve3D<P = Identity> {
     g: u32,
     x_min: f64,
     x_max: f64,
     y_min: f64,
     y_max: f64,
+    z_min: f64,
     z_max: f64,
+    policy: BoundsPolicy,
+    projection: P,
 }
 
 const MAX_RECURSION: usize = 32;
 
 impl Default for ZCurve3D {
     fn default() -> ZCurve3D {
-        ZCurve3D::new(10_000, -180.0, -90.0, 180.0, 90.0, 2_556_057_600.0)
+        ZCurve3D::new(10_000, -180.0, -90.0, 180.0, 90.0, 0.0, 2_556_057_600.0)
     }
 }
 
 impl ZCurve3D {
-    /// Constructor with bounds on the space-time that this index will act on.
+    /// Constructor with bounds on the space-time that this index will act on. Time
+    /// runs from `z_min`, which may be negative, e.g. for times before the Unix epoch.
     #[must_use]
-    pub fn new(g: u32, x_min: f64, y_min: f64, x_max: f64, y_max: f64, z_max: f64) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        g: u32,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        z_min: f64,
+        z_max: f64,
+    ) -> Self {
         ZCurve3D {
             g,
             x_min,
             x_max,
             y_min,
             y_max,
+            z_min,
             z_max,
+            policy: BoundsPolicy::default(),
+            projection: Identity,
+        }
+    }
+
+    /// Fallible constructor, checking that `g` can be indexed in `Z3::BITS_PER_DIMENSION`
+    /// bits and that the bounds are finite and not empty.
+    #[allow(clippy::too_many_arguments)]
+    pub fn try_new(
+        g: u32,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        z_min: f64,
+        z_max: f64,
+    ) -> Result<Self, Error> {
+        if g == 0 || u64::from(g) > Z3::MAX_MASK {
+            return Err(Error::InvalidResolution);
+        }
+        check_bounds(x_min, x_max)?;
+        check_bounds(y_min, y_max)?;
+        check_bounds(z_min, z_max)?;
+
+        Ok(Self::new(g, x_min, y_min, x_max, y_max, z_min, z_max))
+    }
+}
+
+impl<P: Projection> ZCurve3D<P> {
+    /// Index x and y in the space of `projection`. The bounds of the curve stay in
+    /// unprojected coordinates.
+    #[must_use]
+    pub fn with_projection<Q: Projection>(self, projection: Q) -> ZCurve3D<Q> {
+        ZCurve3D {
+            g: self.g,
+            x_min: self.x_min,
+            x_max: self.x_max,
+            y_min: self.y_min,
+            y_max: self.y_max,
+            z_min: self.z_min,
+            z_max: self.z_max,
+            policy: self.policy,
+            projection,
         }
     }
 
+    /// The projection applied to x and y before normalizing them.
+    #[must_use]
+    pub fn projection(&self) -> &P {
+        &self.projection
+    }
+
+    /// Set how `index` and `ranges` treat coordinates outside of the curve bounds.
+    #[must_use]
+    pub fn with_bounds_policy(mut self, policy: BoundsPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// How coordinates outside of the curve bounds are treated.
+    #[must_use]
+    pub fn bounds_policy(&self) -> BoundsPolicy {
+        self.policy
+    }
+
     fn cell_height(&self) -> f64 {
-        (self.y_max - self.y_min) / f64::from(self.g)
+        let p = &self.projection;
+        (p.project_y(self.y_max) - p.project_y(self.y_min)) / f64::from(self.g)
     }
 
     fn cell_width(&self) -> f64 {
-        (self.x_max - self.x_min) / f64::from(self.g)
+        let p = &self.projection;
+        (p.project_x(self.x_max) - p.project_x(self.x_min)) / f64::from(self.g)
     }
 
     fn cell_depth(&self) -> f64 {
-        self.z_max / f64::from(self.g)
+        (self.z_max - self.z_min) / f64::from(self.g)
     }
 
     fn map_to_col(&self, x: f64) -> u32 {
-        ((x - self.x_min) / self.cell_width()) as u32
+        let p = &self.projection;
+        ((p.project_x(x) - p.project_x(self.x_min)) / self.cell_width()) as u32
     }
 
     fn map_to_row(&self, y: f64) -> u32 {
-        ((self.y_max - y) / self.cell_height()) as u32
+        let p = &self.projection;
+        ((p.project_y(self.y_max) - p.project_y(y)) / self.cell_height()) as u32
     }
 
     fn time_to_depth(&self, z: f64) -> u32 {
-        (z / self.cell_depth()) as u32
+        ((z - self.z_min) / self.cell_depth()) as u32
+    }
+
+    /// The x of the west edge of a column, which may be fractional. The edges of the
+    /// grid are the curve bounds, even where the projection clamps.
+    fn col_edge(&self, col: f64) -> f64 {
+        if col <= 0.0 {
+            self.x_min
+        } else if col >= f64::from(self.g) {
+            self.x_max
+        } else {
+            let p = &self.projection;
+            p.unproject_x(p.project_x(self.x_min) + col * self.cell_width())
+        }
+    }
+
+    /// The y of the north edge of a row, which may be fractional.
+    fn row_edge(&self, row: f64) -> f64 {
+        if row <= 0.0 {
+            self.y_max
+        } else if row >= f64::from(self.g) {
+            self.y_min
+        } else {
+            let p = &self.projection;
+            p.unproject_y(p.project_y(self.y_max) - row * self.cell_height())
+        }
     }
 
     fn col_to_map(&self, col: u32) -> f64 {
-        (f64::from(col) * self.cell_width() + self.x_min + self.cell_width() / 2.0)
-            .min(self.x_max)
-            .max(self.x_min)
+        self.col_edge(f64::from(col) + 0.5)
     }
 
     fn row_to_map(&self, row: u32) -> f64 {
-        (self.y_max - f64::from(row) * self.cell_height() - self.cell_height() / 2.0)
-            .max(self.y_min)
-            .min(self.y_max)
+        self.row_edge(f64::from(row) + 0.5)
     }
 
     fn depth_to_time(&self, depth: u32) -> f64 {
-        (f64::from(depth) * self.cell_depth() + self.cell_height() / 2.0)
+        (f64::from(depth) * self.cell_depth() + self.z_min + self.cell_depth() / 2.0)
             .min(self.z_max)
-            .max(0.0)
+            .max(self.z_min)
     }
 
     /// Index a `x` longitude, `y` latitude, and a timestamp `t`.
     #[must_use]
     pub fn index(&self, x: f64, y: f64, t: f64) -> u64 {
+        let x = self.policy.constrain_x(x, self.x_min, self.x_max);
+        let y = self.policy.constrain(y, self.y_min, self.y_max);
+        let t = self.policy.constrain(t, self.z_min, self.z_max);
         Z3::new(
             self.map_to_col(x),
             self.map_to_row(y),
@@ -216,6 +351,17 @@ impl ZCurve3D {
         .z
     }
 
+    /// Index a `x` longitude, `y` latitude, and a timestamp `t`, returning an error
+    /// if any value is not finite or is outside of the curve bounds and rejected by
+    /// the `BoundsPolicy`.
+    pub fn try_index(&self, x: f64, y: f64, t: f64) -> Result<u64, Error> {
+        let x = self.policy.check_x(x, self.x_min, self.x_max)?;
+        let y = self.policy.check(y, self.y_min, self.y_max)?;
+        let t = self.policy.check(t, self.z_min, self.z_max)?;
+
+        Ok(self.index(x, y, t))
+    }
+
     /// Return the x,y,t from an index.
     #[must_use]
     pub fn invert(&self, i: u64) -> (f64, f64, f64) {
@@ -227,7 +373,56 @@ impl ZCurve3D {
         )
     }
 
-    /// Return the `IndexRange`s that cover the bounding box and time range.
+    /// Return the `(x_min, y_min, x_max, y_max, t_min, t_max)` bounds of the cell of
+    /// an index.
+    #[must_use]
+    pub fn cell_bounds(&self, i: u64) -> (f64, f64, f64, f64, f64, f64) {
+        let (col, row, depth) = Z3::new_from_raw(i).decode();
+        let (col, row) = (f64::from(col), f64::from(row));
+        let t_min = self.z_min + f64::from(depth) * self.cell_depth();
+        (
+            self.col_edge(col),
+            self.row_edge(row + 1.0),
+            self.col_edge(col + 1.0),
+            self.row_edge(row),
+            t_min,
+            t_min + self.cell_depth(),
+        )
+    }
+
+    /// Return the indexes of the neighbors of the cell of an index, in ascending
+    /// order. Neighbors wrap across the x bounds if the `BoundsPolicy` is
+    /// `WrapLongitude`, otherwise cells on the edge of the curve have fewer neighbors.
+    #[must_use]
+    pub fn neighbors(&self, i: u64, adjacency: Adjacency) -> Vec<u64> {
+        let (col, row, depth) = Z3::new_from_raw(i).decode();
+        let wrap = self.policy == BoundsPolicy::WrapLongitude;
+
+        let mut neighbors = Vec::with_capacity(26);
+        for d_depth in -1..=1 {
+            for d_row in -1..=1 {
+                for d_col in -1..=1 {
+                    if !adjacency.includes(&[d_col, d_row, d_depth]) {
+                        continue;
+                    }
+                    if let (Some(col), Some(row), Some(depth)) = (
+                        step(col, d_col, self.g, wrap),
+                        step(row, d_row, self.g, false),
+                        step(depth, d_depth, self.g, false),
+                    ) {
+                        neighbors.push(Z3::new(col, row, depth).z);
+                    }
+                }
+            }
+        }
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        neighbors.retain(|&n| n != i);
+        neighbors
+    }
+
+    /// Return the `KeyRange`s that cover the bounding box and time range. A box with
+    /// `x_min > x_max` wraps across the x bounds, e.g. a window over the antimeridian.
     #[must_use]
     #[allow(clippy::too_many_arguments)]
     pub fn ranges(
@@ -239,45 +434,377 @@ impl ZCurve3D {
         t_min: f64,
         t_max: f64,
         hints: &[RangeComputeHints],
-    ) -> Vec<Box<dyn IndexRange>> {
-        let col_min = self.map_to_col(x_min);
-        let row_min = self.map_to_row(y_max);
-        let depth_min: u32 = self.time_to_depth(t_min);
-        let min = Z3::new(col_min, row_min, depth_min);
-
-        let col_max = self.map_to_col(x_max);
-        let row_max = self.map_to_row(y_min);
-        let depth_max: u32 = self.time_to_depth(t_max);
-        let max = Z3::new(col_max, row_max, depth_max);
-
-        let max_recurse = hints
+    ) -> Vec<KeyRange> {
+        self.ranges_for_boxes(&[(x_min, y_min, x_max, y_max, t_min, t_max)], hints)
+    }
+
+    /// Return the `KeyRange`s that cover the bounding box and time range, returning
+    /// an error if y or time is inverted, the box is not finite or outside of the curve
+    /// bounds and rejected by the `BoundsPolicy`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn try_ranges(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        t_min: f64,
+        t_max: f64,
+        hints: &[RangeComputeHints],
+    ) -> Result<Vec<KeyRange>, Error> {
+        self.try_ranges_for_boxes(&[(x_min, y_min, x_max, y_max, t_min, t_max)], hints)
+    }
+
+    /// Return one merged list of `KeyRange`s covering all of the `(x_min, y_min,
+    /// x_max, y_max, t_min, t_max)` boxes.
+    #[must_use]
+    pub fn ranges_for_boxes(
+        &self,
+        boxes: &[(f64, f64, f64, f64, f64, f64)],
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        let zbounds = self.zbounds(boxes);
+        if zbounds.is_empty() {
+            return Vec::new();
+        }
+
+        let hints = RangeHints::new(hints);
+        hints.coalesce(hinted_zranges::<Z3>(
+            &zbounds,
+            hints.precision,
+            hints.max_ranges,
+            hints.max_recurse(MAX_RECURSION),
+        ))
+    }
+
+    /// Return one merged list of `KeyRange`s covering all of the boxes, returning an
+    /// error if y or time is inverted in any box, or a box is not finite or outside of
+    /// the curve bounds and rejected by the `BoundsPolicy`.
+    pub fn try_ranges_for_boxes(
+        &self,
+        boxes: &[(f64, f64, f64, f64, f64, f64)],
+        hints: &[RangeComputeHints],
+    ) -> Result<Vec<KeyRange>, Error> {
+        let boxes = boxes
             .iter()
-            .map(|h| {
-                let RangeComputeHints::MaxRecurse(max) = *h;
-                if max > MAX_RECURSION {
-                    MAX_RECURSION
+            .map(|&(x_min, y_min, x_max, y_max, t_min, t_max)| {
+                let (x_min, x_max) = self
+                    .policy
+                    .check_x_interval(x_min, x_max, self.x_min, self.x_max)?;
+                let (y_min, y_max) = self
+                    .policy
+                    .check_interval(y_min, y_max, self.y_min, self.y_max)?;
+                let (t_min, t_max) = self
+                    .policy
+                    .check_interval(t_min, t_max, self.z_min, self.z_max)?;
+                Ok((x_min, y_min, x_max, y_max, t_min, t_max))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(self.ranges_for_boxes(&boxes, hints))
+    }
+
+    /// Return the `KeyRange`s of the cells within `meters` of a point during the time
+    /// range, with x as longitude and y as latitude. The circle may cross the
+    /// antimeridian or cover a pole. Only ranges of cells entirely inside the circle
+    /// and time range are `contained`. Times outside of the curve are clamped.
+    ///
+    /// # NOTE:
+    ///   panics if the curve bounds are not longitude and latitude, or if any
+    ///   argument is not finite or `meters` is negative.
+    #[must_use]
+    #[allow(clippy::too_many_arguments)]
+    pub fn ranges_within_distance(
+        &self,
+        lon: f64,
+        lat: f64,
+        meters: f64,
+        t_min: f64,
+        t_max: f64,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        assert!(
+            check_geographic(self.x_min, self.y_min, self.x_max, self.y_max).is_ok(),
+            "Distance queries need longitude and latitude bounds"
+        );
+        self.ranges_within_circle(&Circle::new(lon, lat, meters), t_min, t_max, hints)
+    }
+
+    /// Return the `KeyRange`s of the cells within `meters` of a point during the time
+    /// range, returning `Error::InvalidBounds` if the curve bounds are not longitude
+    /// and latitude, the errors of `Circle::try_new`, and an error if the time range
+    /// is inverted, not finite or outside of the curve and rejected by the
+    /// `BoundsPolicy`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn try_ranges_within_distance(
+        &self,
+        lon: f64,
+        lat: f64,
+        meters: f64,
+        t_min: f64,
+        t_max: f64,
+        hints: &[RangeComputeHints],
+    ) -> Result<Vec<KeyRange>, Error> {
+        check_geographic(self.x_min, self.y_min, self.x_max, self.y_max)?;
+        let circle = Circle::try_new(lon, lat, meters)?;
+        let (t_min, t_max) = self
+            .policy
+            .check_interval(t_min, t_max, self.z_min, self.z_max)?;
+        Ok(self.ranges_within_circle(&circle, t_min, t_max, hints))
+    }
+
+    fn ranges_within_circle(
+        &self,
+        circle: &Circle,
+        t_min: f64,
+        t_max: f64,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        let (x_min, y_min, x_max, y_max) = circle.bounding_box();
+        let boxes = split_x_interval(x_min, x_max, self.x_min, self.x_max)
+            .map(|(x_min, x_max)| (x_min, y_min, x_max, y_max, t_min, t_max))
+            .collect::<Vec<_>>();
+
+        self.ranges_for_region(
+            &boxes,
+            &|x_min, y_min, x_max, y_max| circle.relate(x_min, y_min, x_max, y_max),
+            hints,
+        )
+    }
+
+    /// Decompose the cells that `relate` finds inside or crossing a region within the
+    /// `(x_min, y_min, x_max, y_max, t_min, t_max)` boxes. `relate` is given the x and
+    /// y bounds of a cell, the boxes bound the cells in time. Parts of the boxes
+    /// outside of the curve bounds are ignored.
+    fn ranges_for_region(
+        &self,
+        boxes: &[(f64, f64, f64, f64, f64, f64)],
+        relate: &dyn Fn(f64, f64, f64, f64) -> Relation,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        let boxes = boxes
+            .iter()
+            .filter(|&&(x_min, y_min, x_max, y_max, t_min, t_max)| {
+                x_max >= self.x_min
+                    && x_min <= self.x_max
+                    && y_max >= self.y_min
+                    && y_min <= self.y_max
+                    && t_max >= self.z_min
+                    && t_min <= self.z_max
+            })
+            .map(|&(x_min, y_min, x_max, y_max, t_min, t_max)| {
+                let clamp = BoundsPolicy::Clamp;
+                let (x_min, x_max) = clamp.constrain_interval(x_min, x_max, self.x_min, self.x_max);
+                let (y_min, y_max) = clamp.constrain_interval(y_min, y_max, self.y_min, self.y_max);
+                let (t_min, t_max) = clamp.constrain_interval(t_min, t_max, self.z_min, self.z_max);
+                (x_min, y_min, x_max, y_max, t_min, t_max)
+            })
+            .collect::<Vec<_>>();
+
+        let zbounds = self.zbounds(&boxes);
+        if zbounds.is_empty() {
+            return Vec::new();
+        }
+
+        let hints = RangeHints::new(hints);
+        let ranges = <Z3 as ZN>::zranges_with::<Z3>(
+            &zbounds,
+            hints.precision,
+            hints.max_ranges,
+            hints.max_recurse(MAX_RECURSION),
+            &|range| {
+                let window = if is_contained::<Z3>(range, &zbounds) {
+                    Relation::Inside
+                } else if is_overlapped::<Z3>(range, &zbounds) {
+                    Relation::Crossing
                 } else {
-                    max
+                    Relation::Outside
+                };
+                if window == Relation::Outside {
+                    return window;
                 }
-            })
-            .next();
-
-        <Z3 as ZN>::zranges::<Z3>(
-            &[ZRange {
-                min: min.z,
-                max: max.z,
-            }],
-            64,
-            None,
-            max_recurse,
+
+                let (col_min, row_min, _) = Z3::new_from_raw(range.min).decode();
+                let (col_max, row_max, _) = Z3::new_from_raw(range.max).decode();
+                window.and(relate(
+                    self.col_edge(f64::from(col_min)),
+                    self.row_edge(f64::from(row_max) + 1.0),
+                    self.col_edge(f64::from(col_max) + 1.0),
+                    self.row_edge(f64::from(row_min)),
+                ))
+            },
+        );
+        hints.coalesce(ranges)
+    }
+
+    /// The cells of the curve as seen from a point during the time range, with x as
+    /// longitude and y as latitude, for a `KnnSearch` over entries keyed by this
+    /// curve.
+    #[must_use]
+    pub fn knn_query(&self, lon: f64, lat: f64, t_min: f64, t_max: f64) -> ZCurve3DKnn<'_, P> {
+        let (t_min, t_max) =
+            BoundsPolicy::Clamp.constrain_interval(t_min, t_max, self.z_min, self.z_max);
+        ZCurve3DKnn {
+            curve: self,
+            lon,
+            lat,
+            depth_min: self.time_to_depth(t_min),
+            depth_max: self.time_to_depth(t_max),
+        }
+    }
+
+    /// Return a resumable cursor over the `KeyRange`s covering all of the `(x_min,
+    /// y_min, x_max, y_max, t_min, t_max)` boxes, in ascending order. Collecting the
+    /// cursor gives the same ranges as `ranges_for_boxes`. Only the `MaxRecurse` and
+    /// `Precision` hints apply, page with a `Budget` instead of `MaxRanges`.
+    #[must_use]
+    pub fn range_cursor(
+        &self,
+        boxes: &[(f64, f64, f64, f64, f64, f64)],
+        hints: &[RangeComputeHints],
+    ) -> ZRangeCursor<Z3> {
+        let hints = RangeHints::new(hints);
+        ZRangeCursor::new(
+            &self.zbounds(boxes),
+            hints.precision,
+            hints.max_recurse(MAX_RECURSION),
         )
     }
+
+    /// Return normalized cells of `coverer` that together cover all of the
+    /// `(x_min, y_min, x_max, y_max, t_min, t_max)` boxes.
+    #[must_use]
+    pub fn covering(
+        &self,
+        boxes: &[(f64, f64, f64, f64, f64, f64)],
+        coverer: &RegionCoverer,
+    ) -> Vec<CellId<Z3>> {
+        let zbounds = self.zbounds(boxes);
+        if zbounds.is_empty() {
+            return Vec::new();
+        }
+        coverer.covering(&zbounds)
+    }
+
+    /// Return normalized cells of `coverer` that are entirely inside the
+    /// `(x_min, y_min, x_max, y_max, t_min, t_max)` boxes.
+    #[must_use]
+    pub fn interior_covering(
+        &self,
+        boxes: &[(f64, f64, f64, f64, f64, f64)],
+        coverer: &RegionCoverer,
+    ) -> Vec<CellId<Z3>> {
+        let zbounds = self.zbounds(boxes);
+        if zbounds.is_empty() {
+            return Vec::new();
+        }
+        coverer.interior_covering(&zbounds)
+    }
+
+    fn zbounds(&self, boxes: &[(f64, f64, f64, f64, f64, f64)]) -> Vec<ZRange> {
+        boxes
+            .iter()
+            .flat_map(|&(x_min, y_min, x_max, y_max, t_min, t_max)| {
+                let (x_min, x_max) = self
+                    .policy
+                    .constrain_x_point_interval(x_min, x_max, self.x_min, self.x_max);
+                let (y_min, y_max) = self
+                    .policy
+                    .constrain_interval(y_min, y_max, self.y_min, self.y_max);
+                let (t_min, t_max) = self
+                    .policy
+                    .constrain_interval(t_min, t_max, self.z_min, self.z_max);
+
+                let depth_min: u32 = self.time_to_depth(t_min);
+                let depth_max: u32 = self.time_to_depth(t_max);
+                split_x_interval(x_min, x_max, self.x_min, self.x_max).map(move |(x_min, x_max)| {
+                    let col_min = self.map_to_col(x_min);
+                    let row_min = self.map_to_row(y_max);
+                    let min = Z3::new(col_min, row_min, depth_min);
+
+                    let col_max = self.map_to_col(x_max);
+                    let row_max = self.map_to_row(y_min);
+                    let max = Z3::new(col_max, row_max, depth_max);
+
+                    ZRange {
+                        min: min.z,
+                        max: max.z,
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+impl<P: Projection> SpaceTimeFillingCurve for ZCurve3D<P> {
+    fn index(&self, x: f64, y: f64, t: f64) -> u64 {
+        ZCurve3D::index(self, x, y, t)
+    }
+
+    fn bounds(&self) -> (f64, f64, f64, f64) {
+        (self.x_min, self.y_min, self.x_max, self.y_max)
+    }
+
+    fn ranges(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        t_min: f64,
+        t_max: f64,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        ZCurve3D::ranges(self, x_min, y_min, x_max, y_max, t_min, t_max, hints)
+    }
+}
+
+/// The cells of a `ZCurve3D` as seen from a point during a time range, with
+/// distances in meters for a `KnnSearch`.
+pub struct ZCurve3DKnn<'a, P> {
+    curve: &'a ZCurve3D<P>,
+    lon: f64,
+    lat: f64,
+    depth_min: u32,
+    depth_max: u32,
+}
+
+impl<P: Projection> KnnQuery for ZCurve3DKnn<'_, P> {
+    type Z = Z3;
+
+    fn root(&self) -> CellId<Z3> {
+        let bits = 32 - (self.curve.g - 1).leading_zeros();
+        CellId::new(0, Z3::BITS_PER_DIMENSION - bits.min(Z3::BITS_PER_DIMENSION))
+    }
+
+    fn min_distance(&self, cell: CellId<Z3>) -> Option<f64> {
+        let (col_min, row_min, depth_min) = Z3::new_from_raw(cell.range_min()).decode();
+        let (col_max, row_max, depth_max) = Z3::new_from_raw(cell.range_max()).decode();
+        let last = self.curve.g - 1;
+        if col_min > last || row_min > last {
+            return None;
+        }
+        if depth_min > self.depth_max || depth_max < self.depth_min {
+            return None;
+        }
+
+        let curve = self.curve;
+        Some(box_distance(
+            self.lon,
+            self.lat,
+            curve.col_edge(f64::from(col_min)),
+            curve.row_edge(f64::from(row_max.min(last)) + 1.0),
+            curve.col_edge(f64::from(col_max.min(last)) + 1.0),
+            curve.row_edge(f64::from(row_min)),
+        ))
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::index_range::Budget;
 
     #[test]
     fn test_encode() {
@@ -308,7 +835,7 @@ mod tests {
 
     #[test]
     fn test_z3_time_curve() {
-        let curve = ZCurve3D::new(1024, -180.0, -90.0, 180.0, 90.0, 1207632712000.0);
+        let curve = ZCurve3D::new(1024, -180.0, -90.0, 180.0, 90.0, 0.0, 1207632712000.0);
 
         let minneapolis_1995 = curve.index(-93.2650, 44.9778, 792013512000.0); // Minneapolis, 1995.
         let minneapolis_2005 = curve.index(-93.2650, 44.9778, 1107632712000.0); //Minneapolis, 2005.
@@ -331,6 +858,166 @@ mod tests {
             .any(|r| r.lower() <= minneapolis_2005 && r.upper() >= minneapolis_2005));
     }
 
+    #[test]
+    fn test_negative_times() {
+        // 1900 to 2050 in milliseconds since the Unix epoch.
+        let (t_min, t_max) = (-2_208_988_800_000.0, 2_524_608_000_000.0);
+        let curve = ZCurve3D::new(1024, -180.0, -90.0, 180.0, 90.0, t_min, t_max);
+
+        let paris_1950 = curve.index(2.3522, 48.8566, -631_152_000_000.0);
+        let paris_1960 = curve.index(2.3522, 48.8566, -315_619_200_000.0);
+        assert_ne!(paris_1950, paris_1960);
+
+        let query = curve.ranges(
+            2.35,
+            48.85,
+            2.36,
+            48.86,
+            -631_152_100_000.0,
+            -631_151_900_000.0,
+            &[],
+        );
+        assert!(query
+            .iter()
+            .any(|r| r.lower() <= paris_1950 && paris_1950 <= r.upper()));
+        assert!(!query
+            .iter()
+            .any(|r| r.lower() <= paris_1960 && paris_1960 <= r.upper()));
+
+        let cell_depth = (t_max - t_min) / 1024.0;
+        let (_, _, t) = curve.invert(paris_1950);
+        assert!((t + 631_152_000_000.0).abs() <= cell_depth / 2.0);
+        let (_, _, t) = curve.invert(Z3::new(0, 0, 0).z);
+        assert!((t - (t_min + cell_depth / 2.0)).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_cell_bounds_and_neighbors() {
+        let curve = ZCurve3D::new(4, -180.0, -90.0, 180.0, 90.0, 0.0, 400.0);
+        let index = curve.index(-44.0, 10.0, 150.0);
+        assert_eq!(
+            curve.cell_bounds(index),
+            (-90.0, 0.0, 0.0, 45.0, 100.0, 200.0)
+        );
+
+        let neighbors = curve.neighbors(index, Adjacency::All);
+        assert_eq!(neighbors.len(), 26);
+        assert!(neighbors.windows(2).all(|w| w[0] < w[1]));
+        assert!(neighbors.contains(&curve.index(-100.0, 50.0, 50.0)));
+        assert_eq!(curve.neighbors(index, Adjacency::Face).len(), 6);
+        assert!(curve
+            .neighbors(index, Adjacency::Face)
+            .contains(&curve.index(-44.0, 10.0, 250.0)));
+
+        let corner = curve.index(-179.0, 89.0, 0.0);
+        let across = curve.index(179.0, 89.0, 0.0);
+        assert_eq!(curve.neighbors(corner, Adjacency::All).len(), 7);
+        let wrapped = curve
+            .with_bounds_policy(BoundsPolicy::WrapLongitude)
+            .neighbors(corner, Adjacency::All);
+        assert_eq!(wrapped.len(), 11);
+        assert!(wrapped.contains(&across));
+    }
+
+    #[test]
+    fn test_fallible() {
+        assert_eq!(Z3::try_new(1, 1, 1), Ok(Z3::new(1, 1, 1)));
+        assert_eq!(Z3::try_new(0, 0, 1 << 21), Err(Error::OutOfBounds));
+
+        assert!(ZCurve3D::try_new(1 << 21, -180.0, -90.0, 180.0, 90.0, 0.0, 1.0).is_err());
+        assert!(ZCurve3D::try_new(1024, -180.0, -90.0, 180.0, 90.0, 0.0, -1.0).is_err());
+
+        let curve = ZCurve3D::try_new(1024, -180.0, -90.0, 180.0, 90.0, 0.0, 10_000.0).unwrap();
+        assert_eq!(
+            curve.try_index(10.0, 10.0, 1_000.0),
+            Ok(curve.index(10.0, 10.0, 1_000.0))
+        );
+        assert_eq!(
+            curve.try_index(10.0, 10.0, 10_001.0),
+            Err(Error::OutOfBounds)
+        );
+        assert_eq!(
+            curve.try_index(f64::NAN, 10.0, 1_000.0),
+            Err(Error::NotFinite)
+        );
+        assert!(curve
+            .try_ranges(10.0, 10.0, 11.0, 11.0, 900.0, 1_100.0, &[])
+            .is_ok());
+        assert_eq!(
+            curve
+                .try_ranges(10.0, 10.0, 11.0, 11.0, 1_100.0, 900.0, &[])
+                .err(),
+            Some(Error::InvertedBox)
+        );
+    }
+
+    #[test]
+    fn test_bounds_policy() {
+        let curve = ZCurve3D::new(1024, -180.0, -90.0, 180.0, 90.0, 0.0, 10_000.0)
+            .with_bounds_policy(BoundsPolicy::Clamp);
+        assert_eq!(
+            curve.try_index(10.0, 10.0, 10_001.0),
+            Ok(curve.index(10.0, 10.0, 10_000.0))
+        );
+        assert_eq!(curve.index(10.0, 10.0, -5.0), curve.index(10.0, 10.0, 0.0));
+
+        let curve = curve.with_bounds_policy(BoundsPolicy::WrapLongitude);
+        assert_eq!(
+            curve.index(190.0, 10.0, 1_000.0),
+            curve.index(-170.0, 10.0, 1_000.0)
+        );
+        let index = curve.index(-175.0, 10.0, 1_000.0);
+        let ranges = curve
+            .try_ranges(170.0, 9.0, 190.0, 11.0, 900.0, 1_100.0, &[])
+            .unwrap();
+        assert!(ranges
+            .iter()
+            .any(|r| r.lower() <= index && index <= r.upper()));
+    }
+
+    #[test]
+    fn test_antimeridian_ranges() {
+        let curve = ZCurve3D::new(1024, -180.0, -90.0, 180.0, 90.0, 0.0, 10_000.0);
+        let ranges = curve.ranges(170.0, -10.0, -170.0, 10.0, 900.0, 1_100.0, &[]);
+
+        assert!(ranges.windows(2).all(|w| w[0].upper() < w[1].lower()));
+        for &x in &[175.0, -175.0] {
+            let index = curve.index(x, 0.0, 1_000.0);
+            assert!(ranges
+                .iter()
+                .any(|r| r.lower() <= index && index <= r.upper()));
+        }
+        let index = curve.index(0.0, 0.0, 1_000.0);
+        assert!(!ranges
+            .iter()
+            .any(|r| r.lower() <= index && index <= r.upper()));
+    }
+
+    #[test]
+    fn test_ranges_for_boxes() {
+        let curve = ZCurve3D::new(1024, -180.0, -90.0, 180.0, 90.0, 0.0, 10_000.0);
+        let boxes = [
+            (-80.0, 35.0, -75.0, 40.0, 900.0, 1_100.0),
+            (10.0, 45.0, 12.0, 47.0, 5_000.0, 5_100.0),
+        ];
+        let ranges = curve.ranges_for_boxes(&boxes, &[]);
+
+        assert!(ranges.windows(2).all(|w| w[0].upper() + 1 < w[1].lower()));
+        for &(x, y, t) in &[(-77.5, 37.5, 1_000.0), (11.0, 46.0, 5_050.0)] {
+            let index = curve.index(x, y, t);
+            assert!(ranges
+                .iter()
+                .any(|r| r.lower() <= index && index <= r.upper()));
+        }
+        assert!(curve.ranges_for_boxes(&[], &[]).is_empty());
+        assert_eq!(
+            curve
+                .try_ranges_for_boxes(&[boxes[0], (0.0, 0.0, 1.0, 1.0, 10.0, 5.0)], &[])
+                .err(),
+            Some(Error::InvertedBox)
+        );
+    }
+
     #[test]
     fn test_sweep_through_map() {
         let curve = ZCurve3D::default();
@@ -364,4 +1051,82 @@ mod tests {
             lon += 5.0;
         }
     }
+
+    #[test]
+    fn test_ranges_within_distance() {
+        let curve = ZCurve3D::new(64, -180.0, -90.0, 180.0, 90.0, 0.0, 64.0);
+        let circle = Circle::new(178.0, 10.0, 1_000_000.0);
+        let ranges = curve.ranges_within_distance(178.0, 10.0, 1_000_000.0, 10.0, 20.0, &[]);
+        assert!(ranges.iter().any(|r| r.contained()));
+
+        for col in 0..64 {
+            for row in 0..64 {
+                for &depth in &[5, 10, 15, 20, 25] {
+                    let index = Z3::new(col, row, depth).z();
+                    let (x, y, _) = curve.invert(index);
+                    let found = ranges
+                        .iter()
+                        .any(|r| r.lower() <= index && index <= r.upper());
+                    if !(10..=20).contains(&depth) {
+                        assert!(!found);
+                    } else if circle.contains(x, y) {
+                        assert!(found);
+                    }
+                }
+            }
+        }
+
+        assert_eq!(
+            curve.try_ranges_within_distance(178.0, 10.0, 1_000_000.0, 10.0, 20.0, &[]),
+            Ok(ranges)
+        );
+        assert_eq!(
+            curve.try_ranges_within_distance(178.0, 10.0, 1_000_000.0, 20.0, 10.0, &[]),
+            Err(Error::InvertedBox)
+        );
+        let meters = ZCurve3D::new(64, -1e6, -1e6, 1e6, 1e6, 0.0, 64.0);
+        assert_eq!(
+            meters.try_ranges_within_distance(0.0, 0.0, 1_000.0, 10.0, 20.0, &[]),
+            Err(Error::InvalidBounds)
+        );
+    }
+
+    #[test]
+    fn test_range_cursor() {
+        let curve = ZCurve3D::new(1024, -180.0, -90.0, 180.0, 90.0, 0.0, 10_000.0);
+        let boxes = [(-80.0, 35.0, -75.0, 40.0, 900.0, 1_100.0)];
+        let hints = [RangeComputeHints::MaxRecurse(12)];
+
+        let mut cursor = curve.range_cursor(&boxes, &hints);
+        let first = cursor.next_batch(Budget::Ranges(4));
+        assert_eq!(first.len(), 4);
+        assert_eq!(
+            first.into_iter().chain(cursor).collect::<Vec<_>>(),
+            curve.ranges_for_boxes(&boxes, &hints)
+        );
+    }
+
+    #[test]
+    fn test_projection() {
+        use crate::projection::WebMercator;
+
+        let curve = ZCurve3D::new(1024, -180.0, -90.0, 180.0, 90.0, 0.0, 10_000.0)
+            .with_projection(WebMercator);
+        let index = curve.index(2.3522, 48.8566, 5_000.0);
+        let (x_min, y_min, x_max, y_max, t_min, t_max) = curve.cell_bounds(index);
+        assert!(x_min <= 2.3522 && 2.3522 < x_max && y_min <= 48.8566 && 48.8566 < y_max);
+        assert!(t_min <= 5_000.0 && 5_000.0 < t_max);
+        assert!((t_max - t_min - 10_000.0 / 1024.0).abs() < 1e-9);
+
+        // Cells are square in projected meters.
+        let p = WebMercator;
+        let width = p.project_x(x_max) - p.project_x(x_min);
+        assert!((p.project_y(y_max) - p.project_y(y_min) - width).abs() < 1e-6);
+
+        let polar = curve.index(0.0, 89.0, 5_000.0);
+        let ranges = curve.ranges(-1.0, 87.0, 1.0, 90.0, 4_000.0, 6_000.0, &[]);
+        assert!(ranges
+            .iter()
+            .any(|r| r.lower() <= polar && polar <= r.upper()));
+    }
 }
@@ -16,19 +16,37 @@
 //! Implementation of `SpaceFillingCurve2D` for zorder.
 
 use crate::{
-    index_range::IndexRange,
-    zorder::{z_2::Z2, z_n::ZN, z_range::ZRange},
-    RangeComputeHints,
+    bounds::{split_x_interval, BoundsPolicy},
+    curve::SpaceFillingCurve2D,
+    error::{check_bounds, check_geographic, Error},
+    geohash,
+    geometry::{box_distance, Circle, Polygon, Relation},
+    index_range::{IndexRange, KeyRange, RangeSet},
+    projection::{Identity, Projection},
+    tiles::{Tile, WEB_MERCATOR_EXTENT},
+    zorder::{
+        adjacency::{step, Adjacency},
+        cell_id::CellId,
+        knn::KnnQuery,
+        region_coverer::RegionCoverer,
+        z_2::Z2,
+        z_n::{hinted_zranges, ZRangeCursor, ZN},
+        z_range::ZRange,
+    },
+    RangeComputeHints, RangeHints,
 };
-use alloc::{boxed::Box, vec::Vec};
+use alloc::{string::String, vec, vec::Vec};
 
-/// 2-Dimensional `ZCurve`, with x as longitude and y as latitude.
-pub struct ZCurve2D {
+/// 2-Dimensional `ZCurve`, with x as longitude and y as latitude. Cells are equal
+/// in the space of the `Projection`, by default in x and y.
+pub struct ZCurve2D<P = Identity> {
     resolution: u32,
     x_min: f64,
     x_max: f64,
     y_min: f64,
     y_max: f64,
+    policy: BoundsPolicy,
+    projection: P,
 }
 
 impl Default for ZCurve2D {
@@ -39,14 +57,13 @@ impl Default for ZCurve2D {
             x_max: 180.0,
             y_min: -90.0,
             y_max: 90.0,
+            policy: BoundsPolicy::default(),
+            projection: Identity,
         }
     }
 }
 
 impl ZCurve2D {
-    /// Max Recursion constant to use.
-    const MAX_RECURSION: usize = 32;
-
     /// Constructor.
     #[must_use]
     pub fn new(resolution: u32, x_min: f64, y_min: f64, x_max: f64, y_max: f64) -> Self {
@@ -56,45 +73,302 @@ impl ZCurve2D {
             x_max,
             y_min,
             y_max,
+            policy: BoundsPolicy::default(),
+            projection: Identity,
+        }
+    }
+
+    /// A curve over Web Mercator (EPSG:3857) meters whose cells are the map tiles at
+    /// `zoom`.
+    ///
+    /// # NOTE:
+    ///   panics if `zoom` is more than 30.
+    #[must_use]
+    pub fn web_mercator(zoom: u32) -> Self {
+        assert!(zoom < Z2::BITS_PER_DIMENSION);
+        Self::new(
+            1 << zoom,
+            -WEB_MERCATOR_EXTENT,
+            -WEB_MERCATOR_EXTENT,
+            WEB_MERCATOR_EXTENT,
+            WEB_MERCATOR_EXTENT,
+        )
+    }
+
+    /// Fallible constructor, checking that `resolution` can be indexed in
+    /// `Z2::BITS_PER_DIMENSION` bits and that the bounds are finite and not empty.
+    pub fn try_new(
+        resolution: u32,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+    ) -> Result<Self, Error> {
+        if resolution == 0 || u64::from(resolution) > Z2::MAX_MASK {
+            return Err(Error::InvalidResolution);
+        }
+        check_bounds(x_min, x_max)?;
+        check_bounds(y_min, y_max)?;
+
+        Ok(Self::new(resolution, x_min, y_min, x_max, y_max))
+    }
+
+    /// Get the geohash of `len` characters that contains the cell of an index.
+    /// Returns `Error::InvalidBounds` unless the curve has WGS84 bounds, and
+    /// `Error::InvalidResolution` unless the resolution is a power of two with at
+    /// least as many bits as the geohash has longitude bits.
+    pub fn geohash(&self, index: u64, len: usize) -> Result<String, Error> {
+        let bits = self.geohash_bits()?;
+        if len > geohash::MAX_LEN {
+            return Err(Error::InvalidResolution);
+        }
+        let (lon_bits, lat_bits) = geohash::bits(len);
+        if lon_bits > bits {
+            return Err(Error::InvalidResolution);
+        }
+
+        let (col, row) = Z2::new_from_zorder(index).decode();
+        let last = self.resolution - 1;
+        let lat = last - row.min(last);
+        Ok(geohash::from_cells(
+            col.min(last) >> (bits - lon_bits),
+            lat >> (bits - lat_bits),
+            len,
+        ))
+    }
+
+    /// Get the index ranges of every cell in a geohash. The ranges are exact, one
+    /// range when the geohash has an even number of bits and two when it has one
+    /// more longitude bit than latitude bits. Returns the errors of `geohash`, or
+    /// `Error::InvalidGeohash` if `hash` is not a geohash.
+    pub fn geohash_ranges(&self, hash: &str) -> Result<Vec<KeyRange>, Error> {
+        let bits = self.geohash_bits()?;
+        let (lon, lat, len) = geohash::to_cells(hash)?;
+        let (lon_bits, lat_bits) = geohash::bits(len);
+        if lon_bits > bits {
+            return Err(Error::InvalidResolution);
+        }
+
+        let (lon_shift, lat_shift) = (bits - lon_bits, bits - lat_bits);
+        let col_min = lon << lon_shift;
+        let col_max = col_min + ((1 << lon_shift) - 1);
+        let lat_min = lat << lat_shift;
+        let lat_max = lat_min + ((1 << lat_shift) - 1);
+
+        let last = self.resolution - 1;
+        let zbounds = [ZRange {
+            min: Z2::new(col_min, last - lat_max).z(),
+            max: Z2::new(col_max, last - lat_min).z(),
+        }];
+        Ok(Z2::zranges::<Z2>(
+            &zbounds,
+            64,
+            None,
+            Some(Self::MAX_RECURSION),
+        ))
+    }
+
+    /// Get the fewest geohashes, in order, that together cover every index in
+    /// `ranges`. Geohashes are at most as long as the longest geohash no smaller than
+    /// a cell. Returns the errors of `geohash`.
+    pub fn geohash_covering<R: IndexRange>(&self, ranges: &[R]) -> Result<Vec<String>, Error> {
+        let bits = self.geohash_bits()?;
+        let max_len = (2 * bits as usize / 5).min(geohash::MAX_LEN);
+        if max_len == 0 {
+            return Err(Error::InvalidResolution);
+        }
+
+        let keys = ranges
+            .iter()
+            .map(|r| KeyRange::covered(r.lower(), r.upper()))
+            .collect::<RangeSet>();
+
+        let mut hashes = Vec::new();
+        let mut remaining = vec![String::new()];
+        while let Some(hash) = remaining.pop() {
+            let relation = if hash.is_empty() {
+                Relation::Crossing
+            } else {
+                geohash::relate(keys.ranges(), &self.geohash_ranges(&hash)?)
+            };
+            match relation {
+                Relation::Outside => (),
+                Relation::Crossing if hash.len() < max_len => {
+                    remaining.extend(geohash::children(&hash).rev());
+                }
+                _ => hashes.push(hash),
+            }
+        }
+        Ok(hashes)
+    }
+
+    fn geohash_bits(&self) -> Result<u32, Error> {
+        self.quadtree_bits((-180.0, -90.0, 180.0, 90.0))
+    }
+
+    /// Get the index range of every cell in a map tile. Returns `Error::InvalidBounds`
+    /// unless the curve is a `web_mercator` curve and `Error::InvalidResolution` if
+    /// the tile is smaller than a cell.
+    pub fn tile_range(&self, tile: Tile) -> Result<KeyRange, Error> {
+        let bits = self.tile_bits()?;
+        if tile.zoom > bits {
+            return Err(Error::InvalidResolution);
         }
+        let cell = CellId::<Z2>::new(
+            tile.z() << (2 * (bits - tile.zoom)),
+            Z2::BITS_PER_DIMENSION - bits + tile.zoom,
+        );
+        Ok(KeyRange::covered(cell.range_min(), cell.range_max()))
+    }
+
+    /// Get the map tile at `zoom` that contains the cell of an index. Returns the
+    /// errors of `tile_range`.
+    pub fn tile(&self, index: u64, zoom: u32) -> Result<Tile, Error> {
+        let bits = self.tile_bits()?;
+        if zoom > bits {
+            return Err(Error::InvalidResolution);
+        }
+        let (col, row) = Z2::new_from_zorder(index).decode();
+        let last = self.resolution - 1;
+        Ok(Tile::new(
+            zoom,
+            col.min(last) >> (bits - zoom),
+            row.min(last) >> (bits - zoom),
+        ))
+    }
+
+    fn tile_bits(&self) -> Result<u32, Error> {
+        self.quadtree_bits((
+            -WEB_MERCATOR_EXTENT,
+            -WEB_MERCATOR_EXTENT,
+            WEB_MERCATOR_EXTENT,
+            WEB_MERCATOR_EXTENT,
+        ))
+    }
+
+    /// The number of bits of each dimension, if the curve has `bounds` and a power of
+    /// two resolution.
+    fn quadtree_bits(&self, bounds: (f64, f64, f64, f64)) -> Result<u32, Error> {
+        if (self.x_min, self.y_min, self.x_max, self.y_max) != bounds {
+            return Err(Error::InvalidBounds);
+        }
+        if !self.resolution.is_power_of_two() {
+            return Err(Error::InvalidResolution);
+        }
+        Ok(self.resolution.trailing_zeros())
+    }
+}
+
+impl<P: Projection> ZCurve2D<P> {
+    /// Max Recursion constant to use.
+    const MAX_RECURSION: usize = 32;
+
+    /// Index coordinates in the space of `projection`. The bounds of the curve stay
+    /// in unprojected coordinates.
+    #[must_use]
+    pub fn with_projection<Q: Projection>(self, projection: Q) -> ZCurve2D<Q> {
+        ZCurve2D {
+            resolution: self.resolution,
+            x_min: self.x_min,
+            x_max: self.x_max,
+            y_min: self.y_min,
+            y_max: self.y_max,
+            policy: self.policy,
+            projection,
+        }
+    }
+
+    /// The projection applied before normalizing coordinates.
+    #[must_use]
+    pub fn projection(&self) -> &P {
+        &self.projection
+    }
+
+    /// Set how `index` and `ranges` treat coordinates outside of the curve bounds.
+    #[must_use]
+    pub fn with_bounds_policy(mut self, policy: BoundsPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// How coordinates outside of the curve bounds are treated.
+    #[must_use]
+    pub fn bounds_policy(&self) -> BoundsPolicy {
+        self.policy
     }
 
     fn cell_width(&self) -> f64 {
-        (self.x_max - self.x_min) / f64::from(self.resolution)
+        let p = &self.projection;
+        (p.project_x(self.x_max) - p.project_x(self.x_min)) / f64::from(self.resolution)
     }
 
     fn cell_height(&self) -> f64 {
-        (self.y_max - self.y_min) / f64::from(self.resolution)
+        let p = &self.projection;
+        (p.project_y(self.y_max) - p.project_y(self.y_min)) / f64::from(self.resolution)
     }
 
     fn map_to_col(&self, x: f64) -> u32 {
-        ((x - self.x_min) / self.cell_width()) as u32
+        let p = &self.projection;
+        ((p.project_x(x) - p.project_x(self.x_min)) / self.cell_width()) as u32
     }
 
     fn map_to_row(&self, y: f64) -> u32 {
-        ((self.y_max - y) / self.cell_height()) as u32
+        let p = &self.projection;
+        ((p.project_y(self.y_max) - p.project_y(y)) / self.cell_height()) as u32
+    }
+
+    /// The x of the west edge of a column, which may be fractional. The edges of the
+    /// grid are the curve bounds, even where the projection clamps.
+    fn col_edge(&self, col: f64) -> f64 {
+        if col <= 0.0 {
+            self.x_min
+        } else if col >= f64::from(self.resolution) {
+            self.x_max
+        } else {
+            let p = &self.projection;
+            p.unproject_x(p.project_x(self.x_min) + col * self.cell_width())
+        }
+    }
+
+    /// The y of the north edge of a row, which may be fractional.
+    fn row_edge(&self, row: f64) -> f64 {
+        if row <= 0.0 {
+            self.y_max
+        } else if row >= f64::from(self.resolution) {
+            self.y_min
+        } else {
+            let p = &self.projection;
+            p.unproject_y(p.project_y(self.y_max) - row * self.cell_height())
+        }
     }
 
     fn col_to_map(&self, col: u32) -> f64 {
-        (f64::from(col) * self.cell_width() + self.x_min + self.cell_width() / 2.0)
-            .min(self.x_max)
-            .max(self.x_min)
+        self.col_edge(f64::from(col) + 0.5)
     }
 
     fn row_to_map(&self, row: u32) -> f64 {
-        (self.y_max - f64::from(row) * self.cell_height() - self.cell_height() / 2.0)
-            .max(self.y_min)
-            .min(self.y_max)
+        self.row_edge(f64::from(row) + 0.5)
     }
 
     /// Get the index for a point.
     #[must_use]
     pub fn index(&self, x: f64, y: f64) -> u64 {
+        let x = self.policy.constrain_x(x, self.x_min, self.x_max);
+        let y = self.policy.constrain(y, self.y_min, self.y_max);
         let col = self.map_to_col(x);
         let row = self.map_to_row(y);
         Z2::new(col, row).z()
     }
 
+    /// Get the index for a point, returning an error if either coordinate is not
+    /// finite or is outside of the curve bounds and rejected by the `BoundsPolicy`.
+    pub fn try_index(&self, x: f64, y: f64) -> Result<u64, Error> {
+        let x = self.policy.check_x(x, self.x_min, self.x_max)?;
+        let y = self.policy.check(y, self.y_min, self.y_max)?;
+
+        Ok(self.index(x, y))
+    }
+
     /// Get the point for an index.
     #[must_use]
     pub fn point(&self, index: u64) -> (f64, f64) {
@@ -102,7 +376,49 @@ impl ZCurve2D {
         (self.col_to_map(col), self.row_to_map(row))
     }
 
-    /// Get the index ranges for a bounding box.
+    /// Get the `(x_min, y_min, x_max, y_max)` bounds of the cell of an index.
+    #[must_use]
+    pub fn cell_bounds(&self, index: u64) -> (f64, f64, f64, f64) {
+        let (col, row) = Z2::new_from_zorder(index).decode();
+        let (col, row) = (f64::from(col), f64::from(row));
+        (
+            self.col_edge(col),
+            self.row_edge(row + 1.0),
+            self.col_edge(col + 1.0),
+            self.row_edge(row),
+        )
+    }
+
+    /// Get the indexes of the neighbors of the cell of an index, in ascending order.
+    /// Neighbors wrap across the x bounds if the `BoundsPolicy` is `WrapLongitude`,
+    /// otherwise cells on the edge of the curve have fewer neighbors.
+    #[must_use]
+    pub fn neighbors(&self, index: u64, adjacency: Adjacency) -> Vec<u64> {
+        let (col, row) = Z2::new_from_zorder(index).decode();
+        let wrap = self.policy == BoundsPolicy::WrapLongitude;
+
+        let mut neighbors = Vec::with_capacity(8);
+        for d_row in -1..=1 {
+            for d_col in -1..=1 {
+                if !adjacency.includes(&[d_col, d_row]) {
+                    continue;
+                }
+                if let (Some(col), Some(row)) = (
+                    step(col, d_col, self.resolution, wrap),
+                    step(row, d_row, self.resolution, false),
+                ) {
+                    neighbors.push(Z2::new(col, row).z());
+                }
+            }
+        }
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        neighbors.retain(|&n| n != index);
+        neighbors
+    }
+
+    /// Get the index ranges for a bounding box. A box with `x_min > x_max` wraps
+    /// across the x bounds, e.g. a window over the antimeridian.
     #[must_use]
     pub fn ranges(
         &self,
@@ -111,43 +427,339 @@ impl ZCurve2D {
         x_max: f64,
         y_max: f64,
         hints: &[RangeComputeHints],
-    ) -> Vec<Box<dyn IndexRange>> {
-        let col_min = self.map_to_col(x_min);
-        let row_min = self.map_to_row(y_max);
-        let min = Z2::new(col_min, row_min);
+    ) -> Vec<KeyRange> {
+        self.ranges_for_boxes(&[(x_min, y_min, x_max, y_max)], hints)
+    }
+
+    /// Get the index ranges for a bounding box, returning an error if the box is
+    /// inverted in y, not finite or outside of the curve bounds and rejected by the
+    /// `BoundsPolicy`.
+    pub fn try_ranges(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        hints: &[RangeComputeHints],
+    ) -> Result<Vec<KeyRange>, Error> {
+        self.try_ranges_for_boxes(&[(x_min, y_min, x_max, y_max)], hints)
+    }
+
+    /// Get one merged list of index ranges covering all of the `(x_min, y_min, x_max,
+    /// y_max)` bounding boxes.
+    #[must_use]
+    pub fn ranges_for_boxes(
+        &self,
+        boxes: &[(f64, f64, f64, f64)],
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        let zbounds = self.zbounds(boxes);
+        if zbounds.is_empty() {
+            return Vec::new();
+        }
 
-        let col_max = self.map_to_col(x_max);
-        let row_max = self.map_to_row(y_min);
-        let max = Z2::new(col_max, row_max);
+        let hints = RangeHints::new(hints);
+        hints.coalesce(hinted_zranges::<Z2>(
+            &zbounds,
+            hints.precision,
+            hints.max_ranges,
+            hints.max_recurse(Self::MAX_RECURSION),
+        ))
+    }
 
-        let max_recurse = hints
+    /// Get one merged list of index ranges covering all of the bounding boxes,
+    /// returning an error if any box is inverted in y, not finite or outside of the
+    /// curve bounds and rejected by the `BoundsPolicy`.
+    pub fn try_ranges_for_boxes(
+        &self,
+        boxes: &[(f64, f64, f64, f64)],
+        hints: &[RangeComputeHints],
+    ) -> Result<Vec<KeyRange>, Error> {
+        let boxes = boxes
             .iter()
-            .map(|h| {
-                let RangeComputeHints::MaxRecurse(max) = *h;
-                if max > Self::MAX_RECURSION {
-                    Self::MAX_RECURSION
-                } else {
-                    max
+            .map(|&(x_min, y_min, x_max, y_max)| {
+                let (x_min, x_max) = self
+                    .policy
+                    .check_x_interval(x_min, x_max, self.x_min, self.x_max)?;
+                let (y_min, y_max) = self
+                    .policy
+                    .check_interval(y_min, y_max, self.y_min, self.y_max)?;
+                Ok((x_min, y_min, x_max, y_max))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(self.ranges_for_boxes(&boxes, hints))
+    }
+
+    /// Get the index ranges of the cells that are inside or cross the polygon. Only
+    /// ranges of cells entirely inside the polygon are `contained`. Parts of the
+    /// polygon outside of the curve bounds are ignored.
+    #[must_use]
+    pub fn ranges_for_polygon(
+        &self,
+        polygon: &Polygon,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        self.ranges_for_region(
+            &[polygon.bounding_box()],
+            &|x_min, y_min, x_max, y_max| polygon.relate(x_min, y_min, x_max, y_max),
+            hints,
+        )
+    }
+
+    /// Get the index ranges of the cells within `meters` of a point, with x as
+    /// longitude and y as latitude. The circle may cross the antimeridian or cover a
+    /// pole. Only ranges of cells entirely inside the circle are `contained`.
+    ///
+    /// # NOTE:
+    ///   panics if the curve bounds are not longitude and latitude, or if any
+    ///   argument is not finite or `meters` is negative.
+    #[must_use]
+    pub fn ranges_within_distance(
+        &self,
+        lon: f64,
+        lat: f64,
+        meters: f64,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        assert!(
+            check_geographic(self.x_min, self.y_min, self.x_max, self.y_max).is_ok(),
+            "Distance queries need longitude and latitude bounds"
+        );
+        self.ranges_within_circle(&Circle::new(lon, lat, meters), hints)
+    }
+
+    /// Get the index ranges of the cells within `meters` of a point, returning
+    /// `Error::InvalidBounds` if the curve bounds are not longitude and latitude, and
+    /// the errors of `Circle::try_new`.
+    pub fn try_ranges_within_distance(
+        &self,
+        lon: f64,
+        lat: f64,
+        meters: f64,
+        hints: &[RangeComputeHints],
+    ) -> Result<Vec<KeyRange>, Error> {
+        check_geographic(self.x_min, self.y_min, self.x_max, self.y_max)?;
+        let circle = Circle::try_new(lon, lat, meters)?;
+        Ok(self.ranges_within_circle(&circle, hints))
+    }
+
+    fn ranges_within_circle(&self, circle: &Circle, hints: &[RangeComputeHints]) -> Vec<KeyRange> {
+        let (x_min, y_min, x_max, y_max) = circle.bounding_box();
+        let boxes = split_x_interval(x_min, x_max, self.x_min, self.x_max)
+            .map(|(x_min, x_max)| (x_min, y_min, x_max, y_max))
+            .collect::<Vec<_>>();
+
+        self.ranges_for_region(
+            &boxes,
+            &|x_min, y_min, x_max, y_max| circle.relate(x_min, y_min, x_max, y_max),
+            hints,
+        )
+    }
+
+    /// The cells of the curve as seen from a point, with x as longitude and y as
+    /// latitude, for a `KnnSearch` over entries keyed by this curve.
+    #[must_use]
+    pub fn knn_query(&self, lon: f64, lat: f64) -> ZCurve2DKnn<'_, P> {
+        ZCurve2DKnn {
+            curve: self,
+            lon,
+            lat,
+        }
+    }
+
+    /// Decompose the cells that `relate` finds inside or crossing a region within the
+    /// bounding boxes. Parts of the boxes outside of the curve bounds are ignored.
+    fn ranges_for_region(
+        &self,
+        boxes: &[(f64, f64, f64, f64)],
+        relate: &dyn Fn(f64, f64, f64, f64) -> Relation,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        let zbounds = boxes
+            .iter()
+            .filter(|&&(x_min, y_min, x_max, y_max)| {
+                x_max >= self.x_min
+                    && x_min <= self.x_max
+                    && y_max >= self.y_min
+                    && y_min <= self.y_max
+            })
+            .map(|&(x_min, y_min, x_max, y_max)| {
+                let (x_min, x_max) =
+                    BoundsPolicy::Clamp.constrain_interval(x_min, x_max, self.x_min, self.x_max);
+                let (y_min, y_max) =
+                    BoundsPolicy::Clamp.constrain_interval(y_min, y_max, self.y_min, self.y_max);
+                ZRange {
+                    min: Z2::new(self.map_to_col(x_min), self.map_to_row(y_max)).z(),
+                    max: Z2::new(self.map_to_col(x_max), self.map_to_row(y_min)).z(),
                 }
             })
-            .next();
+            .collect::<Vec<_>>();
+        if zbounds.is_empty() {
+            return Vec::new();
+        }
 
-        Z2::zranges::<Z2>(
-            &[ZRange {
-                min: min.z(),
-                max: max.z(),
-            }],
-            64,
-            None,
-            max_recurse,
+        let hints = RangeHints::new(hints);
+        let ranges = Z2::zranges_with::<Z2>(
+            &zbounds,
+            hints.precision,
+            hints.max_ranges,
+            hints.max_recurse(Self::MAX_RECURSION),
+            &|range| {
+                let (col_min, row_min) = Z2::new_from_zorder(range.min).decode();
+                let (col_max, row_max) = Z2::new_from_zorder(range.max).decode();
+
+                relate(
+                    self.col_edge(f64::from(col_min)),
+                    self.row_edge(f64::from(row_max) + 1.0),
+                    self.col_edge(f64::from(col_max) + 1.0),
+                    self.row_edge(f64::from(row_min)),
+                )
+            },
+        );
+        hints.coalesce(ranges)
+    }
+
+    /// Get a resumable cursor over the index ranges covering all of the `(x_min,
+    /// y_min, x_max, y_max)` bounding boxes, in ascending order. Collecting the cursor
+    /// gives the same ranges as `ranges_for_boxes`. Only the `MaxRecurse` and
+    /// `Precision` hints apply, page with a `Budget` instead of `MaxRanges`.
+    #[must_use]
+    pub fn range_cursor(
+        &self,
+        boxes: &[(f64, f64, f64, f64)],
+        hints: &[RangeComputeHints],
+    ) -> ZRangeCursor<Z2> {
+        let hints = RangeHints::new(hints);
+        ZRangeCursor::new(
+            &self.zbounds(boxes),
+            hints.precision,
+            hints.max_recurse(Self::MAX_RECURSION),
         )
     }
+
+    /// Return normalized cells of `coverer` that together cover all of the
+    /// `(x_min, y_min, x_max, y_max)` bounding boxes.
+    #[must_use]
+    pub fn covering(
+        &self,
+        boxes: &[(f64, f64, f64, f64)],
+        coverer: &RegionCoverer,
+    ) -> Vec<CellId<Z2>> {
+        let zbounds = self.zbounds(boxes);
+        if zbounds.is_empty() {
+            return Vec::new();
+        }
+        coverer.covering(&zbounds)
+    }
+
+    /// Return normalized cells of `coverer` that are entirely inside the
+    /// `(x_min, y_min, x_max, y_max)` bounding boxes.
+    #[must_use]
+    pub fn interior_covering(
+        &self,
+        boxes: &[(f64, f64, f64, f64)],
+        coverer: &RegionCoverer,
+    ) -> Vec<CellId<Z2>> {
+        let zbounds = self.zbounds(boxes);
+        if zbounds.is_empty() {
+            return Vec::new();
+        }
+        coverer.interior_covering(&zbounds)
+    }
+
+    fn zbounds(&self, boxes: &[(f64, f64, f64, f64)]) -> Vec<ZRange> {
+        boxes
+            .iter()
+            .flat_map(|&(x_min, y_min, x_max, y_max)| {
+                let (x_min, x_max) = self
+                    .policy
+                    .constrain_x_point_interval(x_min, x_max, self.x_min, self.x_max);
+                let (y_min, y_max) = self
+                    .policy
+                    .constrain_interval(y_min, y_max, self.y_min, self.y_max);
+
+                split_x_interval(x_min, x_max, self.x_min, self.x_max).map(move |(x_min, x_max)| {
+                    let col_min = self.map_to_col(x_min);
+                    let row_min = self.map_to_row(y_max);
+                    let min = Z2::new(col_min, row_min);
+
+                    let col_max = self.map_to_col(x_max);
+                    let row_max = self.map_to_row(y_min);
+                    let max = Z2::new(col_max, row_max);
+
+                    ZRange {
+                        min: min.z(),
+                        max: max.z(),
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+impl<P: Projection> SpaceFillingCurve2D for ZCurve2D<P> {
+    fn index(&self, x: f64, y: f64) -> u64 {
+        ZCurve2D::index(self, x, y)
+    }
+
+    fn bounds(&self) -> (f64, f64, f64, f64) {
+        (self.x_min, self.y_min, self.x_max, self.y_max)
+    }
+
+    fn ranges(
+        &self,
+        x_min: f64,
+        y_min: f64,
+        x_max: f64,
+        y_max: f64,
+        hints: &[RangeComputeHints],
+    ) -> Vec<KeyRange> {
+        ZCurve2D::ranges(self, x_min, y_min, x_max, y_max, hints)
+    }
+}
+
+/// The cells of a `ZCurve2D` as seen from a point, with distances in meters for a
+/// `KnnSearch`.
+pub struct ZCurve2DKnn<'a, P> {
+    curve: &'a ZCurve2D<P>,
+    lon: f64,
+    lat: f64,
+}
+
+impl<P: Projection> KnnQuery for ZCurve2DKnn<'_, P> {
+    type Z = Z2;
+
+    fn root(&self) -> CellId<Z2> {
+        let bits = 32 - (self.curve.resolution - 1).leading_zeros();
+        CellId::new(0, Z2::BITS_PER_DIMENSION - bits.min(Z2::BITS_PER_DIMENSION))
+    }
+
+    fn min_distance(&self, cell: CellId<Z2>) -> Option<f64> {
+        let (col_min, row_min) = Z2::new_from_zorder(cell.range_min()).decode();
+        let (col_max, row_max) = Z2::new_from_zorder(cell.range_max()).decode();
+        let last = self.curve.resolution - 1;
+        if col_min > last || row_min > last {
+            return None;
+        }
+
+        let curve = self.curve;
+        Some(box_distance(
+            self.lon,
+            self.lat,
+            curve.col_edge(f64::from(col_min)),
+            curve.row_edge(f64::from(row_max.min(last)) + 1.0),
+            curve.col_edge(f64::from(col_max.min(last)) + 1.0),
+            curve.row_edge(f64::from(row_min)),
+        ))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::SpaceFillingCurves;
+    use crate::{index_range::Budget, SpaceFillingCurves};
+    use alloc::vec;
 
     #[test]
     fn test_produce_covering_ranges() {
@@ -186,6 +798,349 @@ mod tests {
         assert!(point < (-45.0 + 1.0, -45.0 + 1.0));
     }
 
+    #[test]
+    fn test_cell_bounds_and_neighbors() {
+        let curve = ZCurve2D::new(4, -180.0, -90.0, 180.0, 90.0);
+        let index = curve.index(-44.0, 10.0);
+        assert_eq!(curve.cell_bounds(index), (-90.0, 0.0, 0.0, 45.0));
+
+        let neighbors = curve.neighbors(index, Adjacency::All);
+        assert_eq!(neighbors.len(), 8);
+        assert!(neighbors.windows(2).all(|w| w[0] < w[1]));
+        assert!(neighbors.contains(&curve.index(-100.0, 50.0)));
+        assert!(neighbors.contains(&curve.index(10.0, -10.0)));
+
+        let mut faces = vec![
+            curve.index(-44.0, 50.0),
+            curve.index(-100.0, 10.0),
+            curve.index(10.0, 10.0),
+            curve.index(-44.0, -10.0),
+        ];
+        faces.sort_unstable();
+        assert_eq!(curve.neighbors(index, Adjacency::Face), faces);
+
+        let corner = curve.index(-179.0, 89.0);
+        let across = curve.index(179.0, 89.0);
+        assert_eq!(curve.neighbors(corner, Adjacency::All).len(), 3);
+        let wrapped = curve
+            .with_bounds_policy(BoundsPolicy::WrapLongitude)
+            .neighbors(corner, Adjacency::All);
+        assert_eq!(wrapped.len(), 5);
+        assert!(wrapped.contains(&across));
+    }
+
+    #[test]
+    fn test_geohash() {
+        let curve = ZCurve2D::new(1024, -180.0, -90.0, 180.0, 90.0);
+        let index = curve.index(-5.6, 42.6);
+        assert_eq!(curve.geohash(index, 4), Ok("ezs4".into()));
+        assert_eq!(curve.geohash(index, 1), Ok("e".into()));
+        assert_eq!(curve.geohash(index, 5), Err(Error::InvalidResolution));
+        assert_eq!(curve.geohash(index, 13), Err(Error::InvalidResolution));
+        assert_eq!(
+            curve.geohash(index, usize::MAX),
+            Err(Error::InvalidResolution)
+        );
+        assert_eq!(
+            ZCurve2D::new(1000, -180.0, -90.0, 180.0, 90.0).geohash(index, 4),
+            Err(Error::InvalidResolution)
+        );
+        assert_eq!(
+            ZCurve2D::new(1024, -180.0, -90.0, 180.0, 0.0).geohash(index, 4),
+            Err(Error::InvalidBounds)
+        );
+
+        // Even and odd numbers of bits.
+        assert_eq!(curve.geohash_ranges("ezs4").unwrap().len(), 1);
+        assert_eq!(
+            curve.geohash_ranges("ezs4"),
+            Ok(vec![KeyRange::covered(index, index)])
+        );
+        let ranges = curve.geohash_ranges("ez").unwrap();
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0].upper() - ranges[0].lower() + 1, 1 << 10);
+        let ranges = curve.geohash_ranges("e").unwrap();
+        assert_eq!(ranges.len(), 2);
+        assert!(ranges.iter().all(|r| r.upper() - r.lower() + 1 == 1 << 14));
+        for &(x, y) in &[(-44.9, 0.1), (-0.1, 44.9), (-22.5, 22.5)] {
+            let index = curve.index(x, y);
+            assert!(ranges
+                .iter()
+                .any(|r| r.lower() <= index && index <= r.upper()));
+        }
+        assert!(!ranges.iter().any(|r| {
+            let index = curve.index(-45.1, 22.5);
+            r.lower() <= index && index <= r.upper()
+        }));
+        assert_eq!(curve.geohash_ranges("ezs4a"), Err(Error::InvalidGeohash));
+        assert_eq!(curve.geohash_ranges("ezs42"), Err(Error::InvalidResolution));
+
+        let mut ranges = curve.geohash_ranges("e").unwrap();
+        ranges.extend(curve.geohash_ranges("s0").unwrap());
+        ranges.extend(curve.geohash_ranges("s1b").unwrap());
+        assert_eq!(
+            curve.geohash_covering(&ranges),
+            Ok(vec!["e".into(), "s0".into(), "s1b".into()])
+        );
+        assert_eq!(
+            curve.geohash_covering(&[KeyRange::covered(index, index + 1)]),
+            Ok(vec!["ezs4".into(), curve.geohash(index + 1, 4).unwrap()])
+        );
+        assert_eq!(curve.geohash_covering::<KeyRange>(&[]), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_tiles() {
+        let curve = ZCurve2D::web_mercator(10);
+        for &(zoom, x, y) in &[(0, 0, 0), (3, 3, 5), (10, 511, 700)] {
+            let tile = Tile::new(zoom, x, y);
+            let range = curve.tile_range(tile).unwrap();
+            assert_eq!(range.upper() - range.lower() + 1, 1 << (2 * (10 - zoom)));
+
+            let (x_min, y_min, x_max, y_max) = tile.bounds();
+            let center = curve.index((x_min + x_max) / 2.0, (y_min + y_max) / 2.0);
+            assert!(range.lower() <= center && center <= range.upper());
+            assert_eq!(curve.tile(center, zoom), Ok(tile));
+            assert_eq!(curve.tile(range.lower(), zoom), Ok(tile));
+            assert_eq!(curve.tile(range.upper(), zoom), Ok(tile));
+        }
+
+        let tile = Tile::new(3, 3, 5);
+        let range = curve.tile_range(tile).unwrap();
+        assert_eq!(curve.tile(range.upper() + 1, 3), Ok(Tile::new(3, 0, 6)));
+        assert_eq!(
+            curve.tile_range(Tile::new(11, 0, 0)),
+            Err(Error::InvalidResolution)
+        );
+        assert_eq!(
+            ZCurve2D::default().tile_range(tile),
+            Err(Error::InvalidBounds)
+        );
+    }
+
+    #[test]
+    fn test_fallible() {
+        assert_eq!(
+            ZCurve2D::try_new(0, -180.0, -90.0, 180.0, 90.0).err(),
+            Some(Error::InvalidResolution)
+        );
+        assert_eq!(
+            ZCurve2D::try_new(u32::MAX, -180.0, -90.0, 180.0, 90.0).err(),
+            Some(Error::InvalidResolution)
+        );
+        assert_eq!(
+            ZCurve2D::try_new(1024, 180.0, -90.0, -180.0, 90.0).err(),
+            Some(Error::InvalidBounds)
+        );
+
+        let curve = ZCurve2D::try_new(1024, -180.0, -90.0, 180.0, 90.0).unwrap();
+        assert_eq!(curve.try_index(-45.0, 45.0), Ok(curve.index(-45.0, 45.0)));
+        assert_eq!(curve.try_index(-181.0, 45.0), Err(Error::OutOfBounds));
+        assert_eq!(curve.try_index(0.0, f64::NAN), Err(Error::NotFinite));
+        assert_eq!(curve.try_index(f64::INFINITY, 0.0), Err(Error::NotFinite));
+
+        assert!(curve.try_ranges(-80.0, 35.0, -75.0, 40.0, &[]).is_ok());
+        assert_eq!(
+            curve.try_ranges(-80.0, 40.0, -75.0, 35.0, &[]).err(),
+            Some(Error::InvertedBox)
+        );
+        assert_eq!(
+            curve.try_ranges(-80.0, 35.0, -75.0, 95.0, &[]).err(),
+            Some(Error::OutOfBounds)
+        );
+    }
+
+    #[test]
+    fn test_bounds_policy() {
+        let clamp = ZCurve2D::default().with_bounds_policy(BoundsPolicy::Clamp);
+        assert_eq!(
+            clamp.try_index(180.0000001, 90.5),
+            Ok(clamp.index(180.0, 90.0))
+        );
+        assert_eq!(clamp.index(-200.0, -95.0), clamp.index(-180.0, -90.0));
+
+        let edge = clamp.index(180.0, 45.0);
+        let ranges = clamp.ranges(179.0, 44.0, 185.0, 46.0, &[]);
+        assert!(ranges
+            .iter()
+            .any(|r| r.lower() <= edge && edge <= r.upper()));
+
+        let wrap = ZCurve2D::default().with_bounds_policy(BoundsPolicy::WrapLongitude);
+        assert_eq!(wrap.index(181.0, 45.0), wrap.index(-179.0, 45.0));
+        assert_eq!(wrap.index(-190.0, 95.0), wrap.index(170.0, 90.0));
+        assert_eq!(wrap.index(180.0, 45.0), wrap.index(-180.0, 45.0));
+        assert_eq!(wrap.try_index(180.0, 45.0), Ok(wrap.index(-180.0, 45.0)));
+
+        let antimeridian = wrap.index(180.0, 45.0);
+        let ranges = wrap.ranges(170.0, 44.0, 180.0, 46.0, &[]);
+        assert!(ranges
+            .iter()
+            .any(|r| r.lower() <= antimeridian && antimeridian <= r.upper()));
+
+        let wrapped = wrap.index(-175.0, 45.0);
+        let ranges = wrap.try_ranges(170.0, 44.0, 190.0, 46.0, &[]).unwrap();
+        assert!(ranges
+            .iter()
+            .any(|r| r.lower() <= wrapped && wrapped <= r.upper()));
+
+        let reject = ZCurve2D::default();
+        assert_eq!(reject.try_index(180.0000001, 45.0), Err(Error::OutOfBounds));
+    }
+
+    #[test]
+    fn test_antimeridian_ranges() {
+        let curve = ZCurve2D::default();
+        let ranges = curve.ranges(170.0, -10.0, -170.0, 10.0, &[]);
+
+        assert!(ranges.windows(2).all(|w| w[0].upper() < w[1].lower()));
+        for &x in &[170.0, 175.0, 180.0, -180.0, -175.0, -170.0] {
+            let index = curve.index(x, 0.0);
+            assert!(ranges
+                .iter()
+                .any(|r| r.lower() <= index && index <= r.upper()));
+        }
+        let index = curve.index(0.0, 0.0);
+        assert!(!ranges
+            .iter()
+            .any(|r| r.lower() <= index && index <= r.upper()));
+
+        assert!(curve.try_ranges(170.0, -10.0, -170.0, 10.0, &[]).is_ok());
+    }
+
+    #[test]
+    fn test_ranges_for_boxes() {
+        let curve = ZCurve2D::default();
+        let boxes = [(-80.0, 35.0, -75.0, 40.0), (10.0, 45.0, 12.0, 47.0)];
+        let ranges = curve.ranges_for_boxes(&boxes, &[]);
+
+        assert!(ranges.windows(2).all(|w| w[0].upper() + 1 < w[1].lower()));
+        for &(x, y) in &[(-77.5, 37.5), (11.0, 46.0)] {
+            let index = curve.index(x, y);
+            assert!(ranges
+                .iter()
+                .any(|r| r.lower() <= index && index <= r.upper()));
+        }
+
+        let first = curve.ranges(-80.0, 35.0, -75.0, 40.0, &[]);
+        assert!(first.iter().all(|f| ranges
+            .iter()
+            .any(|r| r.lower() <= f.lower() && f.upper() <= r.upper())));
+
+        let same = curve.ranges_for_boxes(&[boxes[0], boxes[0]], &[]);
+        assert_eq!(
+            same.iter().map(|r| r.tuple()).collect::<Vec<_>>(),
+            first.iter().map(|r| r.tuple()).collect::<Vec<_>>()
+        );
+
+        assert!(curve.ranges_for_boxes(&[], &[]).is_empty());
+        assert_eq!(
+            curve
+                .try_ranges_for_boxes(&[boxes[0], (0.0, 0.0, 1.0, 91.0)], &[])
+                .err(),
+            Some(Error::OutOfBounds)
+        );
+    }
+
+    #[test]
+    fn test_ranges_within_distance() {
+        let curve = ZCurve2D::new(256, -180.0, -90.0, 180.0, 90.0);
+        for &(lon, lat) in &[(10.0, 20.0), (179.0, -30.0), (0.0, 88.0)] {
+            let circle = Circle::new(lon, lat, 500_000.0);
+            let ranges = curve.ranges_within_distance(lon, lat, 500_000.0, &[]);
+            assert!(ranges.iter().any(|r| r.contained()));
+
+            for col in 0..256 {
+                for row in 0..256 {
+                    let index = Z2::new(col, row).z();
+                    let (x, y) = curve.point(index);
+                    let range = ranges
+                        .iter()
+                        .find(|r| r.lower() <= index && index <= r.upper());
+                    if circle.contains(x, y) {
+                        assert!(range.is_some());
+                    }
+                    if range.is_some_and(|r| r.contained()) {
+                        let (x_min, y_min, x_max, y_max) = curve.cell_bounds(index);
+                        let corners = [
+                            (x_min, y_min),
+                            (x_min, y_max),
+                            (x_max, y_min),
+                            (x_max, y_max),
+                        ];
+                        assert!(corners.iter().all(|&(x, y)| circle.contains(x, y)));
+                    }
+                }
+            }
+        }
+
+        let local = ZCurve2D::new(64, 0.0, 0.0, 10.0, 10.0);
+        assert!(local
+            .ranges_within_distance(-100.0, 0.0, 1_000.0, &[])
+            .is_empty());
+
+        // A circle over the antimeridian reaches a curve east of it.
+        let east = ZCurve2D::new(64, -180.0, -10.0, -170.0, 10.0);
+        let ranges = east.ranges_within_distance(179.5, 0.0, 200_000.0, &[]);
+        let index = east.index(-179.5, 0.0);
+        assert!(ranges
+            .iter()
+            .any(|r| r.lower() <= index && index <= r.upper()));
+        assert_eq!(
+            east.try_ranges_within_distance(179.5, 0.0, 200_000.0, &[]),
+            Ok(ranges)
+        );
+
+        assert_eq!(
+            ZCurve2D::web_mercator(8).try_ranges_within_distance(0.0, 0.0, 1_000.0, &[]),
+            Err(Error::InvalidBounds)
+        );
+        assert_eq!(
+            local.try_ranges_within_distance(5.0, 5.0, -1.0, &[]),
+            Err(Error::OutOfBounds)
+        );
+    }
+
+    #[test]
+    fn test_ranges_for_polygon() {
+        let curve = ZCurve2D::new(64, 0.0, 0.0, 64.0, 64.0);
+        let polygon = Polygon::new(
+            vec![(0.0, 0.0), (4.0, 0.0), (44.0, 40.0), (40.0, 40.0)],
+            vec![vec![(20.0, 18.0), (22.0, 20.0), (21.0, 20.0)]],
+        );
+        let hints = [RangeComputeHints::MaxRecurse(32)];
+        let ranges = curve.ranges_for_polygon(&polygon, &hints);
+
+        let cells_in = |contained: bool| {
+            ranges
+                .iter()
+                .filter(|r| r.contained() == contained)
+                .map(|r| r.upper() - r.lower() + 1)
+                .sum::<u64>()
+        };
+        assert!(cells_in(true) > 0);
+        assert!(cells_in(true) + cells_in(false) < 64 * 64 / 2);
+
+        for col in 0..64 {
+            for row in 0..64 {
+                let (x, y) = (f64::from(col) + 0.5, f64::from(row) + 0.5);
+                let index = curve.index(x, y);
+                let range = ranges
+                    .iter()
+                    .find(|r| r.lower() <= index && index <= r.upper());
+                if polygon.contains(x, y) {
+                    assert!(range.is_some());
+                }
+                if range.is_some_and(|r| r.contained()) {
+                    let corners = [(x - 0.5, y - 0.5), (x + 0.5, y + 0.5)];
+                    assert!(corners.iter().all(|&(x, y)| polygon.contains(x, y)));
+                }
+            }
+        }
+
+        let elsewhere = Polygon::new(vec![(100.0, 100.0), (101.0, 100.0), (101.0, 101.0)], vec![]);
+        assert!(curve.ranges_for_polygon(&elsewhere, &hints).is_empty());
+    }
+
     #[test]
     fn test_sweep_through_map() {
         let curve = ZCurve2D::default();
@@ -212,4 +1167,134 @@ mod tests {
             lon += 1.0;
         }
     }
+
+    #[test]
+    fn test_range_cursor() {
+        let curve = ZCurve2D::default();
+        let boxes = [(-80.0, 35.0, -75.0, 40.0), (170.0, 10.0, -170.0, 20.0)];
+        let hints = [RangeComputeHints::MaxRecurse(16)];
+        let expected = curve.ranges_for_boxes(&boxes, &hints);
+
+        assert_eq!(
+            curve.range_cursor(&boxes, &hints).collect::<Vec<_>>(),
+            expected
+        );
+        assert!(curve.range_cursor(&[], &hints).next().is_none());
+
+        // Paging with a node budget splits ranges at most at batch ends.
+        let mut cursor = curve.range_cursor(&boxes, &hints);
+        let mut paged = cursor.next_batch(Budget::Nodes(50));
+        let mut saved = cursor.clone();
+        let next = cursor.next_batch(Budget::Ranges(3));
+        assert_eq!(next, saved.next_batch(Budget::Ranges(3)));
+        paged.extend(next);
+        while !cursor.is_finished() {
+            paged.extend(cursor.next_batch(Budget::Nodes(50)));
+        }
+
+        assert!(paged.windows(2).all(|w| w[0].upper() < w[1].lower()));
+        assert!(paged.iter().all(|p| expected
+            .iter()
+            .any(|r| r.lower() <= p.lower() && p.upper() <= r.upper())));
+        assert_eq!(
+            paged.iter().map(|r| r.upper() - r.lower()).sum::<u64>() + paged.len() as u64,
+            expected.iter().map(|r| r.upper() - r.lower()).sum::<u64>() + expected.len() as u64
+        );
+    }
+
+    #[test]
+    fn test_range_hints() {
+        let curve = ZCurve2D::default();
+        let covered_by = |ranges: &[KeyRange], wider: &[KeyRange]| {
+            ranges.iter().all(|r| {
+                wider
+                    .iter()
+                    .any(|w| w.lower() <= r.lower() && r.upper() <= w.upper())
+            })
+        };
+        let recurse = RangeComputeHints::MaxRecurse(16);
+        let full = curve.ranges(-80.0, 35.0, -75.0, 40.0, &[recurse]);
+        assert!(full.len() > 20);
+
+        let capped = curve.ranges(
+            -80.0,
+            35.0,
+            -75.0,
+            40.0,
+            &[recurse, RangeComputeHints::MaxRanges(20)],
+        );
+        assert!(capped.len() <= 20);
+        assert!(covered_by(&full, &capped));
+
+        let coarse = curve.ranges(
+            -80.0,
+            35.0,
+            -75.0,
+            40.0,
+            &[recurse, RangeComputeHints::Precision(24)],
+        );
+        assert!(coarse.len() < full.len());
+        assert!(covered_by(&full, &coarse));
+
+        let tolerant = curve.ranges(
+            -80.0,
+            35.0,
+            -75.0,
+            40.0,
+            &[recurse, RangeComputeHints::GapTolerance(1 << 20)],
+        );
+        assert!(tolerant.len() < full.len());
+        assert!(covered_by(&full, &tolerant));
+        assert!(tolerant
+            .windows(2)
+            .all(|w| w[1].lower() - w[0].upper() > 1 << 20));
+    }
+
+    #[test]
+    fn test_projection() {
+        use crate::projection::{EqualArea, WebMercator, MAX_MERCATOR_LATITUDE};
+
+        // A Web Mercator curve over longitude and latitude has the cells of web
+        // mercator tiles.
+        let curve = ZCurve2D::new(1 << 10, -180.0, -90.0, 180.0, 90.0).with_projection(WebMercator);
+        let tiles = ZCurve2D::web_mercator(10);
+        for &(x, y) in &[(2.3522, 48.8566), (-75.1234, 38.9876), (151.2093, -33.8688)] {
+            let (px, py) = (WebMercator.project_x(x), WebMercator.project_y(y));
+            assert_eq!(curve.index(x, y), tiles.index(px, py));
+
+            let (x_min, y_min, x_max, y_max) = curve.cell_bounds(curve.index(x, y));
+            assert!(x_min <= x && x < x_max && y_min <= y && y < y_max);
+        }
+
+        // Latitudes past the projection are clamped into the polar rows, and query
+        // boxes there still find them.
+        let polar = curve.index(10.0, 89.0);
+        assert_eq!(polar, curve.index(10.0, MAX_MERCATOR_LATITUDE));
+        assert_eq!(curve.cell_bounds(polar).3, 90.0);
+        let ranges = curve.ranges(9.0, 86.0, 11.0, 90.0, &[]);
+        assert!(ranges
+            .iter()
+            .any(|r| r.lower() <= polar && polar <= r.upper()));
+        let polygon = Polygon::new(
+            vec![(9.0, 86.0), (11.0, 86.0), (11.0, 89.5), (9.0, 89.5)],
+            vec![],
+        );
+        let ranges = curve.ranges_for_polygon(&polygon, &[]);
+        assert!(ranges
+            .iter()
+            .any(|r| r.lower() <= polar && polar <= r.upper()));
+
+        // Equal area cells are taller in latitude towards the poles.
+        let curve = curve.with_projection(EqualArea);
+        let (_, y_min, _, y_max) = curve.cell_bounds(curve.index(0.0, 1.0));
+        let (_, polar_min, _, polar_max) = curve.cell_bounds(curve.index(0.0, 89.0));
+        assert!(polar_max - polar_min > 10.0 * (y_max - y_min));
+        for &(x, y) in &[(0.0, 1.0), (0.0, 89.0), (-120.0, -60.0)] {
+            let index = curve.index(x, y);
+            let ranges = curve.ranges(x - 0.5, y - 0.5, x + 0.5, y + 0.5, &[]);
+            assert!(ranges
+                .iter()
+                .any(|r| r.lower() <= index && index <= r.upper()));
+        }
+    }
 }
@@ -16,12 +16,15 @@
 //! An N-Dimensional Z-Order Curve base class.
 
 use crate::{
-    index_range::{CoveredRange, IndexRange, OverlappingRange},
+    geometry::Relation,
+    index_range::{merge_ranges, next_batch, next_range, Budget, KeyRange, RangeMerger, RangeWalk},
     zorder::z_range::ZRange,
 };
-use alloc::{boxed::Box, collections::VecDeque, vec, vec::Vec};
+use alloc::{collections::VecDeque, vec, vec::Vec};
+use core::marker::PhantomData;
 
-const DEFAULT_RECURSE: usize = 7;
+/// Levels of recursion when no limit is given, geomesa's `ZN.DefaultRecurse`.
+pub(crate) const DEFAULT_RECURSE: usize = 7;
 
 const LEVEL_TERMINATOR: (Option<u64>, Option<u64>) = (None, None);
 
@@ -69,120 +72,52 @@ pub trait ZN {
     /// Compute the Z-index ranges that cover zbounds (Default values: precision = 64,
     /// `max_recurse` = 7, `max_ranges` = `usize::MAX`).
     #[must_use]
-    fn zranges_default<Z: ZN>(zbounds: &[ZRange]) -> Vec<Box<dyn IndexRange>> {
+    fn zranges_default<Z: ZN>(zbounds: &[ZRange]) -> Vec<KeyRange> {
         Self::zranges::<Z>(zbounds, 64, Some(usize::MAX), Some(DEFAULT_RECURSE))
     }
 
-    /// Compute the Z-index ranges that cover zbounds.
+    /// Compute the Z-index ranges that cover zbounds. Like geomesa's `ZN.zranges`,
+    /// every cell past `precision` is returned as a covered range, even one outside
+    /// of zbounds.
     #[must_use]
     fn zranges<Z: ZN>(
         zbounds: &[ZRange],
         precision: u64,
         max_ranges: Option<usize>,
         max_recurse: Option<usize>,
-    ) -> Vec<Box<dyn IndexRange>> {
-        let mut ranges: Vec<Box<dyn IndexRange>> = Vec::with_capacity(100);
-
-        let mut remaining: VecDeque<(Option<u64>, Option<u64>)> = VecDeque::with_capacity(100);
-
-        let lcp = Self::longest_common_prefix(
-            zbounds
-                .iter()
-                .flat_map(|b| vec![b.min, b.max])
-                .collect::<Vec<u64>>()
-                .as_slice(),
-        );
-
-        let mut offset = 64 - lcp.precision;
-
-        check_value::<Z>(
-            lcp.prefix,
-            0,
-            offset,
+    ) -> Vec<KeyRange> {
+        decompose::<Z>(
             zbounds,
             precision,
-            &mut ranges,
-            &mut remaining,
-        );
-        remaining.push_back(LEVEL_TERMINATOR);
-        offset -= Self::DIMENSIONS;
-
-        let mut level = 0;
-
-        let max_recurse = max_recurse.unwrap_or(DEFAULT_RECURSE);
-        let max_ranges = max_ranges.unwrap_or(usize::MAX);
-
-        loop {
-            let next = remaining.pop_front();
-
-            match next {
-                Some(LEVEL_TERMINATOR) => {
-                    if !remaining.is_empty() {
-                        level += 1;
-
-                        if offset == 0 || level >= max_recurse {
-                            bottom_out(&mut ranges, &mut remaining);
-                        } else {
-                            remaining.push_back(LEVEL_TERMINATOR);
-                        }
-                        offset -= Self::DIMENSIONS;
-                    }
-                }
-                Some((Some(min), _)) => {
-                    let prefix = min;
-                    let mut quadrant = 0_u64;
-                    while quadrant < Self::QUADRANTS.into() {
-                        check_value::<Z>(
-                            prefix,
-                            quadrant,
-                            offset,
-                            zbounds,
-                            precision,
-                            &mut ranges,
-                            &mut remaining,
-                        );
-                        quadrant += 1;
-                    }
-                    if ranges.len() + remaining.len() > max_ranges {
-                        bottom_out(&mut ranges, &mut remaining);
-                    }
-                }
-                _ => (),
-            }
-
-            if remaining.is_empty() {
-                break;
-            }
-        }
-
-        // All ranges found. Now reduce them by merging overlapping values.
-        ranges.sort();
-
-        let mut current: Option<Box<dyn IndexRange>> = None;
-        let mut results = Vec::new();
+            max_ranges,
+            max_recurse,
+            &|range| classify_zbounds::<Z>(range, zbounds),
+            true,
+            PastPrecision::Covered,
+        )
+    }
 
-        for range in ranges {
-            if let Some(cur) = current.take() {
-                if range.lower() <= cur.upper() + 1 {
-                    let max = cur.upper().max(range.upper());
-                    let min = cur.lower();
-                    if cur.contained() && range.contained() {
-                        current = Some(Box::new(CoveredRange::new(min, max)));
-                    } else {
-                        current = Some(Box::new(OverlappingRange::new(min, max)));
-                    }
-                } else {
-                    results.push(cur);
-                    current = Some(range);
-                }
-            } else {
-                current = Some(range);
-            }
-        }
-        if let Some(cur) = current {
-            results.push(cur);
-        }
-        results
+    /// Compute the Z-index ranges of the cells that `classify` finds inside or
+    /// crossing a query region. Recursion starts from the longest common prefix of
+    /// zbounds, which must cover the region. Ranges are only merged with neighbours
+    /// of the same containment, so `contained` ranges need no further refinement.
+    #[must_use]
+    fn zranges_with<Z: ZN>(
+        zbounds: &[ZRange],
+        precision: u64,
+        max_ranges: Option<usize>,
+        max_recurse: Option<usize>,
+        classify: &dyn Fn(ZRange) -> Relation,
+    ) -> Vec<KeyRange> {
+        decompose::<Z>(
+            zbounds,
+            precision,
+            max_ranges,
+            max_recurse,
+            classify,
+            false,
+            PastPrecision::Overlapping,
+        )
     }
 
     /// Compute the longest common binary prefix for a slice of i64s.
@@ -224,38 +159,288 @@ pub struct ZPrefix {
     pub precision: u64,
 }
 
-fn check_value<Z: ZN>(
+/// A resumable cursor over the Z-index ranges that cover zbounds, yielding ranges in
+/// ascending order as the tree is refined. Collecting every range gives the same
+/// result as the `ranges` of a curve with no `MaxRanges`, so a cell past `precision`
+/// is an overlapping range if it crosses zbounds.
+pub struct ZRangeCursor<Z> {
+    walk: ZWalk<Z>,
+    merger: RangeMerger,
+}
+
+impl<Z: ZN> ZRangeCursor<Z> {
+    /// Start a cursor over the ranges that cover zbounds. There are no ranges if
+    /// zbounds is empty.
+    #[must_use]
+    pub fn new(zbounds: &[ZRange], precision: u64, max_recurse: Option<usize>) -> Self {
+        let stack = if zbounds.is_empty() {
+            Vec::new()
+        } else {
+            let lcp = Z::longest_common_prefix(
+                zbounds
+                    .iter()
+                    .flat_map(|b| vec![b.min, b.max])
+                    .collect::<Vec<u64>>()
+                    .as_slice(),
+            );
+            vec![(lcp.prefix, 64 - lcp.precision, 0)]
+        };
+
+        ZRangeCursor {
+            walk: ZWalk {
+                zbounds: zbounds.to_vec(),
+                precision,
+                max_recurse: max_recurse.unwrap_or(DEFAULT_RECURSE).max(1),
+                stack,
+                curve: PhantomData,
+            },
+            merger: RangeMerger::new(true),
+        }
+    }
+
+    /// The ranges found within `budget`. Ranges in later batches follow the ranges in
+    /// earlier batches.
+    pub fn next_batch(&mut self, budget: Budget) -> Vec<KeyRange> {
+        next_batch(&mut self.walk, &mut self.merger, budget)
+    }
+
+    /// Whether every range has been returned.
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.walk.stack.is_empty() && self.merger.is_empty()
+    }
+}
+
+impl<Z: ZN> Iterator for ZRangeCursor<Z> {
+    type Item = KeyRange;
+
+    fn next(&mut self) -> Option<KeyRange> {
+        next_range(&mut self.walk, &mut self.merger)
+    }
+}
+
+impl<Z> Clone for ZRangeCursor<Z> {
+    fn clone(&self) -> Self {
+        ZRangeCursor {
+            walk: ZWalk {
+                zbounds: self.walk.zbounds.clone(),
+                precision: self.walk.precision,
+                max_recurse: self.walk.max_recurse,
+                stack: self.walk.stack.clone(),
+                curve: PhantomData,
+            },
+            merger: self.merger.clone(),
+        }
+    }
+}
+
+/// Depth first walk of the quadrants in ascending Z-index order.
+struct ZWalk<Z> {
+    zbounds: Vec<ZRange>,
+    precision: u64,
+    max_recurse: usize,
+    // (min, offset, depth) of the nodes still to visit, the next on top.
+    stack: Vec<(u64, u64, usize)>,
+    curve: PhantomData<fn() -> Z>,
+}
+
+impl<Z: ZN> RangeWalk for ZWalk<Z> {
+    fn step(&mut self, emit: &mut dyn FnMut(KeyRange)) -> bool {
+        let (min, offset, depth) = match self.stack.pop() {
+            Some(node) => node,
+            None => return false,
+        };
+        let max = min | (1_u64.wrapping_shl(offset as u32) - 1);
+        let range = ZRange { min, max };
+
+        if is_contained::<Z>(range, &self.zbounds) {
+            emit(KeyRange::covered(min, max));
+        } else if is_overlapped::<Z>(range, &self.zbounds) {
+            if offset == 0 || offset < 64 - self.precision || depth >= self.max_recurse {
+                emit(KeyRange::overlapping(min, max));
+            } else {
+                let offset = offset - Z::DIMENSIONS;
+                for quadrant in (0..u64::from(Z::QUADRANTS)).rev() {
+                    self.stack.push((
+                        min | quadrant.wrapping_shl(offset as u32),
+                        offset,
+                        depth + 1,
+                    ));
+                }
+            }
+        }
+        true
+    }
+}
+
+/// Compute the Z-index ranges that cover zbounds for the `RangeComputeHints` of a
+/// curve. The same as `ZN::zranges`, except that a cell past `precision` is an
+/// overlapping range if it crosses zbounds and is dropped if it is outside.
+pub(crate) fn hinted_zranges<Z: ZN>(
+    zbounds: &[ZRange],
+    precision: u64,
+    max_ranges: Option<usize>,
+    max_recurse: Option<usize>,
+) -> Vec<KeyRange> {
+    decompose::<Z>(
+        zbounds,
+        precision,
+        max_ranges,
+        max_recurse,
+        &|range| classify_zbounds::<Z>(range, zbounds),
+        true,
+        PastPrecision::Overlapping,
+    )
+}
+
+/// How a cell past the requested precision is returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PastPrecision {
+    /// As a covered range, whatever its relation to the query.
+    Covered,
+    /// As an overlapping range if it crosses the query, not at all if outside.
+    Overlapping,
+}
+
+fn classify_zbounds<Z: ZN>(range: ZRange, zbounds: &[ZRange]) -> Relation {
+    if is_contained::<Z>(range, zbounds) {
+        Relation::Inside
+    } else if is_overlapped::<Z>(range, zbounds) {
+        Relation::Crossing
+    } else {
+        Relation::Outside
+    }
+}
+
+fn decompose<Z: ZN>(
+    zbounds: &[ZRange],
+    precision: u64,
+    max_ranges: Option<usize>,
+    max_recurse: Option<usize>,
+    classify: &dyn Fn(ZRange) -> Relation,
+    merge_mixed: bool,
+    past_precision: PastPrecision,
+) -> Vec<KeyRange> {
+    let mut ranges: Vec<KeyRange> = Vec::with_capacity(100);
+
+    let mut remaining: VecDeque<(Option<u64>, Option<u64>)> = VecDeque::with_capacity(100);
+
+    let lcp = Z::longest_common_prefix(
+        zbounds
+            .iter()
+            .flat_map(|b| vec![b.min, b.max])
+            .collect::<Vec<u64>>()
+            .as_slice(),
+    );
+
+    let mut offset = 64 - lcp.precision;
+
+    check_value(
+        lcp.prefix,
+        0,
+        offset,
+        classify,
+        precision,
+        past_precision,
+        &mut ranges,
+        &mut remaining,
+    );
+    remaining.push_back(LEVEL_TERMINATOR);
+    offset -= Z::DIMENSIONS;
+
+    let mut level = 0;
+
+    let max_recurse = max_recurse.unwrap_or(DEFAULT_RECURSE);
+    let max_ranges = max_ranges.unwrap_or(usize::MAX);
+
+    loop {
+        let next = remaining.pop_front();
+
+        #[allow(clippy::collapsible_match)]
+        match next {
+            Some(LEVEL_TERMINATOR) => {
+                if !remaining.is_empty() {
+                    level += 1;
+
+                    if offset == 0 || level >= max_recurse {
+                        bottom_out(&mut ranges, &mut remaining);
+                    } else {
+                        remaining.push_back(LEVEL_TERMINATOR);
+                        offset -= Z::DIMENSIONS;
+                    }
+                }
+            }
+            Some((Some(min), _)) => {
+                let prefix = min;
+                let mut quadrant = 0_u64;
+                while quadrant < Z::QUADRANTS.into() {
+                    check_value(
+                        prefix,
+                        quadrant,
+                        offset,
+                        classify,
+                        precision,
+                        past_precision,
+                        &mut ranges,
+                        &mut remaining,
+                    );
+                    quadrant += 1;
+                }
+                if ranges.len() + remaining.len() > max_ranges {
+                    bottom_out(&mut ranges, &mut remaining);
+                }
+            }
+            _ => (),
+        }
+
+        if remaining.is_empty() {
+            break;
+        }
+    }
+
+    // All ranges found. Now reduce them by merging overlapping and adjacent values.
+    merge_ranges(ranges, merge_mixed)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn check_value(
     prefix: u64,
     quadrant: u64,
     offset: u64,
-    zbounds: &[ZRange],
+    classify: &dyn Fn(ZRange) -> Relation,
     precision: u64,
-    ranges: &mut Vec<Box<dyn IndexRange>>,
+    past_precision: PastPrecision,
+    ranges: &mut Vec<KeyRange>,
     remaining: &mut VecDeque<(Option<u64>, Option<u64>)>,
 ) {
     let min = prefix | quadrant.wrapping_shl(offset as u32);
     let max = min | (1_u64.wrapping_shl(offset as u32) - 1);
     let quadrant_range = ZRange { min, max };
 
-    if is_contained::<Z>(quadrant_range, zbounds) || offset < 64 - precision {
-        ranges.push(Box::new(CoveredRange::new(min, max)));
-    } else if is_overlapped::<Z>(quadrant_range, zbounds) {
-        remaining.push_back((Some(min), Some(max)));
+    let past = offset < 64 - precision;
+    if past && past_precision == PastPrecision::Covered {
+        ranges.push(KeyRange::covered(min, max));
+        return;
+    }
+
+    match classify(quadrant_range) {
+        Relation::Inside => ranges.push(KeyRange::covered(min, max)),
+        // Past the requested precision the cell is returned whole.
+        Relation::Crossing if past => ranges.push(KeyRange::overlapping(min, max)),
+        Relation::Crossing => remaining.push_back((Some(min), Some(max))),
+        Relation::Outside => (),
     }
 }
 
-fn bottom_out(
-    ranges: &mut Vec<Box<dyn IndexRange>>,
-    remaining: &mut VecDeque<(Option<u64>, Option<u64>)>,
-) {
+fn bottom_out(ranges: &mut Vec<KeyRange>, remaining: &mut VecDeque<(Option<u64>, Option<u64>)>) {
     while let Some((min, max)) = remaining.pop_front() {
         if let (Some(min), Some(max)) = (min, max) {
-            ranges.push(Box::new(OverlappingRange::new(min, max)));
+            ranges.push(KeyRange::overlapping(min, max));
         }
     }
 }
 
-fn is_contained<Z: ZN>(range: ZRange, zbounds: &[ZRange]) -> bool {
+pub(crate) fn is_contained<Z: ZN>(range: ZRange, zbounds: &[ZRange]) -> bool {
     for bound in zbounds {
         if Z::contains_value(*bound, range) {
             return true;
@@ -264,7 +449,7 @@ fn is_contained<Z: ZN>(range: ZRange, zbounds: &[ZRange]) -> bool {
     false
 }
 
-fn is_overlapped<Z: ZN>(range: ZRange, zbounds: &[ZRange]) -> bool {
+pub(crate) fn is_overlapped<Z: ZN>(range: ZRange, zbounds: &[ZRange]) -> bool {
     for bound in zbounds {
         if Z::overlaps(*bound, range) {
             return true;